@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
-use clap::{command, Subcommand};
+use clap::{command, Subcommand, ValueEnum};
+use leaky_common::prelude::Cid;
 use url::Url;
 
 pub use clap::Parser;
@@ -8,10 +9,25 @@ pub use clap::Parser;
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Checkout to operate on, instead of the nearest one above the current directory
+    #[clap(long, global = true, env = "LEAKY_DIR")]
+    pub repo: Option<PathBuf>,
+    /// Print results as text, or as JSON for scripts. Errors follow the same format.
+    #[clap(long, global = true, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    /// Don't show progress while hashing, uploading and downloading
+    #[clap(long, short, global = true)]
+    pub quiet: bool,
     #[clap(subcommand)]
     pub command: Command,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     Init {
@@ -20,18 +36,157 @@ pub enum Command {
         #[clap(long = "leaky-api", short = 'l')]
         maybe_leaky_api_url: Option<Url>,
     },
+    /// Check out an existing bucket
+    Clone {
+        leaky_api_url: Url,
+        /// Directory to check the bucket out into, defaulting to the current one
+        dir: Option<PathBuf>,
+        #[clap(long = "ipfs-rpc", short = 'i')]
+        maybe_ipfs_rpc_url: Option<Url>,
+        /// Branch to check out and follow. With --cid or --ref, the new branch to start at that
+        /// root, since pushes to an existing branch have to build on its head.
+        #[clap(long, short)]
+        branch: Option<String>,
+        /// Check out a specific root instead of the head of a branch, onto a new branch named
+        /// with --branch
+        #[clap(long, conflicts_with = "ref_name", requires = "branch")]
+        cid: Option<Cid>,
+        /// Check out the root a ref points at instead of the head of a branch, onto a new branch
+        /// named with --branch
+        #[clap(long = "ref", requires = "branch")]
+        ref_name: Option<String>,
+        /// Only check out the parts of the bucket matching these sparse patterns
+        #[clap(long)]
+        include: Vec<String>,
+    },
     Add,
+    /// Set a file or directory's metadata. Values map from JSON as in DAG-JSON, so
+    /// `{"/": "<cid>"}` is a link and `{"/": {"bytes": "<base64>"}}` is bytes.
     Tag {
         #[clap(long, short)]
         path: PathBuf,
-        #[clap(long, short)]
-        metadata: String,
+        /// Metadata as a JSON object
+        #[clap(long, short, required_unless_present_any = ["file", "unset"])]
+        metadata: Option<String>,
+        /// Read the metadata from a file instead, or from stdin if it's -
+        #[clap(long, short, conflicts_with = "metadata")]
+        file: Option<PathBuf>,
+        /// Keep the keys the path already has that aren't being set, instead of replacing them
+        #[clap(long)]
+        merge: bool,
+        /// Remove a key from the path's metadata, keeping the rest
+        #[clap(long, value_name = "KEY")]
+        unset: Vec<String>,
     },
     Stat,
-    Push,
-    Pull,
+    /// Show what changed between two roots
+    Diff {
+        /// CID or ref name of the older root
+        from: String,
+        /// CID or ref name of the newer root, instead of the one checked out
+        to: Option<String>,
+    },
+    /// List the roots the bucket went through to get to a root, newest first
+    Log {
+        /// CID or ref name of the root to start from, instead of the one checked out
+        revision: Option<String>,
+        /// Stop after this many roots
+        #[clap(long, short = 'n')]
+        limit: Option<usize>,
+    },
+    Push {
+        /// Message passed along to anyone following the bucket's root events
+        #[clap(long, short)]
+        message: Option<String>,
+    },
+    Pull {
+        /// CID or ref name to pull, instead of the latest root
+        revision: Option<String>,
+    },
+    Ref {
+        #[clap(subcommand)]
+        command: RefCommand,
+    },
+    /// List branches, or start a new one at the current root
+    Branch {
+        name: Option<String>,
+    },
+    /// Switch to another branch and check out its head
+    Switch {
+        name: String,
+        /// Start the branch at the current root rather than checking out an existing one
+        #[clap(long, short)]
+        create: bool,
+    },
+    /// Show the sparse patterns, or check out only the parts of the bucket they select
+    Sparse {
+        /// Glob patterns like `images/train/**`, relative to the root of the bucket
+        patterns: Vec<String>,
+        /// Go back to checking out the whole bucket
+        #[clap(long, conflicts_with = "patterns")]
+        clear: bool,
+    },
+    /// Stage changes as they happen, pulling new heads of the branch as they're pushed
+    Watch {
+        /// Push staged changes every this many seconds
+        #[clap(long)]
+        push_interval: Option<u64>,
+        /// Milliseconds the filesystem has to settle before changes are staged
+        #[clap(long, default_value_t = 500)]
+        debounce: u64,
+        /// Don't follow the remote branch
+        #[clap(long)]
+        no_pull: bool,
+    },
+    /// List a directory in the bucket
     Ls {
+        /// Directory to list, defaulting to the current one
+        path: Option<PathBuf>,
+        /// Show CIDs, sizes and metadata
         #[clap(long, short)]
+        long: bool,
+        /// List everything under the directory
+        #[clap(long, short)]
+        recursive: bool,
+        /// CID or ref name of the root to list, instead of the one checked out
+        #[clap(long)]
+        cid: Option<String>,
+    },
+    /// Print a file in the bucket, without checking it out
+    Cat {
         path: PathBuf,
+        /// CID or ref name of the root to read from, instead of the one checked out
+        #[clap(long)]
+        cid: Option<String>,
+    },
+    /// Show the schema metadata in the bucket has to follow, or set it from a JSON file like
+    /// `{"fields": {"author": "string", "score": "float"}, "open": false}`
+    Schema {
+        /// File to read the schema from, or - for stdin
+        file: Option<PathBuf>,
+        /// Stop checking metadata against a schema
+        #[clap(long, conflicts_with = "file")]
+        clear: bool,
+    },
+    /// Check the local state for damage left by interrupted writes, and repair it
+    Fsck {
+        /// Only report problems, without repairing them
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RefCommand {
+    /// Name a pushed root, defaulting to the one currently checked out
+    Create {
+        name: String,
+        /// CID or existing ref of the root to name
+        #[clap(long, short)]
+        cid: Option<String>,
+    },
+    List,
+    Delete {
+        name: String,
     },
 }
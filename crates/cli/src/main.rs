@@ -1,24 +1,62 @@
 use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
+use serde_json::{json, Value};
 use url::Url;
 
 mod cli;
 mod ops;
+mod progress;
 
-use cli::{Cli, Command, Parser};
+use cli::{Cli, Command, Format, Parser, RefCommand};
+use leaky_common::prelude::{LeakyApiError, LeakyError, ManifestChange, DEFAULT_BRANCH};
 use ops::{
-    add, init, pull, push, stat, tag, AddError, InitError, PullError, PushError, StatError,
-    TagError,
+    add, cat, clone_bucket, create_branch, create_ref, delete_ref, diff_roots, fsck, init,
+    list_branches, list_refs, list_sparse, log, ls, pull, push, set_schema, set_sparse,
+    show_schema, stat, switch, tag, utils, watch, AddError, BranchError, BucketSchemaError,
+    CatError, CloneError, CloneRevision, DiffRootsError, FsckError, InitError, LogError, LsError,
+    PullError, PushError, RefError, SparseCheckoutError, StatError, SwitchError, TagError,
+    TagOptions, WatchError, WatchOptions,
 };
+use progress::ProgressRenderer;
 
 #[tokio::main]
 async fn main() {
-    // Run the app and capture any errors
-    capture_error(run().await);
+    let args = Cli::parse();
+    let format = args.format;
+    if let Err(err) = run(args).await {
+        report_error(format, &err);
+        std::process::exit(err.exit_code());
+    }
 }
 
-pub async fn run() -> Result<(), AppError> {
-    let args = Cli::parse();
+pub async fn run(args: Cli) -> Result<(), AppError> {
+    let format = args.format;
+
+    // Everything on disk is relative to the root of the checkout, so run from there and resolve
+    // paths given on the command line against where we were invoked
+    let cwd = std::env::current_dir()?;
+    let root = match &args.command {
+        Command::Init { .. } | Command::Clone { .. } => {
+            let dir = match &args.command {
+                Command::Clone { dir: Some(dir), .. } => dir.as_path(),
+                _ => args.repo.as_deref().unwrap_or(Path::new(".")),
+            };
+            let root = cwd.join(dir);
+            std::fs::create_dir_all(&root)?;
+            root
+        }
+        _ => utils::find_root(args.repo.as_deref(), &cwd)?,
+    };
+    std::env::set_current_dir(&root)?;
+
+    // Watch runs unattended, so it only reports what it did
+    if !args.quiet && !matches!(args.command, Command::Watch { .. }) {
+        utils::set_progress(Arc::new(ProgressRenderer::default()));
+    }
+
     match args.command {
         Command::Init {
             maybe_ipfs_rpc_url,
@@ -33,49 +71,376 @@ pub async fn run() -> Result<(), AppError> {
                 None => Url::parse("http://localhost:3000").unwrap(),
             };
             let cid = init(ipfs_rpc, leaky_api).await?;
-            pretty_print(format!("LeakyBucket @ {}", cid));
+            output(
+                format,
+                || json!({ "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {}", cid)),
+            );
+        }
+        Command::Clone {
+            leaky_api_url,
+            dir: _,
+            maybe_ipfs_rpc_url,
+            branch,
+            cid,
+            ref_name,
+            include,
+        } => {
+            let ipfs_rpc = match maybe_ipfs_rpc_url {
+                Some(url) => url,
+                None => Url::parse("http://localhost:5001").unwrap(),
+            };
+            let branch = branch.unwrap_or_else(|| DEFAULT_BRANCH.to_string());
+            let revision = match (cid, ref_name) {
+                (Some(cid), _) => CloneRevision::Cid { cid, branch },
+                (None, Some(name)) => CloneRevision::Ref { name, branch },
+                (None, None) => CloneRevision::Branch(branch),
+            };
+            let cid = clone_bucket(ipfs_rpc, leaky_api_url, revision, include).await?;
+            output(
+                format,
+                || json!({ "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {}", cid)),
+            );
         }
         Command::Add => {
             let cid = add().await?;
-            pretty_print(format!("LeakyBucket @ {}", cid));
+            output(
+                format,
+                || json!({ "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {}", cid)),
+            );
         }
-        Command::Tag { path, metadata } => {
-            let cid = tag(path, metadata).await?;
-            pretty_print(format!("LeakyBucket @ {}", cid));
+        Command::Tag {
+            path,
+            metadata,
+            file,
+            merge,
+            unset,
+        } => {
+            let path = utils::bucket_path(&root, &cwd, &path)?;
+            // A metadata file is given relative to where we were invoked; - stands for stdin
+            let file = file.map(|file| match file.to_str() {
+                Some("-") => file,
+                _ => cwd.join(file),
+            });
+            let options = TagOptions {
+                metadata,
+                file,
+                merge,
+                unset,
+            };
+            let cid = tag(path, options).await?;
+            output(
+                format,
+                || json!({ "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {}", cid)),
+            );
         }
         Command::Stat => {
             let stats = stat().await?;
-            println!("{}", stats);
-        }
-        Command::Push => {
-            let cid = push().await?;
-            pretty_print(format!("LeakyBucket @ {}", cid));
-        }
-        Command::Pull => {
-            let cid = pull().await?;
-            pretty_print(format!("LeakyBucket @ {}", cid));
-        }
-
-        /*
-                Command::Add { root, path } => {
-                    leaky.pull(&root).await?;
-                    // Read the data as a stream
-                    let data = std::fs::read(&path)?;
-                    let data = std::io::Cursor::new(data);
-
-                    let cid = leaky.add(&path, data, None).await?;
-                    pretty_print(&format!("{} -> {}", &path.to_string_lossy(), &cid));
-                    changed = true;
-                }
-                Command::Ls { root, path } => {
-                    leaky.pull(&root).await?;
-                    let entries = leaky.ls(path).await?;
-                    for entry in entries {
-                        pretty_print(&format!("{} -> {}", entry.0, entry.0));
+            output(format, || stats.to_json(), || println!("{}", stats));
+        }
+        Command::Diff { from, to } => {
+            let changes = diff_roots(&from, to).await?;
+            output(
+                format,
+                || json!({ "changes": changes.iter().map(change_to_json).collect::<Vec<_>>() }),
+                || {
+                    for change in &changes {
+                        let line = match change {
+                            ManifestChange::Added { path, .. } => format!("A {}", path.display()),
+                            ManifestChange::Removed { path, .. } => {
+                                format!("D {}", path.display())
+                            }
+                            ManifestChange::Modified { path, .. } => {
+                                format!("M {}", path.display())
+                            }
+                            ManifestChange::MetadataChanged { path, .. } => {
+                                format!("T {}", path.display())
+                            }
+                            ManifestChange::Moved { from, to, .. } => {
+                                format!("R {} -> {}", from.display(), to.display())
+                            }
+                        };
+                        pretty_print(line);
+                    }
+                },
+            );
+        }
+        Command::Log { revision, limit } => {
+            let entries = log(revision, limit).await?;
+            output(
+                format,
+                || {
+                    let entries = entries
+                        .iter()
+                        .map(|entry| {
+                            json!({
+                                "cid": entry.cid.to_string(),
+                                "previous": entry.previous.map(|cid| cid.to_string()),
+                                "version": entry.version,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({ "roots": entries })
+                },
+                || {
+                    for entry in &entries {
+                        pretty_print(format!("{} (leaky {})", entry.cid, entry.version));
+                    }
+                },
+            );
+        }
+        Command::Push { message } => {
+            let cid = push(message).await?;
+            output(
+                format,
+                || json!({ "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {}", cid)),
+            );
+        }
+        Command::Pull { revision } => {
+            let cid = pull(revision).await?;
+            output(
+                format,
+                || json!({ "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {}", cid)),
+            );
+        }
+        Command::Branch { name: Some(name) } => {
+            let cid = create_branch(&name).await?;
+            output(
+                format,
+                || json!({ "name": name, "cid": cid.to_string() }),
+                || pretty_print(format!("{} -> {}", name, cid)),
+            );
+        }
+        Command::Branch { name: None } => {
+            let (current, branches) = list_branches().await?;
+            output(
+                format,
+                || {
+                    let branches = branches
+                        .iter()
+                        .map(|(name, cid)| json!({ "name": name, "cid": cid.to_string() }))
+                        .collect::<Vec<_>>();
+                    json!({ "current": current, "branches": branches })
+                },
+                || {
+                    for (name, cid) in &branches {
+                        let marker = if *name == current { "*" } else { " " };
+                        pretty_print(format!("{} {} -> {}", marker, name, cid));
+                    }
+                },
+            );
+        }
+        Command::Switch { name, create } => {
+            let cid = switch(&name, create).await?;
+            output(
+                format,
+                || json!({ "branch": name, "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {} on {}", cid, name)),
+            );
+        }
+        Command::Sparse { patterns, clear } if clear || !patterns.is_empty() => {
+            let cid = set_sparse(patterns.clone()).await?;
+            output(
+                format,
+                || json!({ "include": patterns, "cid": cid.to_string() }),
+                || {
+                    if patterns.is_empty() {
+                        pretty_print(format!("LeakyBucket @ {} (everything)", cid));
+                    } else {
+                        pretty_print(format!("LeakyBucket @ {} ({})", cid, patterns.join(", ")));
+                    }
+                },
+            );
+        }
+        Command::Sparse { .. } => {
+            let patterns = list_sparse()?;
+            output(
+                format,
+                || json!({ "include": patterns }),
+                || {
+                    for pattern in &patterns {
+                        pretty_print(pattern);
+                    }
+                },
+            );
+        }
+        Command::Watch {
+            push_interval,
+            debounce,
+            no_pull,
+        } => {
+            watch(WatchOptions {
+                debounce: Duration::from_millis(debounce),
+                push_interval: push_interval.map(Duration::from_secs),
+                pull: !no_pull,
+                json: format == Format::Json,
+            })
+            .await?;
+        }
+        Command::Ls {
+            path,
+            long,
+            recursive,
+            cid,
+        } => {
+            let path = path.unwrap_or_else(|| PathBuf::from("."));
+            let path = utils::bucket_path(&root, &cwd, &path)?;
+            let entries = ls(path, recursive, long, cid).await?;
+            output(
+                format,
+                || {
+                    let entries = entries
+                        .iter()
+                        .map(|entry| {
+                            json!({
+                                "path": entry.path,
+                                "cid": entry.cid.to_string(),
+                                "dir": entry.is_dir(),
+                                "size": entry.size,
+                                "metadata": entry.metadata(),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({ "entries": entries })
+                },
+                || {
+                    for entry in &entries {
+                        let mut name = entry.path.display().to_string();
+                        if entry.is_dir() {
+                            name.push('/');
+                        }
+                        if !long {
+                            pretty_print(name);
+                            continue;
+                        }
+                        let size = match entry.size {
+                            Some(size) => size.to_string(),
+                            None => "-".to_string(),
+                        };
+                        let metadata = match entry.metadata() {
+                            Some(metadata) => metadata.to_string(),
+                            None => String::new(),
+                        };
+                        pretty_print(
+                            format!("{} {:>12} {} {}", entry.cid, size, name, metadata).trim_end(),
+                        );
+                    }
+                },
+            );
+        }
+        // File contents go to stdout as they are, whatever the format
+        Command::Cat { path, cid } => {
+            let path = utils::bucket_path(&root, &cwd, &path)?;
+            cat(path, cid).await?;
+        }
+        Command::Schema { file, clear } if clear || file.is_some() => {
+            // A schema file is given relative to where we were invoked; - stands for stdin
+            let file = file.map(|file| match file.to_str() {
+                Some("-") => file,
+                _ => cwd.join(file),
+            });
+            let cid = set_schema(file).await?;
+            output(
+                format,
+                || json!({ "cid": cid.to_string() }),
+                || pretty_print(format!("LeakyBucket @ {}", cid)),
+            );
+        }
+        Command::Schema { .. } => {
+            let schema = show_schema().await?;
+            output(
+                format,
+                || json!({ "schema": schema }),
+                || match &schema {
+                    Some(schema) => {
+                        for (key, field) in &schema.fields {
+                            pretty_print(format!("{}: {}", key, field));
+                        }
+                        if schema.open {
+                            pretty_print("other keys are allowed");
+                        }
+                    }
+                    None => pretty_print("no schema"),
+                },
+            );
+        }
+        Command::Fsck { dry_run } => {
+            let problems = fsck(dry_run).await?;
+            output(
+                format,
+                || {
+                    let problems = problems
+                        .iter()
+                        .map(|problem| {
+                            json!({
+                                "description": problem.description,
+                                "repaired": problem.repaired,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({ "problems": problems })
+                },
+                || {
+                    if problems.is_empty() {
+                        pretty_print("no problems found");
                     }
-                }
-        */
-        _ => {}
+                    for problem in &problems {
+                        let status = if problem.repaired {
+                            "repaired"
+                        } else {
+                            "not repaired"
+                        };
+                        pretty_print(format!("{} ({})", problem.description, status));
+                    }
+                },
+            );
+            // Anything left broken is a failure, so scripts can tell
+            let unrepaired = problems.iter().filter(|problem| !problem.repaired).count();
+            if unrepaired > 0 {
+                return Err(FsckError::Unrepaired(unrepaired).into());
+            }
+        }
+        Command::Ref { command } => match command {
+            RefCommand::Create { name, cid } => {
+                let cid = create_ref(&name, cid).await?;
+                output(
+                    format,
+                    || json!({ "name": name, "cid": cid.to_string() }),
+                    || pretty_print(format!("{} -> {}", name, cid)),
+                );
+            }
+            RefCommand::List => {
+                let refs = list_refs().await?;
+                output(
+                    format,
+                    || {
+                        let refs = refs
+                            .iter()
+                            .map(|(name, cid)| json!({ "name": name, "cid": cid.to_string() }))
+                            .collect::<Vec<_>>();
+                        json!({ "refs": refs })
+                    },
+                    || {
+                        for (name, cid) in &refs {
+                            pretty_print(format!("{} -> {}", name, cid));
+                        }
+                    },
+                );
+            }
+            RefCommand::Delete { name } => {
+                delete_ref(&name).await?;
+                output(
+                    format,
+                    || json!({ "deleted": name }),
+                    || pretty_print(format!("deleted {}", name)),
+                );
+            }
+        },
     };
     Ok(())
 }
@@ -84,26 +449,168 @@ pub async fn run() -> Result<(), AppError> {
 pub enum AppError {
     #[error("anyhow error: {0}")]
     Default(#[from] anyhow::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
     #[error("Init error: {0}")]
     Init(#[from] InitError),
+    #[error("Clone error: {0}")]
+    Clone(#[from] CloneError),
     #[error("Stage error: {0}")]
     Add(#[from] AddError),
     #[error("Stat error: {0}")]
     Stat(#[from] StatError),
+    #[error("Diff error: {0}")]
+    Diff(#[from] DiffRootsError),
+    #[error("Log error: {0}")]
+    Log(#[from] LogError),
     #[error("Push error: {0}")]
     Push(#[from] PushError),
     #[error("Pull error: {0}")]
     Pull(#[from] PullError),
     #[error("Tag error: {0}")]
     Tag(#[from] TagError),
+    #[error("Ref error: {0}")]
+    Ref(#[from] RefError),
+    #[error("Branch error: {0}")]
+    Branch(#[from] BranchError),
+    #[error("Switch error: {0}")]
+    Switch(#[from] SwitchError),
+    #[error("Watch error: {0}")]
+    Watch(#[from] WatchError),
+    #[error("Ls error: {0}")]
+    Ls(#[from] LsError),
+    #[error("Cat error: {0}")]
+    Cat(#[from] CatError),
+    #[error("Sparse error: {0}")]
+    Sparse(#[from] SparseCheckoutError),
+    #[error("Fsck error: {0}")]
+    Fsck(#[from] FsckError),
+    #[error("Schema error: {0}")]
+    Schema(#[from] BucketSchemaError),
+}
+
+/// What went wrong, in terms a script can act on. Each kind has its own exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorKind {
+    /// Anything we don't have a more specific kind for
+    Other,
+    /// A path, CID or ref that doesn't exist
+    NotFound,
+    /// The branch moved on, or local changes are in the way
+    Conflict,
+    /// The ipfs node or leaky server couldn't be reached or failed
+    Remote,
+    /// Reading or writing the working tree or local state failed
+    Io,
+}
+
+impl ErrorKind {
+    fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::Other => "error",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::Remote => "remote",
+            ErrorKind::Io => "io",
+        }
+    }
+
+    // 2 is left to clap, which uses it for usage errors
+    fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::NotFound => 3,
+            ErrorKind::Conflict => 4,
+            ErrorKind::Remote => 5,
+            ErrorKind::Io => 6,
+        }
+    }
 }
 
-fn capture_error<T>(result: Result<T, AppError>) {
-    match result {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("{}", e);
+impl AppError {
+    fn kind(&self) -> ErrorKind {
+        if matches!(
+            self,
+            AppError::Switch(SwitchError::UnpushedChanges)
+                | AppError::Sparse(SparseCheckoutError::UnpushedChanges)
+                | AppError::Watch(WatchError::Conflict(..))
+        ) {
+            return ErrorKind::Conflict;
         }
+
+        // Look through the errors this one wraps for the first we know how to classify
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(self);
+        while let Some(err) = source {
+            if let Some(err) = err.downcast_ref::<LeakyError>() {
+                return match err {
+                    LeakyError::PathNotFound(_)
+                    | LeakyError::PathNotDir(_)
+                    | LeakyError::PathNotFile(_) => ErrorKind::NotFound,
+                    // Caught before the request is sent, so the server isn't at fault
+                    LeakyError::LeakyApi(LeakyApiError::InvalidRefName(_)) => ErrorKind::Other,
+                    LeakyError::LeakyApi(LeakyApiError::Api(status, _)) => match status.as_u16() {
+                        404 => ErrorKind::NotFound,
+                        409 => ErrorKind::Conflict,
+                        _ => ErrorKind::Remote,
+                    },
+                    LeakyError::IpfsRpc(_) | LeakyError::LeakyApi(_) => ErrorKind::Remote,
+                    _ => ErrorKind::Other,
+                };
+            }
+            if err.is::<std::io::Error>() {
+                return ErrorKind::Io;
+            }
+            source = err.source();
+        }
+        ErrorKind::Other
+    }
+
+    fn exit_code(&self) -> i32 {
+        self.kind().exit_code()
+    }
+}
+
+fn report_error(format: Format, err: &AppError) {
+    match format {
+        Format::Json => eprintln!(
+            "{}",
+            json!({ "error": { "code": err.kind().code(), "message": err.to_string() } })
+        ),
+        Format::Text => eprintln!("{}", err),
+    }
+}
+
+/// Print a command's result, either as a single JSON document or as text for people
+fn output(format: Format, json: impl FnOnce() -> Value, text: impl FnOnce()) {
+    match format {
+        Format::Json => println!("{}", json()),
+        Format::Text => text(),
+    }
+}
+
+fn change_to_json(change: &ManifestChange) -> Value {
+    match change {
+        ManifestChange::Added { path, cid } => {
+            json!({ "change": "added", "path": path, "cid": cid.to_string() })
+        }
+        ManifestChange::Removed { path, cid } => {
+            json!({ "change": "removed", "path": path, "cid": cid.to_string() })
+        }
+        ManifestChange::Modified { path, from, to } => json!({
+            "change": "modified",
+            "path": path,
+            "from": from.to_string(),
+            "to": to.to_string(),
+        }),
+        ManifestChange::MetadataChanged { path, cid } => {
+            json!({ "change": "metadata_changed", "path": path, "cid": cid.to_string() })
+        }
+        ManifestChange::Moved { from, to, cid } => json!({
+            "change": "moved",
+            "from": from,
+            "to": to,
+            "cid": cid.to_string(),
+        }),
     }
 }
 
@@ -111,3 +618,120 @@ fn pretty_print<T: Display>(value: T) {
     let bullet = "•";
     println!("{} {}", bullet, value);
 }
+
+#[cfg(test)]
+mod test {
+    use leaky_common::prelude::Cid;
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    fn api_error(status: StatusCode) -> LeakyError {
+        LeakyError::LeakyApi(LeakyApiError::Api(status, String::new()))
+    }
+
+    fn io_error() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied")
+    }
+
+    #[test]
+    fn errors_are_classified_through_their_sources() {
+        let cases = [
+            (AppError::Default(anyhow::anyhow!("oops")), ErrorKind::Other),
+            (
+                AppError::Ls(LsError::Leaky(LeakyError::PathNotFound(PathBuf::from("a")))),
+                ErrorKind::NotFound,
+            ),
+            (
+                AppError::Ls(LsError::Leaky(LeakyError::PathNotDir(PathBuf::from("a")))),
+                ErrorKind::NotFound,
+            ),
+            (
+                AppError::Cat(CatError::Leaky(LeakyError::PathNotFile(PathBuf::from("a")))),
+                ErrorKind::NotFound,
+            ),
+            (
+                AppError::Diff(DiffRootsError::Leaky(api_error(StatusCode::NOT_FOUND))),
+                ErrorKind::NotFound,
+            ),
+            (
+                AppError::Push(PushError::Leaky(api_error(StatusCode::CONFLICT))),
+                ErrorKind::Conflict,
+            ),
+            (
+                AppError::Switch(SwitchError::UnpushedChanges),
+                ErrorKind::Conflict,
+            ),
+            (
+                AppError::Watch(WatchError::Conflict(Cid::default(), Cid::default())),
+                ErrorKind::Conflict,
+            ),
+            (
+                AppError::Pull(PullError::Leaky(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ))),
+                ErrorKind::Remote,
+            ),
+            (
+                AppError::Ref(RefError::Leaky(LeakyError::LeakyApi(
+                    LeakyApiError::InvalidRefName("..".to_string()),
+                ))),
+                ErrorKind::Other,
+            ),
+            (AppError::Io(io_error()), ErrorKind::Io),
+            (AppError::Cat(CatError::Io(io_error())), ErrorKind::Io),
+            // Errors wrapped in anyhow are looked through too
+            (
+                AppError::Ls(LsError::Default(anyhow::Error::from(io_error()))),
+                ErrorKind::Io,
+            ),
+            (
+                AppError::Default(anyhow::Error::from(LeakyError::PathNotFound(
+                    PathBuf::from("a"),
+                ))),
+                ErrorKind::NotFound,
+            ),
+        ];
+        for (err, kind) in cases {
+            assert_eq!(err.kind(), kind, "{err}");
+        }
+    }
+
+    #[test]
+    fn each_kind_has_its_own_exit_code() {
+        let cases = [
+            (AppError::Default(anyhow::anyhow!("oops")), 1),
+            (
+                AppError::Ls(LsError::Leaky(LeakyError::PathNotFound(PathBuf::from("a")))),
+                3,
+            ),
+            (AppError::Switch(SwitchError::UnpushedChanges), 4),
+            (
+                AppError::Push(PushError::Leaky(api_error(StatusCode::BAD_GATEWAY))),
+                5,
+            ),
+            (AppError::Io(io_error()), 6),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.exit_code(), code, "{err}");
+        }
+    }
+
+    #[test]
+    fn cloning_an_old_root_needs_a_branch_to_start() {
+        let cid = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku";
+        let clone = |args: &[&str]| {
+            let base = ["leaky", "clone", "http://localhost:3000", "--cid", cid];
+            Cli::try_parse_from(base.iter().chain(args))
+        };
+        assert!(clone(&[]).is_err());
+        match clone(&["--branch", "restore"]).unwrap().command {
+            Command::Clone {
+                cid: Some(_),
+                branch: Some(branch),
+                ..
+            } => assert_eq!(branch, "restore"),
+            command => panic!("unexpected command: {command:?}"),
+        }
+    }
+}
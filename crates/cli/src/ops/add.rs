@@ -52,7 +52,7 @@ pub async fn add() -> Result<Cid, AddError> {
     let new_root_cid = leaky.cid()?;
 
     if new_root_cid == root_cid {
-        println!("No changes to add");
+        eprintln!("No changes to add");
         return Ok(root_cid);
     }
 
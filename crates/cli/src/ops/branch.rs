@@ -0,0 +1,26 @@
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// List the head of every branch on the server, along with the branch checked out locally
+pub async fn list_branches() -> Result<(String, Vec<(String, Cid)>), BranchError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let branches = leaky.branches().await?;
+    Ok((leaky.branch().to_string(), branches))
+}
+
+/// Start a branch at the root last pulled or pushed, without switching to it
+pub async fn create_branch(name: &str) -> Result<Cid, BranchError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let cid = leaky.base()?;
+    leaky.create_branch(name, &cid).await?;
+    Ok(cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BranchError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
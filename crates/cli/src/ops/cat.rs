@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use futures_util::TryStreamExt;
+use leaky_common::prelude::*;
+use tokio::io::AsyncWriteExt;
+
+use super::utils;
+
+/// Stream the file at `path` in the bucket to stdout. `revision` reads it from another root
+/// instead of the one checked out.
+pub async fn cat(path: PathBuf, revision: Option<String>) -> Result<(), CatError> {
+    let leaky = utils::load_revision(revision).await?;
+    let mut data = Box::pin(leaky.cat_stream(&path).await?);
+
+    let mut stdout = tokio::io::stdout();
+    while let Some(chunk) = data.try_next().await? {
+        match stdout.write_all(&chunk).await {
+            Ok(()) => {}
+            // Whoever we were writing to stopped reading, e.g. `leaky cat file | head`
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    stdout.flush().await?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CatError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -5,6 +5,7 @@ use std::path::PathBuf;
 use leaky_common::prelude::*;
 
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 // TODO: this is an akward way to do this, i could probably
 // constructs diffs better
@@ -30,6 +31,18 @@ pub enum ChangeType {
     Removed,
 }
 
+impl ChangeType {
+    /// Stable name for the change, for machine-readable output
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Base => "base",
+            Self::Added { .. } => "added",
+            Self::Modified => "modified",
+            Self::Removed => "removed",
+        }
+    }
+}
+
 impl std::fmt::Display for ChangeType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
@@ -68,6 +81,21 @@ impl ChangeLog {
 
 pub struct DisplayableChangeLog(pub ChangeLog);
 
+impl DisplayableChangeLog {
+    /// The staged changes as a JSON document, leaving out unchanged files like the text output
+    pub fn to_json(&self) -> Value {
+        let changes = self
+            .0
+            .iter()
+            .filter(|(_, (_, change))| *change != ChangeType::Base)
+            .map(|(path, (cid, change))| {
+                json!({ "path": path, "cid": cid.to_string(), "change": change.name() })
+            })
+            .collect::<Vec<_>>();
+        json!({ "changes": changes })
+    }
+}
+
 impl std::fmt::Display for DisplayableChangeLog {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut s = String::new();
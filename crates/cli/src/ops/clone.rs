@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use url::Url;
+
+use leaky_common::prelude::*;
+
+use super::pull::{sync_working_tree, PullError};
+use super::utils;
+
+/// Which root of a remote bucket to clone
+pub enum CloneRevision {
+    /// The head of a branch, which the clone then follows
+    Branch(String),
+    /// A specific root, checked out onto a new branch started at it
+    Cid { cid: Cid, branch: String },
+    /// The root a ref points at, checked out onto a new branch started at it
+    Ref { name: String, branch: String },
+}
+
+/// Check out an existing bucket into the current directory, which must be empty. Only the parts
+/// selected by the sparse patterns in `include` are fetched. If the clone fails, whatever it
+/// wrote is removed again, so it can be retried.
+///
+/// A clone of an older root can't follow an existing branch, since pushes to that branch have to
+/// build on its head. So a `Cid` or `Ref` clone starts its own branch at the root it checks out,
+/// and fails if that branch already exists.
+pub async fn clone_bucket(
+    ipfs_rpc_url: Url,
+    leaky_api_url: Url,
+    revision: CloneRevision,
+    include: Vec<String>,
+) -> Result<Cid, CloneError> {
+    // Checking out would clobber, or start tracking, whatever is already here
+    let dir = Path::new(".");
+    if std::fs::read_dir(dir)?.next().is_some() {
+        return Err(CloneError::DirectoryNotEmpty);
+    }
+
+    let result = checkout_remote(ipfs_rpc_url, leaky_api_url, revision, include).await;
+    if result.is_err() {
+        if let Err(err) = clear_dir(dir) {
+            eprintln!("could not clean up after the failed clone: {}", err);
+        }
+    }
+    result
+}
+
+async fn checkout_remote(
+    ipfs_rpc_url: Url,
+    leaky_api_url: Url,
+    revision: CloneRevision,
+    include: Vec<String>,
+) -> Result<Cid, CloneError> {
+    let sparse = Sparse::new(&include)?;
+
+    let mut remote = Leaky::new(ipfs_rpc_url.clone(), leaky_api_url.clone())?;
+    let (cid, branch, new_branch) = match revision {
+        CloneRevision::Branch(branch) => {
+            remote.set_branch(&branch);
+            (remote.pull_root_cid().await?, branch, false)
+        }
+        CloneRevision::Cid { cid, branch } => (cid, branch, true),
+        CloneRevision::Ref { name, branch } => {
+            (remote.resolve_revision(&name).await?, branch, true)
+        }
+    };
+
+    let mut leaky = utils::init_on_disk(ipfs_rpc_url, leaky_api_url, Some(cid), include).await?;
+    leaky.set_branch(&branch);
+    sync_working_tree(&mut leaky, &sparse).await?;
+    // Started last, so a clone that fails partway doesn't leave the branch behind
+    if new_branch {
+        leaky.create_branch(&branch, &cid).await?;
+    }
+    Ok(cid)
+}
+
+/// Remove everything in a directory, leaving the directory itself. Symlinks are removed, not
+/// followed.
+fn clear_dir(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CloneError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("sparse error: {0}")]
+    Sparse(#[from] SparseError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("can only clone into an empty directory")]
+    DirectoryNotEmpty,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clearing_leaves_an_empty_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("clone");
+        let outside = tmp.path().join("outside");
+        std::fs::create_dir_all(dir.join(".leaky/blocks")).unwrap();
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(dir.join(".leaky/leaky.conf"), "{}").unwrap();
+        std::fs::write(dir.join("docs/notes.txt"), "notes").unwrap();
+        std::fs::write(outside.join("kept.txt"), "kept").unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+
+        clear_dir(&dir).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        assert!(outside.join("kept.txt").is_file());
+    }
+}
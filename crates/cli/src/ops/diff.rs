@@ -1,16 +1,55 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use fs_tree::FsTree;
 use leaky_common::prelude::*;
 
 use super::change_log::{ChangeLog, ChangeType};
 use super::utils;
 
+/// Hash a file in the working tree, counting it towards the diff's progress
+async fn hash_file(
+    leaky: &Leaky,
+    path: &PathBuf,
+    hashed: &mut usize,
+    total: usize,
+) -> Result<Cid, DiffError> {
+    let hash = utils::hash_file(path, leaky).await?;
+    *hashed += 1;
+    leaky.report(ProgressEvent::Hashed {
+        done: *hashed,
+        total,
+    });
+    Ok(hash)
+}
+
 pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, DiffError> {
-    let base: &mut ChangeLog = base;
+    let config = utils::load_config()?;
+    let sparse = Sparse::new(&config.include)?;
+    let root = Path::new(".");
+    // Files the change log tracks stay in the tree even if they're ignored, so they aren't
+    // mistaken for removals
+    let next = utils::fs_tree(root, &config.ignore, &sparse, base)?;
+    diff_tree(leaky, base, &next, root).await
+}
+
+/// Diff the change log against the working tree at `root`
+async fn diff_tree(
+    leaky: &Leaky,
+    base: &mut ChangeLog,
+    next: &FsTree,
+    root: &Path,
+) -> Result<ChangeLog, DiffError> {
     let mut update = base.clone();
-    let next = utils::fs_tree()?;
     let default_hash = Cid::default();
 
+    // Every file in the tree gets hashed once, which is most of the work here
+    let mut hashed = 0;
+    let to_hash = next.iter().filter(|(tree, _)| !tree.is_dir()).count();
+    leaky.report(ProgressEvent::Hashed {
+        done: hashed,
+        total: to_hash,
+    });
+
     // Insert the root directory hash into the change_log for comparison
     // This should always just get matched out and removed
     base.insert(PathBuf::from(""), (default_hash, ChangeType::Base));
@@ -32,7 +71,7 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
                 // If the base comes before then this file was removed
                 // strip off the base object and log the removal
                 if base_path < next_path {
-                    if !base_path.is_dir() {
+                    if !root.join(&base_path).is_dir() {
                         match base_type {
                             ChangeType::Added { .. } => {
                                 update.remove(&base_path);
@@ -49,8 +88,9 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
                 // If next comes before base then the file was added
                 // strip off the next object and log the addition
                 if next_path < base_path {
-                    if !next_path.is_dir() {
-                        let hash = utils::hash_file(&next_path, leaky).await?;
+                    if !root.join(&next_path).is_dir() {
+                        let hash =
+                            hash_file(leaky, &root.join(&next_path), &mut hashed, to_hash).await?;
                         update.insert(
                             next_path.clone(),
                             (hash, ChangeType::Added { modified: true }),
@@ -67,7 +107,8 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
                     if !next_tree.is_dir() {
                         // If the hashes are different then the file was modified
                         // strip off the next object and log the modification
-                        let next_hash = utils::hash_file(&next_path, leaky).await?;
+                        let next_hash =
+                            hash_file(leaky, &root.join(&next_path), &mut hashed, to_hash).await?;
                         if base_hash != &next_hash {
                             match base_type {
                                 // If it has been added before, then it was modified
@@ -102,7 +143,8 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
             // Theres more new files than old, so this file was added
             (Some((next_tree, next_path)), None) => {
                 if !next_tree.is_dir() {
-                    let hash = utils::hash_file(&next_path, leaky).await?;
+                    let hash =
+                        hash_file(leaky, &root.join(&next_path), &mut hashed, to_hash).await?;
                     update.insert(
                         next_path.clone(),
                         (hash, ChangeType::Added { modified: true }),
@@ -114,7 +156,7 @@ pub async fn diff(leaky: &Leaky, base: &mut ChangeLog) -> Result<ChangeLog, Diff
 
             // There's more old files than new, so this file was removed
             (None, Some((base_path, (_base_hash, base_type)))) => {
-                if !base_path.is_dir() {
+                if !root.join(&base_path).is_dir() {
                     match base_type {
                         ChangeType::Added { .. } => {
                             update.remove(&base_path);
@@ -145,6 +187,8 @@ pub enum DiffError {
     ReadChanges(#[from] serde_json::Error),
     #[error("fs-tree error: {0}")]
     FsTree(#[from] fs_tree::Error),
+    #[error("sparse error: {0}")]
+    Sparse(#[from] SparseError),
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
     #[error("leaky error: {0}")]
@@ -154,3 +198,33 @@ pub enum DiffError {
     #[error("path is a directory")]
     PathIsDirectory(PathBuf),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn pulled_files_are_kept_when_ignored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        let ignore = ["target/".to_string(), "*~".to_string()];
+
+        // What a pull of a root holding ignored paths leaves behind
+        let leaky = Leaky::default();
+        let mut base = ChangeLog::new();
+        for file in ["notes.txt", "notes.txt~", "target/out"] {
+            std::fs::write(root.join(file), file).unwrap();
+            let cid = leaky.hash_data(file.as_bytes()).await.unwrap();
+            base.insert(PathBuf::from(file), (cid, ChangeType::Base));
+        }
+        // Ignored, and never tracked
+        std::fs::write(root.join("target/new"), "new").unwrap();
+
+        let next = utils::fs_tree(root, &ignore, &Sparse::default(), &base).unwrap();
+        let update = diff_tree(&leaky, &mut base.clone(), &next, root)
+            .await
+            .unwrap();
+        assert_eq!(update, base);
+    }
+}
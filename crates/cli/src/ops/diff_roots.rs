@@ -0,0 +1,27 @@
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// Compare two roots, each given as a CID or ref name. `to` defaults to the root currently
+/// checked out.
+pub async fn diff_roots(
+    from: &str,
+    to: Option<String>,
+) -> Result<Vec<ManifestChange>, DiffRootsError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let from = leaky.resolve_revision(from).await?;
+    let to = match to {
+        Some(to) => leaky.resolve_revision(&to).await?,
+        None => leaky.cid()?,
+    };
+    let changes = leaky.diff_manifests(&from, &to).await?;
+    Ok(changes)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiffRootsError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
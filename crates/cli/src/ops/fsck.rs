@@ -0,0 +1,261 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use leaky_common::prelude::*;
+
+use super::change_log::{ChangeLog, ChangeType};
+use super::utils::{
+    self, DEFAULT_CACHE_NAME, DEFAULT_CHAGE_LOG_NAME, DEFAULT_LOCAL_DIR, TMP_SUFFIX,
+};
+
+/// Something wrong with the local state, and whether it was fixed
+#[derive(Debug, Clone)]
+pub struct FsckProblem {
+    pub description: String,
+    pub repaired: bool,
+}
+
+/// Check the local state for leftovers of interrupted writes, and for blocks or a change log that
+/// don't go with the state, repairing what we can unless this is a `dry_run`. Corrupt and missing
+/// blocks are fetched again from ipfs; a change log that doesn't match the root is rebuilt from it.
+pub async fn fsck(dry_run: bool) -> Result<Vec<FsckProblem>, FsckError> {
+    let config = utils::load_config()?;
+    let sparse = Sparse::new(&config.include)?;
+    // Without the state there's no telling which generation of everything else is current
+    let state = utils::load_state().map_err(FsckError::State)?;
+
+    let mut problems = Vec::new();
+    let mut problem = |description: String, repaired: bool| {
+        problems.push(FsckProblem {
+            description,
+            repaired,
+        })
+    };
+
+    // Clear out what interrupted writes and saves left behind
+    let blocks_dir = Path::new(DEFAULT_LOCAL_DIR).join(DEFAULT_BLOCKS_DIR_NAME);
+    for dir in [Path::new(DEFAULT_LOCAL_DIR), blocks_dir.as_path()] {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let description = if name.ends_with(TMP_SUFFIX) {
+                format!("{} was left behind by an interrupted write", name)
+            } else if dir != blocks_dir && is_stale_generation(&name, state.generation) {
+                format!("{} belongs to an old generation of the local state", name)
+            } else {
+                continue;
+            };
+            let repaired = !dry_run && std::fs::remove_file(entry.path()).is_ok();
+            problem(description, repaired);
+        }
+    }
+
+    let mut leaky = Leaky::new(config.ipfs_rpc_url, config.leaky_api_url)?;
+    leaky.set_concurrency(config.concurrency);
+    leaky.set_branch(&state.branch);
+    leaky.set_block_store(utils::block_store());
+    leaky.set_cache_capacity(config.cache_capacity);
+    let block_cache = match utils::load_legacy_cache(state.generation) {
+        Ok(block_cache) => block_cache,
+        Err(err) => {
+            problem(format!("the old block cache can't be read: {}", err), false);
+            BlockCache::default()
+        }
+    };
+    leaky.load(&state.cid, &state.manifest, block_cache).await?;
+    leaky.set_base(state.base.unwrap_or(state.cid));
+
+    // Corrupt blocks are dropped, so they're fetched again along with anything else missing
+    let corrupt = leaky.corrupt_blocks()?;
+    for cid in &corrupt {
+        let repaired = !dry_run && leaky.remove_block(cid).is_ok();
+        problem(format!("block {} is corrupt", cid), repaired);
+    }
+    // Left in place, they'd stop us walking the tree any further
+    if dry_run && !corrupt.is_empty() {
+        return Ok(problems);
+    }
+
+    // The block store needs every node of the checked out tree
+    let missing = leaky.missing_nodes(&sparse).await?;
+    for cid in &missing {
+        problem(format!("the block store is missing node {}", cid), false);
+    }
+    let mut cache_ok = missing.is_empty();
+    let mut changed = false;
+    if !cache_ok && !dry_run {
+        // Nodes we still have are kept, so only what's missing is fetched
+        match leaky.pull_sparse(&state.cid, &sparse).await {
+            Ok(()) => {
+                // Fetching the root's nodes doesn't mean it's been pushed
+                leaky.set_base(state.base.unwrap_or(state.cid));
+                problem("fetched the missing nodes from ipfs".to_string(), true);
+                cache_ok = true;
+                changed = true;
+            }
+            Err(err) => problem(
+                format!(
+                    "couldn't fetch root {} from ipfs ({}); clone the bucket again",
+                    state.cid, err
+                ),
+                false,
+            ),
+        }
+    }
+    // Without the tree there's nothing to check the change log against
+    if !cache_ok {
+        return Ok(problems);
+    }
+
+    // The change log needs an entry for every file in the root
+    let items = leaky.sparse_items(&sparse).await?;
+    let mut change_log = match utils::load_change_log(state.generation) {
+        Ok(change_log) if matches_root(&change_log, &items) => Some(change_log),
+        Ok(_) => {
+            problem("the change log doesn't match the root".to_string(), false);
+            None
+        }
+        Err(err) => {
+            problem(format!("the change log can't be read: {}", err), false);
+            None
+        }
+    };
+    if change_log.is_none() && !dry_run {
+        change_log = Some(rebuild_change_log(&leaky, &items).await);
+        problem(
+            "rebuilt the change log from the root; staged removals need to be added again"
+                .to_string(),
+            true,
+        );
+        changed = true;
+    }
+
+    if let (true, Some(change_log)) = (changed, change_log) {
+        utils::commit_on_disk(&leaky, &change_log)?;
+    }
+
+    Ok(problems)
+}
+
+/// Whether a file in the local directory is the cache or change log of a generation other than
+/// the current one
+fn is_stale_generation(name: &str, current: u64) -> bool {
+    [DEFAULT_CACHE_NAME, DEFAULT_CHAGE_LOG_NAME]
+        .iter()
+        .any(|base| {
+            let generation = if name == *base {
+                Some(0)
+            } else {
+                name.strip_prefix(base)
+                    .and_then(|rest| rest.strip_prefix('.'))
+                    .and_then(|rest| rest.parse::<u64>().ok())
+            };
+            generation.is_some_and(|generation| generation != current)
+        })
+}
+
+/// Whether the change log tracks exactly the files in the root, leaving aside staged removals
+fn matches_root(change_log: &ChangeLog, items: &[(PathBuf, Cid)]) -> bool {
+    let logged = change_log
+        .iter()
+        .filter(|(_, (_, change))| *change != ChangeType::Removed)
+        .map(|(path, _)| path.clone())
+        .collect::<BTreeSet<_>>();
+    let in_root = items
+        .iter()
+        .map(|(path, _)| path.strip_prefix("/").unwrap_or(path).to_path_buf())
+        .collect::<BTreeSet<_>>();
+    logged == in_root
+}
+
+/// A change log for the root. Files whose data ipfs doesn't have yet were added since the last
+/// push, so they're marked modified for the next push to upload.
+async fn rebuild_change_log(leaky: &Leaky, items: &[(PathBuf, Cid)]) -> ChangeLog {
+    let mut change_log = ChangeLog::new();
+    for (path, cid) in items {
+        let change = if leaky.has_block(cid).await {
+            ChangeType::Base
+        } else {
+            ChangeType::Modified
+        };
+        let path = path.strip_prefix("/").unwrap_or(path).to_path_buf();
+        change_log.insert(path, (*cid, change));
+    }
+    change_log
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FsckError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("sparse error: {0}")]
+    Sparse(#[from] SparseError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not read the local state, so the checkout needs cloning again: {0}")]
+    State(anyhow::Error),
+    #[error("{0} problem(s) with the local state weren't repaired")]
+    Unrepaired(usize),
+}
+
+#[cfg(test)]
+mod test {
+    use libipld::cid::multihash::{Code, MultihashDigest};
+
+    use super::*;
+
+    fn cid(data: &[u8]) -> Cid {
+        Cid::new_v1(0x55, Code::Blake3_256.digest(data))
+    }
+
+    #[test]
+    fn other_generations_are_stale() {
+        assert!(is_stale_generation("leaky.cache", 2));
+        assert!(is_stale_generation("leaky.cache.1", 2));
+        assert!(is_stale_generation("leaky.log.3", 2));
+        assert!(!is_stale_generation("leaky.cache.2", 2));
+        assert!(!is_stale_generation("leaky.log.2", 2));
+        // Generation 0 is the files from before generations existed
+        assert!(!is_stale_generation("leaky.cache", 0));
+        assert!(!is_stale_generation("leaky.log", 0));
+        for name in [
+            "leaky.conf",
+            "leaky.state",
+            "leaky.cache.old",
+            "leaky.cachex.1",
+        ] {
+            assert!(!is_stale_generation(name, 2), "{name}");
+        }
+    }
+
+    #[test]
+    fn change_log_must_track_the_files_in_the_root() {
+        let items = vec![
+            (PathBuf::from("/a"), cid(b"a")),
+            (PathBuf::from("/d/b"), cid(b"b")),
+        ];
+        let mut change_log = ChangeLog::new();
+        change_log.insert(PathBuf::from("a"), (cid(b"a"), ChangeType::Base));
+        change_log.insert(PathBuf::from("d/b"), (cid(b"c"), ChangeType::Modified));
+        // Staged removals are gone from the root already
+        change_log.insert(PathBuf::from("e"), (cid(b"e"), ChangeType::Removed));
+        assert!(matches_root(&change_log, &items));
+
+        let mut extra = change_log.clone();
+        extra.insert(
+            PathBuf::from("f"),
+            (cid(b"f"), ChangeType::Added { modified: true }),
+        );
+        assert!(!matches_root(&extra, &items));
+
+        change_log.remove(&PathBuf::from("d/b"));
+        assert!(!matches_root(&change_log, &items));
+    }
+}
@@ -5,7 +5,7 @@ use leaky_common::prelude::*;
 use super::utils;
 
 pub async fn init(ipfs_rpc_url: Url, leaky_api_url: Url) -> Result<Cid, InitError> {
-    let mut leaky = utils::init_on_disk(ipfs_rpc_url, leaky_api_url, None).await?;
+    let mut leaky = utils::init_on_disk(ipfs_rpc_url, leaky_api_url, None, Vec::new()).await?;
     leaky.push().await?;
     let cid = leaky.cid()?;
     Ok(cid)
@@ -0,0 +1,49 @@
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// A root in the history of the bucket
+pub struct LogEntry {
+    pub cid: Cid,
+    /// The root this one was pushed on top of. The first root of a bucket has none.
+    pub previous: Option<Cid>,
+    /// Version of leaky that made the root
+    pub version: String,
+}
+
+/// Follow the chain of previous roots back from `revision`, a CID or ref name, or from the root
+/// checked out. Stops after `limit` roots if it's given.
+pub async fn log(
+    revision: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<LogEntry>, LogError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let mut cid = match revision {
+        Some(revision) => leaky.resolve_revision(&revision).await?,
+        None => leaky.cid()?,
+    };
+
+    let mut entries = Vec::new();
+    while limit.is_none_or(|limit| entries.len() < limit) {
+        let manifest = leaky.manifest_at(&cid).await?;
+        let previous = Some(*manifest.previous()).filter(|previous| *previous != Cid::default());
+        entries.push(LogEntry {
+            cid,
+            previous,
+            version: manifest.version().version().to_string(),
+        });
+        match previous {
+            Some(previous) => cid = previous,
+            None => break,
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
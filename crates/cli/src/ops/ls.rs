@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+use serde_json::Value;
+
+use super::metadata::metadata_to_value;
+use super::utils;
+
+/// An entry in a listing of the bucket
+pub struct LsEntry {
+    /// Path relative to the directory being listed
+    pub path: PathBuf,
+    pub cid: Cid,
+    /// Files and directories made since directories could have metadata have an object, older
+    /// directories don't
+    pub object: Option<Object>,
+    /// Only looked up for long listings
+    pub size: Option<u64>,
+}
+
+impl LsEntry {
+    pub fn is_dir(&self) -> bool {
+        is_dir_link(&self.cid)
+    }
+
+    /// The entry's metadata as JSON, if it has any
+    pub fn metadata(&self) -> Option<Value> {
+        let metadata = self.object.as_ref()?.metadata();
+        if metadata.is_empty() {
+            return None;
+        }
+        Some(metadata_to_value(metadata))
+    }
+}
+
+/// List the directory at `path` in the bucket, or everything under it if `recursive`. `revision`
+/// lists another root instead of the one checked out.
+pub async fn ls(
+    path: PathBuf,
+    recursive: bool,
+    long: bool,
+    revision: Option<String>,
+) -> Result<Vec<LsEntry>, LsError> {
+    let leaky = utils::load_revision(revision).await?;
+
+    let mut entries = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for (name, (cid, object)) in leaky.ls(&path.join(&dir)).await? {
+            let entry_path = dir.join(name);
+            let is_dir = is_dir_link(&cid);
+            if recursive && is_dir {
+                dirs.push(entry_path.clone());
+            }
+            let size = if long && !is_dir {
+                Some(leaky.data_size(&cid).await?)
+            } else {
+                None
+            };
+            entries.push(LsEntry {
+                path: entry_path,
+                cid,
+                object,
+                size,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LsError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use leaky_common::prelude::*;
+use serde_json::{json, Map, Value};
+
+// Metadata goes to and from JSON following DAG-JSON: links are `{"/": "<cid>"}` and bytes are
+// `{"/": {"bytes": "<base64>"}}`, so what `leaky ls` prints can be fed back to `leaky tag`.
+
+/// Read metadata from a JSON object
+pub fn value_to_metadata(value: &Value) -> Result<BTreeMap<String, Ipld>, MetadataError> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), value_to_ipld(value)?)))
+            .collect(),
+        _ => Err(MetadataError::NotAnObject(value.clone())),
+    }
+}
+
+fn value_to_ipld(value: &Value) -> Result<Ipld, MetadataError> {
+    let ipld = match value {
+        Value::Null => Ipld::Null,
+        Value::Bool(b) => Ipld::Bool(*b),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => Ipld::Integer(i as i128),
+            (_, Some(u)) => Ipld::Integer(u as i128),
+            _ => Ipld::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => Ipld::String(s.clone()),
+        Value::Array(a) => Ipld::List(a.iter().map(value_to_ipld).collect::<Result<_, _>>()?),
+        Value::Object(map) => match map.get("/") {
+            Some(inner) if map.len() == 1 => reserved_to_ipld(inner)?,
+            Some(_) => return Err(MetadataError::Reserved(value.clone())),
+            None => Ipld::Map(value_to_metadata(value)?),
+        },
+    };
+    Ok(ipld)
+}
+
+/// The value of an object with only a `/` key, which is either a link or bytes
+fn reserved_to_ipld(inner: &Value) -> Result<Ipld, MetadataError> {
+    match inner {
+        Value::String(cid) => Cid::from_str(cid)
+            .map(Ipld::Link)
+            .map_err(|err| MetadataError::Link(cid.clone(), err)),
+        Value::Object(map) if map.len() == 1 => match map.get("bytes") {
+            Some(Value::String(bytes)) => {
+                // DAG-JSON leaves out the padding, but there's no harm in accepting it
+                let bytes = STANDARD_NO_PAD.decode(bytes.trim_end_matches('='))?;
+                Ok(Ipld::Bytes(bytes))
+            }
+            _ => Err(MetadataError::Reserved(json!({ "/": inner }))),
+        },
+        _ => Err(MetadataError::Reserved(json!({ "/": inner }))),
+    }
+}
+
+/// Show metadata as a JSON object
+pub fn metadata_to_value(metadata: &BTreeMap<String, Ipld>) -> Value {
+    Value::Object(
+        metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), ipld_to_value(value)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+fn ipld_to_value(ipld: &Ipld) -> Value {
+    match ipld {
+        Ipld::Null => Value::Null,
+        Ipld::Bool(b) => Value::Bool(*b),
+        // JSON numbers can't hold every integer IPLD can
+        Ipld::Integer(i) => match (i64::try_from(*i), u64::try_from(*i)) {
+            (Ok(i), _) => Value::from(i),
+            (_, Ok(u)) => Value::from(u),
+            _ => Value::String(i.to_string()),
+        },
+        Ipld::Float(f) => Value::from(*f),
+        Ipld::String(s) => Value::String(s.clone()),
+        Ipld::Bytes(b) => json!({ "/": { "bytes": STANDARD_NO_PAD.encode(b) } }),
+        Ipld::List(l) => Value::Array(l.iter().map(ipld_to_value).collect()),
+        Ipld::Map(m) => metadata_to_value(m),
+        Ipld::Link(cid) => json!({ "/": cid.to_string() }),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataError {
+    #[error("metadata must be a JSON object, not {0}")]
+    NotAnObject(Value),
+    #[error("invalid link to {0}: {1}")]
+    Link(String, libipld::cid::Error),
+    #[error("invalid base64 bytes: {0}")]
+    Bytes(#[from] base64::DecodeError),
+    #[error("{0} is neither a link nor bytes; objects keyed \"/\" are reserved for them")]
+    Reserved(Value),
+}
+
+#[cfg(test)]
+mod test {
+    use libipld::cid::multihash::{Code, MultihashDigest};
+
+    use super::*;
+
+    fn cid() -> Cid {
+        Cid::new_v1(0x55, Code::Blake3_256.digest(b"data"))
+    }
+
+    fn metadata(value: Value) -> Result<BTreeMap<String, Ipld>, MetadataError> {
+        value_to_metadata(&value)
+    }
+
+    #[test]
+    fn links_and_bytes() {
+        let cid = cid();
+        let parsed = metadata(json!({
+            "link": { "/": cid.to_string() },
+            "bytes": { "/": { "bytes": "aGVsbG8" } },
+            "padded": { "/": { "bytes": "aGVsbG8=" } },
+        }))
+        .unwrap();
+        assert_eq!(parsed["link"], Ipld::Link(cid));
+        assert_eq!(parsed["bytes"], Ipld::Bytes(b"hello".to_vec()));
+        assert_eq!(parsed["padded"], Ipld::Bytes(b"hello".to_vec()));
+
+        assert_eq!(
+            ipld_to_value(&Ipld::Link(cid)),
+            json!({ "/": cid.to_string() })
+        );
+        assert_eq!(
+            ipld_to_value(&Ipld::Bytes(b"hello".to_vec())),
+            json!({ "/": { "bytes": "aGVsbG8" } })
+        );
+    }
+
+    #[test]
+    fn nested_maps_and_lists() {
+        let parsed = metadata(json!({
+            "labels": ["cat", { "score": 0.5, "boxes": [[1, 2], []] }],
+            "source": { "camera": { "id": -3, "ok": true }, "notes": null },
+        }))
+        .unwrap();
+        assert_eq!(
+            parsed["labels"],
+            Ipld::List(vec![
+                Ipld::String("cat".to_string()),
+                Ipld::Map(BTreeMap::from([
+                    ("score".to_string(), Ipld::Float(0.5)),
+                    (
+                        "boxes".to_string(),
+                        Ipld::List(vec![
+                            Ipld::List(vec![Ipld::Integer(1), Ipld::Integer(2)]),
+                            Ipld::List(vec![]),
+                        ]),
+                    ),
+                ])),
+            ])
+        );
+        assert_eq!(
+            parsed["source"],
+            Ipld::Map(BTreeMap::from([
+                (
+                    "camera".to_string(),
+                    Ipld::Map(BTreeMap::from([
+                        ("id".to_string(), Ipld::Integer(-3)),
+                        ("ok".to_string(), Ipld::Bool(true)),
+                    ])),
+                ),
+                ("notes".to_string(), Ipld::Null),
+            ]))
+        );
+    }
+
+    #[test]
+    fn reserved_objects_must_be_links_or_bytes() {
+        for value in [
+            json!({ "key": { "/": "x", "other": 1 } }),
+            json!({ "key": { "/": 1 } }),
+            json!({ "key": { "/": { "bytes": 1 } } }),
+            json!({ "key": { "/": { "bytes": "aGVsbG8", "other": 1 } } }),
+            json!({ "key": [{ "/": { "other": "aGVsbG8" } }] }),
+        ] {
+            assert!(
+                matches!(metadata(value.clone()), Err(MetadataError::Reserved(_))),
+                "{value}"
+            );
+        }
+        assert!(matches!(
+            metadata(json!({ "key": { "/": "not a cid" } })),
+            Err(MetadataError::Link(..))
+        ));
+        assert!(matches!(
+            metadata(json!({ "key": { "/": { "bytes": "not base64!" } } })),
+            Err(MetadataError::Bytes(_))
+        ));
+        assert!(matches!(
+            metadata(json!(["not", "an", "object"])),
+            Err(MetadataError::NotAnObject(_))
+        ));
+    }
+
+    #[test]
+    fn large_integers() {
+        let parsed = metadata(json!({ "big": u64::MAX, "small": i64::MIN })).unwrap();
+        assert_eq!(parsed["big"], Ipld::Integer(u64::MAX as i128));
+        assert_eq!(parsed["small"], Ipld::Integer(i64::MIN as i128));
+        assert_eq!(
+            metadata_to_value(&parsed),
+            json!({ "big": u64::MAX, "small": i64::MIN })
+        );
+        // Past what JSON numbers can hold, integers are shown as strings
+        assert_eq!(
+            ipld_to_value(&Ipld::Integer(u64::MAX as i128 + 1)),
+            json!("18446744073709551616")
+        );
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let value = json!({
+            "name": "sample",
+            "count": 42,
+            "ratio": 0.25,
+            "big": u64::MAX,
+            "tags": ["a", "b"],
+            "nested": { "empty": {}, "list": [null, false] },
+            "link": { "/": cid().to_string() },
+            "bytes": { "/": { "bytes": "AAEC" } },
+        });
+        assert_eq!(metadata_to_value(&metadata(value.clone()).unwrap()), value);
+    }
+}
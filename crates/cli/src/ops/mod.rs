@@ -1,16 +1,41 @@
 mod add;
+mod branch;
+mod cat;
 mod change_log;
+mod clone;
 mod diff;
+mod diff_roots;
+mod fsck;
 mod init;
+mod log;
+mod ls;
+mod metadata;
 mod pull;
 mod push;
+mod refs;
+mod schema;
+mod sparse;
 mod stat;
+mod switch;
 mod tag;
 pub mod utils;
+mod watch;
 
 pub use add::{add, AddError};
+pub use branch::{create_branch, list_branches, BranchError};
+pub use cat::{cat, CatError};
+pub use clone::{clone_bucket, CloneError, CloneRevision};
+pub use diff_roots::{diff_roots, DiffRootsError};
+pub use fsck::{fsck, FsckError};
 pub use init::{init, InitError};
+pub use log::{log, LogError};
+pub use ls::{ls, LsError};
 pub use pull::{pull, PullError};
 pub use push::{push, PushError};
+pub use refs::{create_ref, delete_ref, list_refs, RefError};
+pub use schema::{set_schema, show_schema, BucketSchemaError};
+pub use sparse::{list_sparse, set_sparse, SparseCheckoutError};
 pub use stat::{stat, StatError};
-pub use tag::{tag, TagError};
+pub use switch::{switch, SwitchError};
+pub use tag::{tag, TagError, TagOptions};
+pub use watch::{watch, WatchError, WatchOptions};
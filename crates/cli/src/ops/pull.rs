@@ -1,5 +1,5 @@
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use leaky_common::prelude::*;
 
@@ -29,6 +29,10 @@ pub async fn pull_file(leaky: &Leaky, path: &PathBuf) -> Result<(), PullError> {
     std::fs::create_dir_all(object_path)?;
     let mut file = std::fs::File::create(path)?;
     file.write_all(data_vec.as_slice())?;
+    leaky.report(ProgressEvent::Bytes {
+        kind: TransferKind::PullData,
+        bytes: data_vec.len() as u64,
+    });
     Ok(())
 }
 
@@ -37,13 +41,28 @@ fn rm_file(path: &PathBuf) -> Result<(), PullError> {
     Ok(())
 }
 
-pub async fn pull() -> Result<Cid, PullError> {
+pub async fn pull(revision: Option<String>) -> Result<Cid, PullError> {
     let (mut leaky, _) = utils::load_on_disk().await?;
-    let root_cid = leaky.pull_root_cid().await?;
-    leaky.pull(&root_cid).await?;
+    let root_cid = match revision {
+        Some(revision) => leaky.resolve_revision(&revision).await?,
+        None => leaky.pull_root_cid().await?,
+    };
+    checkout(&mut leaky, &root_cid).await?;
+    Ok(root_cid)
+}
+
+/// Pull a root and bring the working tree in line with it
+pub async fn checkout(leaky: &mut Leaky, root_cid: &Cid) -> Result<(), PullError> {
+    // Only the parts of the bucket the sparse patterns select are fetched and tracked
+    let sparse = utils::load_sparse()?;
+    leaky.pull_sparse(root_cid, &sparse).await?;
+    sync_working_tree(leaky, &sparse).await
+}
 
+/// Bring the working tree in line with the root that's loaded, starting the change log over
+pub async fn sync_working_tree(leaky: &mut Leaky, sparse: &Sparse) -> Result<(), PullError> {
     let pulled_items = leaky
-        .items()
+        .sparse_items(sparse)
         .await?
         .iter()
         .map(|(path, cid)| (path.strip_prefix("/").unwrap().to_path_buf(), *cid))
@@ -55,7 +74,10 @@ pub async fn pull() -> Result<Cid, PullError> {
         change_log.insert(path.clone(), (*cid, ChangeType::Base));
     }
 
-    let current_fs_tree = utils::fs_tree()?;
+    // Unselected files, and ignored files the root doesn't have, are left out of the tree, so
+    // they're never pruned
+    let config = utils::load_config()?;
+    let current_fs_tree = utils::fs_tree(Path::new("."), &config.ignore, &sparse, &change_log)?;
 
     let mut pi_iter = pulled_items.iter();
     let mut ci_iter = current_fs_tree.iter();
@@ -78,7 +100,7 @@ pub async fn pull() -> Result<Cid, PullError> {
                     to_pull.push((pi_path, pi_cid));
                 } else if pi_path > &ci_path {
                     to_prune.push(ci_path);
-                } else if file_needs_pull(&leaky, &ci_path, pi_cid).await?
+                } else if file_needs_pull(leaky, &ci_path, pi_cid).await?
                     && *pi_cid != Cid::default()
                 {
                     to_pull.push((pi_path, pi_cid));
@@ -100,16 +122,21 @@ pub async fn pull() -> Result<Cid, PullError> {
         }
     }
 
-    for item in to_pull {
-        pull_file(&leaky, item.0).await?;
-    }
+    let leaky_ref = &*leaky;
+    leaky
+        .transfer()
+        .run(TransferKind::PullData, to_pull, |(path, _)| async move {
+            pull_file(leaky_ref, path).await?;
+            Ok::<_, PullError>(true)
+        })
+        .await?;
 
     for path in to_prune {
         rm_file(&path)?;
     }
 
-    utils::save_on_disk(&mut leaky, &change_log).await?;
-    Ok(root_cid)
+    utils::save_on_disk(leaky, &change_log).await?;
+    Ok(())
 }
 
 #[derive(Debug, thiserror::Error)]
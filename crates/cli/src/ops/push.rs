@@ -1,11 +1,88 @@
-use std::fs::File;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
 
 use leaky_common::prelude::*;
 
 use super::change_log::ChangeType;
-use super::utils;
+use super::utils::{self, DEFAULT_LOCAL_DIR, DEFAULT_PUSH_JOURNAL_NAME};
 
-pub async fn push() -> Result<Cid, PushError> {
+/// Starts the first line of the push journal, naming the root it's for
+const JOURNAL_ROOT_PREFIX: &str = "root ";
+
+/// The push journal kept in the local directory: the root being pushed, then the CID of each
+/// block confirmed on ipfs, one per line, appended as they're confirmed. It's only removed once a
+/// push has been published and saved, so a push that dies part way through resumes from it on
+/// the next run. A journal left by a push of a different root is thrown away.
+struct DiskJournal {
+    confirmed: HashSet<Cid>,
+    file: Mutex<File>,
+}
+
+impl DiskJournal {
+    fn path() -> PathBuf {
+        Path::new(DEFAULT_LOCAL_DIR).join(DEFAULT_PUSH_JOURNAL_NAME)
+    }
+
+    fn open(path: &Path, root: &Cid) -> Result<Self, std::io::Error> {
+        let header = format!("{}{}\n", JOURNAL_ROOT_PREFIX, root);
+        let journal = match std::fs::read_to_string(path) {
+            Ok(journal) => Some(journal),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let resumed = journal
+            .as_deref()
+            .and_then(|journal| journal.strip_prefix(&header));
+        let (confirmed, file) = match resumed {
+            // A line cut off by a crash mid-write won't parse, and its block is just checked again
+            Some(lines) => (
+                lines
+                    .lines()
+                    .filter_map(|line| Cid::from_str(line).ok())
+                    .collect(),
+                OpenOptions::new().append(true).open(path)?,
+            ),
+            None => {
+                let mut file = File::create(path)?;
+                file.write_all(header.as_bytes())?;
+                file.sync_data()?;
+                (HashSet::new(), file)
+            }
+        };
+        Ok(Self {
+            confirmed,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn remove(path: &Path) -> Result<(), std::io::Error> {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl PushJournal for DiskJournal {
+    fn is_confirmed(&self, cid: &Cid) -> bool {
+        self.confirmed.contains(cid)
+    }
+
+    fn confirm(&self, cid: &Cid) -> Result<(), std::io::Error> {
+        // Each line goes out in a single write, so concurrent confirmations don't interleave
+        let line = format!("{}\n", cid);
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+}
+
+pub async fn push(message: Option<String>) -> Result<Cid, PushError> {
     let (mut leaky, change_log) = utils::load_on_disk().await?;
 
     let mut updates = change_log.clone();
@@ -13,21 +90,14 @@ pub async fn push() -> Result<Cid, PushError> {
     let root_cid = leaky.cid()?;
 
     let mut changed = false;
+    let mut to_upload = Vec::new();
     let change_log_iter = change_log.iter();
     // Iterate over the ChangeLog -- play updates against the base ... probably better to do this
     for (path, (hash, diff_type)) in change_log_iter {
         match diff_type {
-            ChangeType::Added { .. } => {
+            ChangeType::Added { .. } | ChangeType::Modified => {
                 changed = true;
-                let file = File::open(&path)?;
-                leaky.add_data(file).await?;
-                updates.insert(path.clone(), (*hash, ChangeType::Base));
-            }
-
-            ChangeType::Modified => {
-                changed = true;
-                let file = File::open(&path)?;
-                leaky.add_data(file).await?;
+                to_upload.push((path.clone(), *hash));
                 updates.insert(path.clone(), (*hash, ChangeType::Base));
             }
 
@@ -40,16 +110,70 @@ pub async fn push() -> Result<Cid, PushError> {
         }
     }
 
+    // A new schema or directory metadata doesn't show up in the change log, so check whether
+    // the branch has it yet. A branch with nothing on it yet doesn't.
     if !changed {
-        println!("No added changes to push");
+        changed = match leaky.pull_root_cid().await {
+            Ok(head) => {
+                let head_manifest = leaky.manifest_at(&head).await?;
+                let manifest = leaky.manifest()?;
+                head_manifest.data() != manifest.data()
+                    || head_manifest.schema() != manifest.schema()
+            }
+            Err(LeakyError::LeakyApi(LeakyApiError::Api(status, _))) if status.as_u16() == 404 => {
+                true
+            }
+            Err(err) => return Err(err.into()),
+        };
+    }
+
+    if !changed {
+        eprintln!("No added changes to push");
         return Ok(root_cid);
     }
 
-    leaky.push().await?;
+    let journal = DiskJournal::open(&DiskJournal::path(), &root_cid)?;
+
+    // Upload the file data, skipping anything an earlier attempt or ipfs already has
+    let leaky_ref = &leaky;
+    let journal_ref = &journal;
+    leaky
+        .transfer()
+        .run(
+            TransferKind::PushData,
+            to_upload,
+            |(path, hash)| async move {
+                if journal_ref.is_confirmed(&hash) {
+                    return Ok(false);
+                }
+                if leaky_ref.has_block(&hash).await {
+                    journal_ref.confirm(&hash)?;
+                    return Ok(false);
+                }
+                let file = File::open(&path)?;
+                let bytes = file.metadata()?.len();
+                let cid = leaky_ref.add_data(file).await?;
+                if cid != hash {
+                    return Err(PushError::CidMismatch(cid, hash));
+                }
+                journal_ref.confirm(&cid)?;
+                leaky_ref.report(ProgressEvent::Bytes {
+                    kind: TransferKind::PushData,
+                    bytes,
+                });
+                Ok::<_, PushError>(true)
+            },
+        )
+        .await?;
+
+    leaky
+        .push_with_journal(message.as_deref(), Some(&journal))
+        .await?;
 
     let root_cid = leaky.cid()?;
 
     utils::save_on_disk(&mut leaky, &updates).await?;
+    DiskJournal::remove(&DiskJournal::path())?;
 
     Ok(root_cid)
 }
@@ -71,3 +195,58 @@ pub enum PushError {
     #[error("device error: {0}")]
     Leaky(#[from] LeakyError),
 }
+
+#[cfg(test)]
+mod test {
+    use libipld::cid::multihash::{Code, MultihashDigest};
+
+    use super::*;
+
+    fn cid(data: &[u8]) -> Cid {
+        Cid::new_v1(0x55, Code::Blake3_256.digest(data))
+    }
+
+    #[test]
+    fn resumed_push_skips_confirmed_blocks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(DEFAULT_PUSH_JOURNAL_NAME);
+        let root = cid(b"root");
+
+        let journal = DiskJournal::open(&path, &root).unwrap();
+        journal.confirm(&cid(b"a")).unwrap();
+        journal.confirm(&cid(b"b")).unwrap();
+        drop(journal);
+        // A crash part way through a line
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"bafy").unwrap();
+
+        let journal = DiskJournal::open(&path, &root).unwrap();
+        assert!(journal.is_confirmed(&cid(b"a")));
+        assert!(journal.is_confirmed(&cid(b"b")));
+        assert!(!journal.is_confirmed(&cid(b"c")));
+    }
+
+    #[test]
+    fn journal_for_another_root_is_ignored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(DEFAULT_PUSH_JOURNAL_NAME);
+
+        let journal = DiskJournal::open(&path, &cid(b"old root")).unwrap();
+        journal.confirm(&cid(b"a")).unwrap();
+        drop(journal);
+
+        let journal = DiskJournal::open(&path, &cid(b"new root")).unwrap();
+        assert!(!journal.is_confirmed(&cid(b"a")));
+        journal.confirm(&cid(b"b")).unwrap();
+        drop(journal);
+
+        // The old root's confirmations are gone for good
+        let journal = DiskJournal::open(&path, &cid(b"old root")).unwrap();
+        assert!(!journal.is_confirmed(&cid(b"a")));
+        assert!(!journal.is_confirmed(&cid(b"b")));
+
+        DiskJournal::remove(&path).unwrap();
+        assert!(!path.exists());
+        DiskJournal::remove(&path).unwrap();
+    }
+}
@@ -0,0 +1,34 @@
+use leaky_common::prelude::*;
+
+use super::utils;
+
+pub async fn create_ref(name: &str, revision: Option<String>) -> Result<Cid, RefError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let cid = match revision {
+        Some(revision) => leaky.resolve_revision(&revision).await?,
+        // Refs name pushed roots, so local changes aren't included
+        None => leaky.base()?,
+    };
+    leaky.create_ref(name, &cid).await?;
+    Ok(cid)
+}
+
+pub async fn list_refs() -> Result<Vec<(String, Cid)>, RefError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let refs = leaky.refs().await?;
+    Ok(refs)
+}
+
+pub async fn delete_ref(name: &str) -> Result<(), RefError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    leaky.delete_ref(name).await?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RefError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use leaky_common::prelude::*;
+
+use super::utils;
+
+/// The schema metadata in the bucket has to follow, if it has one
+pub async fn show_schema() -> Result<Option<Schema>, BucketSchemaError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    Ok(leaky.schema()?)
+}
+
+/// Set the bucket's schema from a JSON file, or stdin if it's `-`, or clear it if there's no
+/// file. It goes out with the next push.
+pub async fn set_schema(file: Option<PathBuf>) -> Result<Cid, BucketSchemaError> {
+    let (mut leaky, change_log) = utils::load_on_disk().await?;
+
+    let schema = match file {
+        Some(file) => Some(serde_json::from_str::<Schema>(&utils::read_input(&file)?)?),
+        None => None,
+    };
+
+    let root_cid = leaky.cid()?;
+    leaky.set_schema(schema).await?;
+    let new_root_cid = leaky.cid()?;
+    if new_root_cid == root_cid {
+        eprintln!("No changes to the schema");
+        return Ok(root_cid);
+    }
+
+    utils::save_on_disk(&mut leaky, &change_log).await?;
+    Ok(new_root_cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BucketSchemaError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse schema: {0}")]
+    Serde(#[from] serde_json::Error),
+}
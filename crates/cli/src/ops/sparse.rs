@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use leaky_common::prelude::*;
+
+use super::pull::{checkout, PullError};
+use super::utils;
+
+/// The sparse patterns of the checkout. Everything is checked out if there are none.
+pub fn list_sparse() -> Result<Vec<String>, SparseCheckoutError> {
+    let config = utils::load_config()?;
+    Ok(config.include)
+}
+
+/// Replace the sparse patterns and bring the working tree in line with them, removing files that
+/// are no longer selected and pulling newly selected ones from the current root
+pub async fn set_sparse(patterns: Vec<String>) -> Result<Cid, SparseCheckoutError> {
+    let (mut leaky, change_log) = utils::load_on_disk().await?;
+
+    // Files leaving the checkout would take their changes with them
+    if utils::has_unpushed_changes(&leaky, &change_log).await? {
+        return Err(SparseCheckoutError::UnpushedChanges);
+    }
+
+    let sparse = Sparse::new(&patterns)?;
+    let mut config = utils::load_config()?;
+    // What the old patterns checked out
+    let current_fs_tree = utils::fs_tree(
+        Path::new("."),
+        &config.ignore,
+        &Sparse::new(&config.include)?,
+        &change_log,
+    )?;
+
+    config.include = patterns;
+    utils::save_config(&config)?;
+
+    for (tree, path) in current_fs_tree.iter() {
+        if tree.is_dir() || sparse.includes_file(&path) {
+            continue;
+        }
+        // Only remove what matches the root, so edits that were never staged aren't lost
+        match change_log.get(&path) {
+            Some((cid, _)) if utils::hash_file(&path, &leaky).await? == *cid => {
+                std::fs::remove_file(&path)?;
+            }
+            _ => eprintln!(
+                "leaving {} in place: it doesn't match the bucket",
+                path.display()
+            ),
+        }
+    }
+
+    let cid = leaky.base()?;
+    checkout(&mut leaky, &cid).await?;
+    Ok(cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SparseCheckoutError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("sparse error: {0}")]
+    Sparse(#[from] SparseError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("there are changes that haven't been pushed yet")]
+    UnpushedChanges,
+}
@@ -0,0 +1,40 @@
+use leaky_common::prelude::*;
+
+use super::pull::{checkout, PullError};
+use super::utils;
+
+/// Switch to another branch, checking out its head. With `create` the branch is started at the
+/// current root instead, leaving the working tree as it is.
+pub async fn switch(name: &str, create: bool) -> Result<Cid, SwitchError> {
+    let (mut leaky, change_log) = utils::load_on_disk().await?;
+
+    // Checking out another head would clobber anything that hasn't been pushed yet
+    if utils::has_unpushed_changes(&leaky, &change_log).await? {
+        return Err(SwitchError::UnpushedChanges);
+    }
+
+    if create {
+        let cid = leaky.base()?;
+        leaky.create_branch(name, &cid).await?;
+        leaky.set_branch(name);
+        utils::save_on_disk(&mut leaky, &change_log).await?;
+        return Ok(cid);
+    }
+
+    leaky.set_branch(name);
+    let cid = leaky.pull_root_cid().await?;
+    checkout(&mut leaky, &cid).await?;
+    Ok(cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SwitchError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("there are changes that haven't been pushed yet")]
+    UnpushedChanges,
+}
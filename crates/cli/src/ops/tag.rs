@@ -1,11 +1,11 @@
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::anyhow;
 use leaky_common::prelude::*;
 use serde_json::Value;
 
 use super::change_log::ChangeType;
+use super::metadata::{value_to_metadata, MetadataError};
 use super::utils;
 
 fn clean_path(path: &PathBuf) -> PathBuf {
@@ -13,41 +13,77 @@ fn clean_path(path: &PathBuf) -> PathBuf {
     path.strip_prefix("/").unwrap().to_path_buf()
 }
 
-fn value_to_metadata(value: String) -> Result<BTreeMap<String, Ipld>, TagError> {
-    let mut metadata = BTreeMap::new();
-    let value: Value = serde_json::from_str(&value)?;
-    for (key, value) in value.as_object().unwrap() {
-        let ipld = match value {
-            Value::String(s) => Ipld::String(s.clone()),
-            Value::Number(n) => {
-                if n.is_i64() {
-                    // Read as i128
-                    let i = n.as_i64().unwrap();
-                    Ipld::Integer(i as i128)
-                } else {
-                    Ipld::Float(n.as_f64().unwrap())
-                }
-            }
-            Value::Bool(b) => Ipld::Bool(*b),
-            Value::Null => Ipld::Null,
-            _ => return Err(TagError::Default(anyhow!("unsupported type: {:?}", value))),
-        };
-        metadata.insert(key.clone(), ipld);
-    }
+/// What to set a file or directory's metadata to
+pub struct TagOptions {
+    /// Metadata as a JSON object
+    pub metadata: Option<String>,
+    /// File to read the JSON object from instead, or `-` for stdin
+    pub file: Option<PathBuf>,
+    /// Keep the keys the path already has that the new metadata doesn't set, rather than
+    /// replacing all of it
+    pub merge: bool,
+    /// Keys to remove from the path's metadata, keeping the rest
+    pub unset: Vec<String>,
+}
+
+/// Read the new metadata from wherever the options say it comes from
+fn read_metadata(options: &TagOptions) -> Result<Option<BTreeMap<String, Ipld>>, TagError> {
+    let json = match (&options.metadata, &options.file) {
+        (Some(metadata), _) => metadata.clone(),
+        (None, Some(file)) => utils::read_input(file)?,
+        (None, None) => return Ok(None),
+    };
+    let value: Value = serde_json::from_str(&json)?;
+    Ok(Some(value_to_metadata(&value)?))
+}
+
+/// The metadata the file or directory at `path` has now, which is none if it has no object yet
+async fn current_metadata(leaky: &Leaky, path: &Path) -> Result<BTreeMap<String, Ipld>, TagError> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(BTreeMap::new());
+    };
+    let entries = match leaky.ls(&parent.to_path_buf()).await {
+        Ok(entries) => entries,
+        Err(LeakyError::PathNotFound(_)) => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let metadata = entries
+        .into_iter()
+        .find(|(entry, _)| *entry == name.to_string_lossy())
+        .and_then(|(_, (_, object))| object)
+        .map(|object| object.metadata().clone())
+        .unwrap_or_default();
     Ok(metadata)
 }
 
-pub async fn tag(path: PathBuf, value: String) -> Result<Cid, TagError> {
+/// Set the metadata of the file or directory at `path`, replacing what it had unless we're
+/// merging with it or only unsetting keys
+pub async fn tag(path: PathBuf, options: TagOptions) -> Result<Cid, TagError> {
     let (mut leaky, change_log) = utils::load_on_disk().await?;
     let mut updates = change_log.clone();
 
+    let new_metadata = read_metadata(&options)?;
+    if new_metadata.is_none() && options.unset.is_empty() {
+        return Err(TagError::NoMetadata);
+    }
+    let mut metadata = match new_metadata {
+        Some(new_metadata) if !options.merge => new_metadata,
+        new_metadata => {
+            let mut metadata = current_metadata(&leaky, &path).await?;
+            metadata.extend(new_metadata.unwrap_or_default());
+            metadata
+        }
+    };
+    for key in &options.unset {
+        metadata.remove(key);
+    }
+
     let root_cid = leaky.cid()?;
-    let metadata = value_to_metadata(value)?;
     leaky.tag(&path, &metadata).await?;
     let new_root_cid = leaky.cid()?;
 
     if new_root_cid == root_cid {
-        println!("No changes to tag");
+        eprintln!("No changes to tag");
         return Ok(root_cid);
     }
 
@@ -90,4 +126,8 @@ pub enum TagError {
     PathPrefix(#[from] std::path::StripPrefixError),
     #[error("device error: {0}")]
     Leaky(#[from] LeakyError),
+    #[error("invalid metadata: {0}")]
+    Metadata(#[from] MetadataError),
+    #[error("no metadata given to set or unset")]
+    NoMetadata,
 }
@@ -1,115 +1,197 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use leaky_common::prelude::*;
 
 use fs_tree::FsTree;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use super::change_log::ChangeLog;
+use super::change_log::{ChangeLog, ChangeType};
 
 pub const DEFAULT_LOCAL_DIR: &str = ".leaky";
 pub const DEFAULT_CONFIG_NAME: &str = "leaky.conf";
+/// The block cache as a single file, from before the block store
 pub const DEFAULT_CACHE_NAME: &str = "leaky.cache";
+/// Directory in the local directory holding a file for each block
+pub const DEFAULT_BLOCKS_DIR_NAME: &str = "blocks";
 pub const DEFAULT_STATE_NAME: &str = "leaky.state";
 pub const DEFAULT_CHAGE_LOG_NAME: &str = "leaky.log";
-
-fn ser_cid(cid: &Cid) -> String {
-    format!("cid-{}", cid)
-}
-
-fn deser_cid(cid: &str) -> Cid {
-    let cid = &cid[4..];
-    Cid::try_from(cid.to_string()).unwrap()
-}
-
-fn ser_ipld(ipld: &Ipld) -> ipld_core::ipld::Ipld {
-    match ipld {
-        Ipld::Null => ipld_core::ipld::Ipld::Null,
-        Ipld::Bool(b) => ipld_core::ipld::Ipld::Bool(*b),
-        Ipld::Integer(i) => ipld_core::ipld::Ipld::Integer(*i),
-        Ipld::Float(f) => ipld_core::ipld::Ipld::Float(*f),
-        Ipld::Bytes(b) => ipld_core::ipld::Ipld::Bytes(b.clone()),
-        Ipld::String(s) => ipld_core::ipld::Ipld::String(s.clone()),
-        Ipld::List(l) => {
-            let l = l.iter().map(|i| ser_ipld(i)).collect();
-            ipld_core::ipld::Ipld::List(l)
-        }
-        Ipld::Map(m) => {
-            let m = m.iter().map(|(k, v)| {
-                // Key must be a string
-                let k = k.clone();
-                let v = ser_ipld(v);
-                (k, v)
-            });
-            let m = m.collect();
-            ipld_core::ipld::Ipld::Map(m)
-        }
-        // TODO: UPSER JANK BUT WORKS
-        Ipld::Link(cid) => ipld_core::ipld::Ipld::String(ser_cid(cid)),
-    }
-}
-
-fn deser_ipld(ipld: &ipld_core::ipld::Ipld) -> Ipld {
-    match ipld {
+pub const DEFAULT_PUSH_JOURNAL_NAME: &str = "leaky.journal";
+/// Suffix of the temporary files written on the way to replacing local state
+pub const TMP_SUFFIX: &str = ".tmp";
+/// Gitignore-syntax file listing paths to leave out of the bucket, relative to its directory
+pub const IGNORE_FILE_NAME: &str = ".leakyignore";
+
+/// Convert a block from the cache format used before the block store, where links were stored
+/// as strings prefixed `cid-`. Metadata strings that only look like that stay strings.
+fn legacy_ipld(ipld: &ipld_core::ipld::Ipld) -> Result<Ipld> {
+    let ipld = match ipld {
         ipld_core::ipld::Ipld::Null => Ipld::Null,
         ipld_core::ipld::Ipld::Bool(b) => Ipld::Bool(*b),
         ipld_core::ipld::Ipld::Integer(i) => Ipld::Integer(*i),
         ipld_core::ipld::Ipld::Float(f) => Ipld::Float(*f),
         ipld_core::ipld::Ipld::Bytes(b) => Ipld::Bytes(b.clone()),
-        // JANK as hell
         ipld_core::ipld::Ipld::String(s) => {
-            // Check if it is a link
-            if s.starts_with("cid-") {
-                Ipld::Link(deser_cid(s))
-            } else {
-                Ipld::String(s.clone())
+            match s
+                .strip_prefix("cid-")
+                .and_then(|cid| Cid::try_from(cid).ok())
+            {
+                Some(cid) => Ipld::Link(cid),
+                None => Ipld::String(s.clone()),
             }
         }
         ipld_core::ipld::Ipld::List(l) => {
-            let l = l.iter().map(|i| deser_ipld(i)).collect();
-            Ipld::List(l)
+            Ipld::List(l.iter().map(legacy_ipld).collect::<Result<_>>()?)
         }
-        ipld_core::ipld::Ipld::Map(m) => {
-            let m = m.iter().map(|(k, v)| {
-                // Key must be a string
-                let k = k.clone();
-                let v = deser_ipld(v);
-                (k, v)
-            });
-            let m = m.collect();
-            Ipld::Map(m)
-        }
-        // TODO: shouldnt ever happen
-        // ipld_core::ipld::Ipld::Link(cid) => Ipld::Link(Cid::try_from(cid.to_string()).unwrap()),
-        _ => panic!("Unexpected Ipld type"),
-    }
+        ipld_core::ipld::Ipld::Map(m) => Ipld::Map(
+            m.iter()
+                .map(|(k, v)| Ok((k.clone(), legacy_ipld(v)?)))
+                .collect::<Result<_>>()?,
+        ),
+        ipld_core::ipld::Ipld::Link(cid) => Ipld::Link(Cid::try_from(cid.to_bytes().as_slice())?),
+    };
+    Ok(ipld)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnDiskConfig {
     pub ipfs_rpc_url: Url,
     pub leaky_api_url: Url,
+    /// Gitignore-syntax patterns ignored everywhere in the checkout. `.leakyignore` files take
+    /// precedence over these.
+    #[serde(default = "default_ignore")]
+    pub ignore: Vec<String>,
+    /// Sparse patterns selecting the parts of the bucket that are checked out. Everything is
+    /// checked out if there are none.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// How many blocks or files to move at once when pushing and pulling
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Most blocks to hold in memory at once, reading the rest from the block store as they're
+    /// needed. They're all held if this isn't set.
+    #[serde(default)]
+    pub cache_capacity: Option<usize>,
+}
+
+fn default_concurrency() -> usize {
+    DEFAULT_CONCURRENCY
+}
+
+fn default_ignore() -> Vec<String> {
+    [
+        ".git/",
+        "node_modules/",
+        "target/",
+        "*.swp",
+        "*~",
+        ".DS_Store",
+    ]
+    .iter()
+    .map(|pattern| pattern.to_string())
+    .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnDiskState {
     pub cid: Cid,
+    /// The root last pulled or pushed, which the next push builds on. State written before this
+    /// was kept builds on `cid`.
+    #[serde(default)]
+    pub base: Option<Cid>,
     pub manifest: Manifest,
+    // State written before branches existed is on the default branch
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    /// Which generation of the cache and change log goes with this state. State written before
+    /// generations existed is generation 0.
+    #[serde(default)]
+    pub generation: u64,
+}
+
+fn default_branch() -> String {
+    DEFAULT_BRANCH.to_string()
 }
 
+/// Where progress from every operation goes, if anywhere. Set once at startup, since every op
+/// loads its own `Leaky`.
+static PROGRESS: OnceLock<Arc<dyn Progress>> = OnceLock::new();
+
+pub fn set_progress(progress: Arc<dyn Progress>) {
+    let _ = PROGRESS.set(progress);
+}
+
+/// Find the canonical root of the checkout, i.e. the directory holding the local directory. An
+/// explicit `repo` may name either the root or the local directory itself; otherwise we walk up
+/// from `cwd`.
+pub fn find_root(repo: Option<&Path>, cwd: &Path) -> Result<PathBuf> {
+    let Some(repo) = repo else {
+        // Walk up the directories cwd really is in, not the symlinks that led to it
+        let cwd = std::fs::canonicalize(cwd)?;
+        return cwd
+            .ancestors()
+            .find(|dir| dir.join(DEFAULT_LOCAL_DIR).is_dir())
+            .map(Path::to_path_buf)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No leaky directory found in {} or any of its parents",
+                    cwd.display()
+                )
+            });
+    };
+
+    let repo = std::fs::canonicalize(cwd.join(repo))?;
+    let root = match repo.parent() {
+        Some(parent) if repo.ends_with(DEFAULT_LOCAL_DIR) => parent.to_path_buf(),
+        _ => repo,
+    };
+    if !root.join(DEFAULT_LOCAL_DIR).is_dir() {
+        return Err(anyhow!("No leaky directory found at {}", root.display()));
+    }
+    Ok(root)
+}
+
+/// Resolve a path given on the command line, relative to `cwd`, to its `/`-rooted path in the
+/// bucket checked out at `root`. Both directories are canonicalized first, so it doesn't matter
+/// whether either was reached through a symlink.
+pub fn bucket_path(root: &Path, cwd: &Path, path: &Path) -> Result<PathBuf> {
+    let root = std::fs::canonicalize(root)?;
+    let cwd = std::fs::canonicalize(cwd)?;
+    // Resolve `.` and `..` in the rest by hand, since the path may not exist on disk
+    let mut absolute = PathBuf::new();
+    for component in cwd.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    let relative = absolute.strip_prefix(&root).map_err(|_| {
+        anyhow!(
+            "{} is outside the bucket at {}",
+            path.display(),
+            root.display()
+        )
+    })?;
+    Ok(Path::new("/").join(relative))
+}
+
+/// Set up the local directory, either for a new bucket or for an existing root `cid`. Only the
+/// parts of an existing root selected by the sparse patterns in `include` are pulled.
 pub async fn init_on_disk(
     ipfs_rpc_url: Url,
     leaky_api_url: Url,
     cid: Option<Cid>,
+    include: Vec<String>,
 ) -> Result<Leaky> {
     let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
-    let config_path = local_dir_path.join(PathBuf::from(DEFAULT_CONFIG_NAME));
-    let state_path = local_dir_path.join(PathBuf::from(DEFAULT_STATE_NAME));
-    let cache_path = local_dir_path.join(PathBuf::from(DEFAULT_CACHE_NAME));
-    let change_log_path = local_dir_path.join(PathBuf::from(DEFAULT_CHAGE_LOG_NAME));
 
     // Check whether the dir exists
     if local_dir_path.exists() {
@@ -121,132 +203,385 @@ pub async fn init_on_disk(
 
     // Initialize Leaky
     let mut leaky = Leaky::new(ipfs_rpc_url.clone(), leaky_api_url.clone())?;
+    leaky.set_progress(PROGRESS.get().cloned());
 
     if let Some(cid) = cid {
-        leaky.pull(&cid).await?;
+        leaky.pull_sparse(&cid, &Sparse::new(&include)?).await?;
     } else {
         leaky.init().await?;
     }
 
-    // Get the initial state
-    let cid = leaky.cid()?;
-    let manifest = leaky.manifest()?;
-
-    // Get the init the cache and serialize
-    let block_cache = leaky.block_cache()?;
-    let ser_block_cache: HashMap<String, ipld_core::ipld::Ipld> = block_cache
-        .iter()
-        .map(|(k, v)| (k.clone(), ser_ipld(v)))
-        .collect();
-
-    // Summarize the state
+    // Summarize the config
     let on_disk_config = OnDiskConfig {
         ipfs_rpc_url,
         leaky_api_url,
+        ignore: default_ignore(),
+        include,
+        concurrency: default_concurrency(),
+        cache_capacity: None,
     };
-    let on_disk_state = OnDiskState { cid, manifest };
 
     // Write everything to disk
     std::fs::create_dir_all(&local_dir_path)?;
-    std::fs::write(config_path, serde_json::to_string(&on_disk_config)?)?;
-    std::fs::write(state_path, serde_json::to_string(&on_disk_state)?)?;
-    let cache_file = std::fs::File::create(cache_path)?;
-    serde_ipld_dagcbor::to_writer(cache_file, &ser_block_cache)?;
-    std::fs::write(change_log_path, serde_json::to_string(&ChangeLog::new())?)?;
+    save_config(&on_disk_config)?;
+    leaky.set_block_store(block_store());
+    commit_on_disk(&leaky, &ChangeLog::new())?;
 
     Ok(leaky)
 }
 
 pub async fn load_on_disk() -> Result<(Leaky, ChangeLog)> {
     let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
-    let config_path = local_dir_path.join(PathBuf::from(DEFAULT_CONFIG_NAME));
-    let state_path = local_dir_path.join(PathBuf::from(DEFAULT_STATE_NAME));
-    let cache_path = local_dir_path.join(PathBuf::from(DEFAULT_CACHE_NAME));
-    let change_log_path = local_dir_path.join(PathBuf::from(DEFAULT_CHAGE_LOG_NAME));
 
     if !local_dir_path.exists() {
         return Err(anyhow::anyhow!("No leaky directory found"));
     }
 
-    use std::io::BufReader;
+    let config = load_config()?;
+    let state = load_state()?;
+    let block_cache = load_legacy_cache(state.generation)?;
+    let change_log = load_change_log(state.generation)?;
 
-    let config_str = std::fs::read_to_string(config_path)?;
-    let config: OnDiskConfig = serde_json::from_str(&config_str)?;
+    let mut leaky = Leaky::new(config.ipfs_rpc_url, config.leaky_api_url)?;
+    leaky.set_concurrency(config.concurrency);
+    leaky.set_progress(PROGRESS.get().cloned());
+    leaky.set_block_store(block_store());
+    leaky.set_cache_capacity(config.cache_capacity);
+    leaky.load(&state.cid, &state.manifest, block_cache).await?;
+    leaky.set_base(state.base.unwrap_or(state.cid));
+    leaky.set_branch(&state.branch);
+
+    // Check if the cid in config matches the cid in the state
+    let cid = leaky.cid()?;
+    if cid != state.cid {
+        return Err(anyhow::anyhow!("Cid in config does not match cid in state"));
+    }
+
+    Ok((leaky, change_log))
+}
+
+pub fn load_state() -> Result<OnDiskState> {
+    let state_path = PathBuf::from(DEFAULT_LOCAL_DIR).join(DEFAULT_STATE_NAME);
     let state_str = std::fs::read_to_string(state_path)?;
     let state: OnDiskState = serde_json::from_str(&state_str)?;
-    let cache_file = std::fs::File::open(cache_path)?;
+    Ok(state)
+}
+
+/// Where the blocks of the checkout are kept, and loaded from as they're needed
+pub fn block_store() -> Arc<dyn BlockStore> {
+    let blocks_path = PathBuf::from(DEFAULT_LOCAL_DIR).join(DEFAULT_BLOCKS_DIR_NAME);
+    Arc::new(FsBlockStore::new(blocks_path))
+}
+
+/// The single-file block cache a checkout saved before it had a block store, if it still has
+/// one. Its blocks move to the block store the next time the local state is saved.
+pub fn load_legacy_cache(generation: u64) -> Result<BlockCache> {
+    use std::io::BufReader;
+
+    let cache_path = generation_path(DEFAULT_CACHE_NAME, generation);
+    if !cache_path.exists() {
+        return Ok(BlockCache::default());
+    }
+    let cache_file = std::fs::File::open(&cache_path)?;
     let cache_reader = BufReader::new(cache_file);
     let ser_block_cache: HashMap<String, ipld_core::ipld::Ipld> =
         serde_ipld_dagcbor::from_reader(cache_reader)?;
 
-    let block_cache: HashMap<String, Ipld> = ser_block_cache
+    let block_cache = ser_block_cache
         .iter()
-        .map(|(k, v)| (k.clone(), deser_ipld(v)))
-        .collect();
-    let block_cache: BlockCache = BlockCache(block_cache);
+        .map(|(k, v)| Ok((k.clone(), legacy_ipld(v)?)))
+        .collect::<Result<HashMap<String, Ipld>>>()?;
+    Ok(BlockCache(block_cache))
+}
 
-    let mut leaky = Leaky::new(config.ipfs_rpc_url, config.leaky_api_url)?;
-    leaky.load(&state.cid, &state.manifest, block_cache).await?;
+/// The change log saved with a generation of the state
+pub fn load_change_log(generation: u64) -> Result<ChangeLog> {
+    let change_log_path = generation_path(DEFAULT_CHAGE_LOG_NAME, generation);
+    let change_log_str = std::fs::read_to_string(&change_log_path)
+        .map_err(|err| inconsistent(&change_log_path, err))?;
+    let change_log: ChangeLog = serde_json::from_str(&change_log_str)?;
+    Ok(change_log)
+}
 
-    // Check if the cid in config matches the cid in the state
-    let cid = leaky.cid()?;
-    if cid != state.cid {
-        return Err(anyhow::anyhow!("Cid in config does not match cid in state"));
+fn inconsistent(path: &Path, err: std::io::Error) -> anyhow::Error {
+    anyhow!(
+        "Could not read {} ({}), so the local state is inconsistent; run `leaky fsck` to repair it",
+        path.display(),
+        err
+    )
+}
+
+/// Where a generation of the cache or change log is kept. Generation 0 is what was written
+/// before there were generations.
+pub fn generation_path(name: &str, generation: u64) -> PathBuf {
+    let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
+    match generation {
+        0 => local_dir_path.join(name),
+        generation => local_dir_path.join(format!("{}.{}", name, generation)),
     }
+}
 
-    let change_log_str = std::fs::read_to_string(change_log_path)?;
-    let change_log: ChangeLog = serde_json::from_str(&change_log_str)?;
+/// Whether there's anything that hasn't been pushed: changes staged in the change log, or a
+/// schema or directory metadata set since the last pull or push, which don't show up in it
+pub async fn has_unpushed_changes(leaky: &Leaky, change_log: &ChangeLog) -> Result<bool> {
+    if change_log
+        .values()
+        .any(|(_, change)| *change != ChangeType::Base)
+    {
+        return Ok(true);
+    }
+    let base = leaky.base()?;
+    if leaky.cid()? == base {
+        return Ok(false);
+    }
+    let base_manifest = leaky.manifest_at(&base).await?;
+    let manifest = leaky.manifest()?;
+    Ok(base_manifest.data() != manifest.data() || base_manifest.schema() != manifest.schema())
+}
 
-    Ok((leaky, change_log))
+/// Read a file given on the command line, or stdin if it's `-`
+pub fn read_input(file: &Path) -> std::io::Result<String> {
+    if file == Path::new("-") {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        return Ok(input);
+    }
+    std::fs::read_to_string(file)
+}
+
+/// Load the checkout, switching to another root in memory if a revision (a CID or ref name) is
+/// given. Nothing is written back, so the working tree and local state are left alone.
+pub async fn load_revision(revision: Option<String>) -> Result<Leaky> {
+    let (mut leaky, _) = load_on_disk().await?;
+    if let Some(revision) = revision {
+        let cid = leaky.resolve_revision(&revision).await?;
+        // Only the nodes on the way to what's read are fetched
+        leaky.pull(&cid).await?;
+    }
+    Ok(leaky)
 }
 
 pub async fn save_on_disk(leaky: &mut Leaky, change_log: &ChangeLog) -> Result<()> {
     let local_dir_path = PathBuf::from(DEFAULT_LOCAL_DIR);
-    let state_path = local_dir_path.join(PathBuf::from(DEFAULT_STATE_NAME));
-    let cache_path = local_dir_path.join(PathBuf::from(DEFAULT_CACHE_NAME));
-    let change_log_path = local_dir_path.join(PathBuf::from(DEFAULT_CHAGE_LOG_NAME));
 
     if !local_dir_path.exists() {
         return Err(anyhow::anyhow!("No leaky directory found"));
     }
 
+    commit_on_disk(leaky, change_log)?;
+    Ok(())
+}
+
+/// Write the local state out as a new generation. New blocks go to the block store and the change
+/// log to a file of its own for the generation, and only once they're on disk is the state
+/// replaced to point at them, so a crash at any point leaves a whole generation to load. The
+/// previous generation is removed afterwards; `leaky fsck` cleans up any a crash leaves behind.
+pub fn commit_on_disk(leaky: &Leaky, change_log: &ChangeLog) -> Result<u64> {
+    let state_path = PathBuf::from(DEFAULT_LOCAL_DIR).join(DEFAULT_STATE_NAME);
+    let previous = if state_path.exists() {
+        Some(load_state()?.generation)
+    } else {
+        None
+    };
+    let generation = previous.map_or(1, |previous| previous + 1);
+
     let cid = leaky.cid()?;
     let manifest = leaky.manifest()?;
-    let block_cache = leaky.block_cache()?;
 
-    // Iterate over the block cache and ser_ipld
-    let ser_block_cache: HashMap<String, ipld_core::ipld::Ipld> = block_cache
-        .iter()
-        .map(|(k, v)| (k.clone(), ser_ipld(v)))
-        .collect();
-
-    let on_disk_state = OnDiskState { cid, manifest };
+    // Blocks never change, so they can go straight into the store
+    leaky.save_blocks()?;
+    write_atomic(
+        &generation_path(DEFAULT_CHAGE_LOG_NAME, generation),
+        serde_json::to_string(&change_log)?.as_bytes(),
+    )?;
+
+    let on_disk_state = OnDiskState {
+        cid,
+        base: Some(leaky.base()?),
+        manifest,
+        branch: leaky.branch().to_string(),
+        generation,
+    };
+    write_atomic(
+        &state_path,
+        serde_json::to_string(&on_disk_state)?.as_bytes(),
+    )?;
+
+    // The blocks of a single-file cache are in the store by now too
+    if let Some(previous) = previous {
+        let _ = std::fs::remove_file(generation_path(DEFAULT_CACHE_NAME, previous));
+        let _ = std::fs::remove_file(generation_path(DEFAULT_CHAGE_LOG_NAME, previous));
+    }
 
-    std::fs::write(state_path, serde_json::to_string(&on_disk_state)?)?;
-    let cache_file = std::fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(cache_path)?;
-    serde_ipld_dagcbor::to_writer(cache_file, &ser_block_cache)?;
-    std::fs::write(change_log_path, serde_json::to_string(&change_log)?)?;
+    Ok(generation)
+}
 
+/// Replace a file all at once: the data goes to a temporary file that's synced to disk and then
+/// renamed over the target, so a crash leaves either the old contents or the new ones
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("No file name in {}", path.display()))?
+        .to_os_string();
+    tmp_name.push(TMP_SUFFIX);
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+
+    // Make the rename itself durable. Not every platform lets a directory be opened to sync it,
+    // so this is best effort.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = std::fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
     Ok(())
 }
 
-pub fn fs_tree() -> Result<FsTree> {
+/// Read the working tree at `root`, leaving out the local directory, anything matched by the
+/// `ignore` patterns or a `.leakyignore`, and anything outside the sparse patterns. Like git,
+/// ignoring only hides untracked files, so anything in the `tracked` change log stays.
+pub fn fs_tree(
+    root: &Path,
+    ignore: &[String],
+    sparse: &Sparse,
+    tracked: &ChangeLog,
+) -> Result<FsTree> {
     let dot_dir = PathBuf::from(DEFAULT_LOCAL_DIR);
 
-    // Read the Fs-tree at the local directory, ignoring the local directory
-    // Read Fs-tree at dir or pwd, stripping off the local dot directory
-    match fs_tree::FsTree::read_at(".")? {
+    // Read the Fs-tree at the root, stripping off the local dot directory
+    match fs_tree::FsTree::read_at(root)? {
         FsTree::Directory(mut d) => {
             let _res = &d.remove_entry(&dot_dir);
+            let mut ignores = vec![global_ignore(ignore)?];
+            prune_ignored(root, &mut d, Path::new(""), &mut ignores, tracked, false)?;
+            if !sparse.is_full() {
+                prune_unselected(&mut d, Path::new(""), sparse);
+            }
             Ok(fs_tree::FsTree::Directory(d))
         }
         _ => Err(anyhow::anyhow!("Expected a directory")),
     }
 }
 
+pub fn load_config() -> Result<OnDiskConfig> {
+    let config_path = PathBuf::from(DEFAULT_LOCAL_DIR).join(DEFAULT_CONFIG_NAME);
+    let config_str = std::fs::read_to_string(config_path)?;
+    let config: OnDiskConfig = serde_json::from_str(&config_str)?;
+    Ok(config)
+}
+
+pub fn save_config(config: &OnDiskConfig) -> Result<()> {
+    let config_path = PathBuf::from(DEFAULT_LOCAL_DIR).join(DEFAULT_CONFIG_NAME);
+    write_atomic(&config_path, serde_json::to_string(config)?.as_bytes())?;
+    Ok(())
+}
+
+/// The sparse patterns of the checkout
+pub fn load_sparse() -> Result<Sparse> {
+    let config = load_config()?;
+    Ok(Sparse::new(&config.include)?)
+}
+
+fn global_ignore(patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(".");
+    for pattern in patterns {
+        builder.add_line(None, pattern)?;
+    }
+    Ok(builder.build()?)
+}
+
+/// Drop ignored entries from a directory of the tree at `root`, recursing into what's left.
+/// `ignores` holds the rules in effect for `dir`, from the global list down to its nearest
+/// `.leakyignore`. Tracked entries are kept, along with directories holding them; everything
+/// else in an ignored directory (`dir_ignored`) is dropped.
+fn prune_ignored(
+    root: &Path,
+    entries: &mut BTreeMap<PathBuf, FsTree>,
+    dir: &Path,
+    ignores: &mut Vec<Gitignore>,
+    tracked: &ChangeLog,
+    dir_ignored: bool,
+) -> Result<()> {
+    let has_ignore_file = entries.contains_key(Path::new(IGNORE_FILE_NAME));
+    if has_ignore_file {
+        let base = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let mut builder = GitignoreBuilder::new(base);
+        if let Some(err) = builder.add(root.join(dir).join(IGNORE_FILE_NAME)) {
+            return Err(err.into());
+        }
+        ignores.push(builder.build()?);
+    }
+
+    entries.retain(|name, tree| {
+        let path = dir.join(name);
+        (!dir_ignored && !is_ignored(ignores, &path, tree.is_dir()))
+            || is_tracked(tracked, &path, tree.is_dir())
+    });
+    for (name, tree) in entries.iter_mut() {
+        if let FsTree::Directory(children) = tree {
+            let path = dir.join(name);
+            let ignored = dir_ignored || is_ignored(ignores, &path, true);
+            prune_ignored(root, children, &path, ignores, tracked, ignored)?;
+        }
+    }
+
+    if has_ignore_file {
+        ignores.pop();
+    }
+    Ok(())
+}
+
+/// Drop entries outside the sparse patterns from a directory of the tree, recursing into the
+/// directories that are left
+fn prune_unselected(entries: &mut BTreeMap<PathBuf, FsTree>, dir: &Path, sparse: &Sparse) {
+    entries.retain(|name, tree| {
+        let path = dir.join(name);
+        if tree.is_dir() {
+            sparse.includes_dir(&path)
+        } else {
+            sparse.includes_file(&path)
+        }
+    });
+    for (name, tree) in entries.iter_mut() {
+        if let FsTree::Directory(children) = tree {
+            prune_unselected(children, &dir.join(name), sparse);
+        }
+    }
+}
+
+/// The deepest rule that matches a path decides whether it's ignored, so a nested
+/// `.leakyignore` can re-include (`!pattern`) what a parent ignored
+fn is_ignored(ignores: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .map(|ignore| ignore.matched(path, is_dir))
+        .find(|matched| !matched.is_none())
+        .map(|matched| matched.is_ignore())
+        .unwrap_or(false)
+}
+
+/// Whether a file is in the change log, or for a directory, whether anything beneath it is
+fn is_tracked(tracked: &ChangeLog, path: &Path, is_dir: bool) -> bool {
+    if !is_dir {
+        return tracked.contains_key(path);
+    }
+    tracked
+        .range(path.to_path_buf()..)
+        .next()
+        .is_some_and(|(tracked, _)| tracked.starts_with(path))
+}
+
 pub async fn hash_file(path: &PathBuf, leaky: &Leaky) -> Result<Cid> {
     if !path.exists() {
         return Err(anyhow::anyhow!("File does not exist"));
@@ -261,3 +596,246 @@ pub async fn hash_file(path: &PathBuf, leaky: &Leaky) -> Result<Cid> {
 
     Ok(cid)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A checkout at `root`, with `root/docs/drafts` and a symlink `link` to `root/docs` next to it
+    fn checkout() -> (tempfile::TempDir, PathBuf) {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("root");
+        std::fs::create_dir_all(root.join(DEFAULT_LOCAL_DIR)).unwrap();
+        std::fs::create_dir_all(root.join("docs/drafts")).unwrap();
+        std::os::unix::fs::symlink(root.join("docs"), tmp.path().join("link")).unwrap();
+        (tmp, root)
+    }
+
+    #[test]
+    fn root_is_found_above_cwd_or_named() {
+        let (tmp, root) = checkout();
+        let canonical = root.canonicalize().unwrap();
+        assert_eq!(
+            find_root(None, &root.join("docs/drafts")).unwrap(),
+            canonical
+        );
+        assert_eq!(
+            find_root(None, &tmp.path().join("link")).unwrap(),
+            canonical
+        );
+        assert_eq!(
+            find_root(Some(Path::new("root")), tmp.path()).unwrap(),
+            canonical
+        );
+        assert_eq!(
+            find_root(Some(Path::new("root/.leaky")), tmp.path()).unwrap(),
+            canonical
+        );
+        assert!(find_root(None, tmp.path()).is_err());
+        assert!(find_root(Some(Path::new("root/docs")), tmp.path()).is_err());
+    }
+
+    #[test]
+    fn paths_resolve_relative_to_cwd() {
+        let (tmp, root) = checkout();
+        let drafts = root.join("docs/drafts");
+        assert_eq!(
+            bucket_path(&root, &drafts, Path::new("new.md")).unwrap(),
+            PathBuf::from("/docs/drafts/new.md")
+        );
+        assert_eq!(
+            bucket_path(&root, &drafts, Path::new("./../../notes.txt")).unwrap(),
+            PathBuf::from("/notes.txt")
+        );
+        assert_eq!(
+            bucket_path(&root, &root, Path::new(".")).unwrap(),
+            PathBuf::from("/")
+        );
+        assert!(bucket_path(&root, &drafts, Path::new("../../../elsewhere")).is_err());
+
+        // Neither side has to be canonical
+        let link = tmp.path().join("link");
+        assert_eq!(
+            bucket_path(&root, &link, Path::new("drafts/new.md")).unwrap(),
+            PathBuf::from("/docs/drafts/new.md")
+        );
+        let root = tmp.path().join("link/..");
+        assert_eq!(
+            bucket_path(&root, &link, Path::new("readme.md")).unwrap(),
+            PathBuf::from("/docs/readme.md")
+        );
+    }
+
+    #[test]
+    fn legacy_links_are_converted_and_other_strings_kept() {
+        use ipld_core::ipld::Ipld as LegacyIpld;
+        use libipld::cid::multihash::{Code, MultihashDigest};
+
+        let cid = Cid::new_v1(0x71, Code::Blake3_256.digest(b"node"));
+        let legacy = LegacyIpld::Map(BTreeMap::from([
+            (
+                "link".to_string(),
+                LegacyIpld::String(format!("cid-{}", cid)),
+            ),
+            (
+                "metadata".to_string(),
+                LegacyIpld::List(vec![
+                    LegacyIpld::String("cid-foo".to_string()),
+                    LegacyIpld::String("plain".to_string()),
+                    LegacyIpld::Integer(1),
+                ]),
+            ),
+        ]));
+        assert_eq!(
+            legacy_ipld(&legacy).unwrap(),
+            Ipld::Map(BTreeMap::from([
+                ("link".to_string(), Ipld::Link(cid)),
+                (
+                    "metadata".to_string(),
+                    Ipld::List(vec![
+                        Ipld::String("cid-foo".to_string()),
+                        Ipld::String("plain".to_string()),
+                        Ipld::Integer(1),
+                    ]),
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn writes_replace_the_file_through_a_temporary() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(DEFAULT_STATE_NAME);
+        let tmp_path = tmp
+            .path()
+            .join(format!("{}{}", DEFAULT_STATE_NAME, TMP_SUFFIX));
+        std::fs::write(&path, "old").unwrap();
+        // Left behind by an interrupted write
+        std::fs::write(&tmp_path, "torn").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!tmp_path.exists());
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+
+        assert!(write_atomic(Path::new("/"), b"new").is_err());
+    }
+
+    fn ignore_file(base: &str, patterns: &[&str]) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(base);
+        for pattern in patterns {
+            builder.add_line(None, pattern).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn nested_ignore_files_take_precedence() {
+        let ignores = vec![
+            global_ignore(&["*.log".to_string()]).unwrap(),
+            ignore_file("logs", &["!keep.log", "*.tmp"]),
+        ];
+        assert!(is_ignored(&ignores, Path::new("debug.log"), false));
+        assert!(is_ignored(&ignores, Path::new("logs/debug.log"), false));
+        assert!(!is_ignored(&ignores, Path::new("logs/keep.log"), false));
+        assert!(is_ignored(&ignores, Path::new("logs/scratch.tmp"), false));
+        assert!(!is_ignored(&ignores, Path::new("notes.txt"), false));
+    }
+
+    #[test]
+    fn ignored_files_are_left_out_of_the_tree() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for dir in [".leaky", "target", "docs/drafts"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            ".leaky/leaky.conf",
+            "target/out",
+            "notes.txt",
+            "notes.txt.swp",
+            "docs/readme.md",
+            "docs/drafts/wip.md",
+            "docs/drafts/keep.md",
+        ] {
+            std::fs::write(root.join(file), file).unwrap();
+        }
+        std::fs::write(root.join("docs").join(IGNORE_FILE_NAME), "drafts/*\n").unwrap();
+        std::fs::write(
+            root.join("docs/drafts").join(IGNORE_FILE_NAME),
+            "!keep.md\n",
+        )
+        .unwrap();
+
+        let tree = fs_tree(
+            root,
+            &default_ignore(),
+            &Sparse::new(&[]).unwrap(),
+            &ChangeLog::new(),
+        )
+        .unwrap();
+        let paths = tree
+            .iter()
+            .filter(|(tree, _)| !tree.is_dir())
+            .map(|(_, path)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "docs/.leakyignore",
+                "docs/drafts/keep.md",
+                "docs/readme.md",
+                "notes.txt",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[tokio::test]
+    async fn staged_changes_and_new_schemas_are_unpushed() {
+        let mut leaky = Leaky::default();
+        leaky.init().await.unwrap();
+        let mut change_log = ChangeLog::new();
+        change_log.insert(PathBuf::from("kept"), (Cid::default(), ChangeType::Base));
+        assert!(!has_unpushed_changes(&leaky, &change_log).await.unwrap());
+
+        let mut staged = change_log.clone();
+        staged.insert(
+            PathBuf::from("new"),
+            (Cid::default(), ChangeType::Added { modified: true }),
+        );
+        assert!(has_unpushed_changes(&leaky, &staged).await.unwrap());
+
+        // A schema never shows up in the change log
+        leaky.set_schema(Some(Schema::default())).await.unwrap();
+        assert!(has_unpushed_changes(&leaky, &change_log).await.unwrap());
+    }
+
+    #[test]
+    fn tracked_files_stay_in_the_tree_when_ignored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+        for file in ["target/out", "target/debug/new", "notes.txt~", "scratch~"] {
+            std::fs::write(root.join(file), file).unwrap();
+        }
+        let mut tracked = ChangeLog::new();
+        for file in ["target/out", "notes.txt~"] {
+            tracked.insert(PathBuf::from(file), (Cid::default(), ChangeType::Base));
+        }
+
+        let tree = fs_tree(
+            root,
+            &default_ignore(),
+            &Sparse::new(&[]).unwrap(),
+            &tracked,
+        )
+        .unwrap();
+        let paths = tree
+            .iter()
+            .filter(|(tree, _)| !tree.is_dir())
+            .map(|(_, path)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["notes.txt~", "target/out"].map(PathBuf::from));
+    }
+}
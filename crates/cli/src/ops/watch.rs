@@ -0,0 +1,323 @@
+use std::path::Path;
+use std::time::Duration;
+
+use futures_util::stream::LocalBoxStream;
+use futures_util::StreamExt;
+use leaky_common::prelude::*;
+use notify_debouncer_mini::notify::{self, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer};
+use tokio::sync::mpsc;
+
+use super::add::{add, AddError};
+use super::pull::{pull, PullError};
+use super::push::{push, PushError};
+use super::utils;
+
+/// How long to wait before trying to follow the remote again after losing the connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+pub struct WatchOptions {
+    /// How long the filesystem has to settle before changes are staged
+    pub debounce: Duration,
+    /// Push staged changes this often, if set
+    pub push_interval: Option<Duration>,
+    /// Pull whenever the remote branch gets a new head
+    pub pull: bool,
+    /// Report each new root as a line of JSON rather than text
+    pub json: bool,
+}
+
+type RootEvents<'a> = LocalBoxStream<'a, Result<RootEvent, LeakyApiError>>;
+type FsChanges = mpsc::Receiver<DebounceEventResult>;
+
+/// Stage local changes as they happen, optionally pushing them on an interval and pulling remote
+/// changes as they land. If the remote moves on while there are local changes that haven't been
+/// pushed, watching stops with a conflict rather than picking a side.
+pub async fn watch(options: WatchOptions) -> Result<(), WatchError> {
+    let (leaky, _) = utils::load_on_disk().await?;
+    let local_dir = std::fs::canonicalize(utils::DEFAULT_LOCAL_DIR)?;
+
+    // Dropping the debouncer stops the watch, so it has to live as long as the loop
+    let (_debouncer, mut fs_rx) = watch_dir(Path::new("."), options.debounce)?;
+
+    let mut remote = if options.pull {
+        subscribe(&leaky).await
+    } else {
+        None
+    };
+    let mut reconnect = tokio::time::interval(RECONNECT_DELAY);
+    let mut push_timer =
+        tokio::time::interval(options.push_interval.unwrap_or(Duration::from_secs(60)));
+    // Don't push before anything has had a chance to change
+    push_timer.reset();
+
+    eprintln!("Watching for changes on {}", leaky.branch());
+    let mut unpushed = unpushed_changes().await?;
+
+    loop {
+        tokio::select! {
+            Some(result) = fs_rx.recv() => {
+                let events = match result {
+                    Ok(events) => events,
+                    Err(err) => {
+                        eprintln!("watch error: {}", err);
+                        continue;
+                    }
+                };
+                if !needs_staging(&events, &local_dir) {
+                    continue;
+                }
+                let cid = add().await?;
+                unpushed = unpushed_changes().await?;
+                if unpushed {
+                    report(options.json, WatchEvent::Staged, &cid);
+                }
+            }
+            _ = push_timer.tick(), if options.push_interval.is_some() && unpushed => {
+                match push(None).await {
+                    Ok(cid) => report(options.json, WatchEvent::Pushed, &cid),
+                    Err(err) if is_conflict(&err) => {
+                        let (mut leaky, _) = utils::load_on_disk().await?;
+                        let remote_cid = leaky.pull_root_cid().await?;
+                        return Err(WatchError::Conflict(leaky.cid()?, remote_cid));
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+                unpushed = false;
+            }
+            event = next_event(&mut remote) => {
+                let remote_cid = match event {
+                    Some(Ok(event)) => Some(event.cid),
+                    // Missed some events, so go and look at the head instead
+                    Some(Err(LeakyApiError::Lagged(_))) => None,
+                    Some(Err(err)) => {
+                        eprintln!("lost track of the remote: {}", err);
+                        remote = None;
+                        continue;
+                    }
+                    None => {
+                        eprintln!("lost track of the remote, reconnecting");
+                        remote = None;
+                        continue;
+                    }
+                };
+                if let Some(cid) = sync_remote(remote_cid).await? {
+                    report(options.json, WatchEvent::Pulled, &cid);
+                }
+            }
+            _ = reconnect.tick(), if options.pull && remote.is_none() => {
+                remote = subscribe(&leaky).await;
+                // Anything pushed while we weren't listening
+                if remote.is_some() {
+                    if let Some(cid) = sync_remote(None).await? {
+                        report(options.json, WatchEvent::Pulled, &cid);
+                    }
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                if unpushed {
+                    eprintln!("Stopped watching; staged changes have not been pushed");
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// How the bucket came to be at a new root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchEvent {
+    Staged,
+    Pushed,
+    Pulled,
+}
+
+impl WatchEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            WatchEvent::Staged => "staged",
+            WatchEvent::Pushed => "pushed",
+            WatchEvent::Pulled => "pulled",
+        }
+    }
+}
+
+/// Report that the bucket moved to a new root
+fn report(json: bool, event: WatchEvent, cid: &Cid) {
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "event": event.name(), "cid": cid.to_string() })
+        );
+        return;
+    }
+    match event {
+        WatchEvent::Staged => println!("Staged changes @ {}", cid),
+        WatchEvent::Pushed => println!("Pushed @ {}", cid),
+        WatchEvent::Pulled => println!("Pulled @ {}", cid),
+    }
+}
+
+/// Watch everything under `dir`, sending batches of changes once the filesystem has been quiet
+/// for `debounce`. A path shows up once in a batch however many times it changed.
+fn watch_dir(
+    dir: &Path,
+    debounce: Duration,
+) -> Result<(Debouncer<RecommendedWatcher>, FsChanges), WatchError> {
+    let (fs_tx, fs_rx) = mpsc::channel(16);
+    let mut debouncer = new_debouncer(debounce, move |result: DebounceEventResult| {
+        let _ = fs_tx.blocking_send(result);
+    })?;
+    debouncer.watcher().watch(dir, RecursiveMode::Recursive)?;
+    Ok((debouncer, fs_rx))
+}
+
+/// Whether a batch of changes touches the bucket. Our own bookkeeping under `.leaky` doesn't.
+fn needs_staging(events: &[DebouncedEvent], local_dir: &Path) -> bool {
+    events
+        .iter()
+        .any(|event| !is_internal(&event.path, local_dir))
+}
+
+async fn subscribe(leaky: &Leaky) -> Option<RootEvents<'_>> {
+    match leaky.subscribe().await {
+        Ok(events) => Some(events.boxed_local()),
+        Err(err) => {
+            eprintln!("could not follow the remote: {}", err);
+            None
+        }
+    }
+}
+
+async fn next_event(
+    remote: &mut Option<RootEvents<'_>>,
+) -> Option<Result<RootEvent, LeakyApiError>> {
+    match remote {
+        Some(events) => events.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Pull the remote head if it has moved, returning the new root. `head` is fetched from the
+/// server if it isn't already known.
+async fn sync_remote(head: Option<Cid>) -> Result<Option<Cid>, WatchError> {
+    let (mut leaky, change_log) = utils::load_on_disk().await?;
+    let remote_cid = match head {
+        Some(cid) => cid,
+        None => leaky.pull_root_cid().await?,
+    };
+    // Our own pushes come back as events too, and are already our base
+    if remote_cid == leaky.base()? {
+        return Ok(None);
+    }
+    if utils::has_unpushed_changes(&leaky, &change_log).await? {
+        return Err(WatchError::Conflict(leaky.cid()?, remote_cid));
+    }
+    let cid = pull(Some(remote_cid.to_string())).await?;
+    Ok(Some(cid))
+}
+
+/// Whether the checkout on disk has anything that hasn't been pushed
+async fn unpushed_changes() -> Result<bool, WatchError> {
+    let (leaky, change_log) = utils::load_on_disk().await?;
+    Ok(utils::has_unpushed_changes(&leaky, &change_log).await?)
+}
+
+fn is_internal(path: &Path, local_dir: &Path) -> bool {
+    path.starts_with(local_dir)
+}
+
+/// Whether a push was rejected because the branch moved on underneath it
+fn is_conflict(err: &PushError) -> bool {
+    matches!(
+        err,
+        PushError::Leaky(LeakyError::LeakyApi(LeakyApiError::Api(status, _)))
+            if status.as_u16() == 409
+    )
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WatchError {
+    #[error("default error: {0}")]
+    Default(#[from] anyhow::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("watch error: {0}")]
+    Notify(#[from] notify::Error),
+    #[error("leaky error: {0}")]
+    Leaky(#[from] LeakyError),
+    #[error("add error: {0}")]
+    Add(#[from] AddError),
+    #[error("push error: {0}")]
+    Push(#[from] PushError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("both sides changed: local changes on {0} haven't been pushed, but the remote has moved on to {1}. Keep them on a new branch with `leaky switch -c <branch>` and push, or discard them and pull")]
+    Conflict(Cid, Cid),
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use notify_debouncer_mini::DebouncedEventKind;
+
+    use super::*;
+
+    fn event(path: &str) -> DebouncedEvent {
+        DebouncedEvent {
+            path: PathBuf::from(path),
+            kind: DebouncedEventKind::Any,
+        }
+    }
+
+    #[test]
+    fn bookkeeping_alone_needs_no_staging() {
+        let local_dir = Path::new("/bucket/.leaky");
+        assert!(!needs_staging(
+            &[event("/bucket/.leaky/leaky.disk")],
+            local_dir
+        ));
+        assert!(needs_staging(
+            &[
+                event("/bucket/.leaky/leaky.disk"),
+                event("/bucket/notes.txt")
+            ],
+            local_dir
+        ));
+    }
+
+    #[tokio::test]
+    async fn bursts_of_writes_are_debounced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let local_dir = dir.join(".leaky");
+        std::fs::create_dir(&local_dir).unwrap();
+        let (_debouncer, mut changes) = watch_dir(&dir, Duration::from_millis(200)).unwrap();
+
+        let file = dir.join("notes.txt");
+        let writes = 20;
+        for i in 0..writes {
+            std::fs::write(&file, i.to_string()).unwrap();
+        }
+        let mut batches = 0;
+        while let Ok(Some(events)) =
+            tokio::time::timeout(Duration::from_secs(1), changes.recv()).await
+        {
+            let events = events.unwrap();
+            assert!(needs_staging(&events, &local_dir));
+            assert_eq!(events.iter().filter(|event| event.path == file).count(), 1);
+            batches += 1;
+        }
+        assert!(batches > 0 && batches < writes);
+
+        // Our own bookkeeping shows up, but doesn't need staging
+        std::fs::write(local_dir.join("leaky.disk"), "state").unwrap();
+        let events = tokio::time::timeout(Duration::from_secs(1), changes.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(!needs_staging(&events, &local_dir));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use leaky_common::prelude::{Progress, ProgressEvent, TransferKind, TransferProgress};
+
+/// Which bar an event belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Stage {
+    Hash,
+    Transfer(TransferKind),
+}
+
+impl Stage {
+    fn label(&self) -> &'static str {
+        match self {
+            Stage::Hash => "hashing files",
+            Stage::Transfer(TransferKind::PushNodes) => "pushing blocks",
+            Stage::Transfer(TransferKind::PullNodes) => "pulling blocks",
+            Stage::Transfer(TransferKind::PushData) => "uploading files",
+            Stage::Transfer(TransferKind::PullData) => "downloading files",
+        }
+    }
+}
+
+struct Bar {
+    bar: ProgressBar,
+    skipped: usize,
+    bytes: u64,
+}
+
+impl Bar {
+    fn update_message(&self, stage: Stage) {
+        let mut message = stage.label().to_string();
+        if self.bytes > 0 {
+            message.push_str(&format!(", {}", HumanBytes(self.bytes)));
+        }
+        if self.skipped > 0 {
+            message.push_str(&format!(", {} already there", self.skipped));
+        }
+        self.bar.set_message(message);
+    }
+}
+
+/// Draws a bar on stderr for each stage of an operation as it runs. indicatif hides them when
+/// stderr isn't a terminal, so output piped into a script stays clean.
+#[derive(Default)]
+pub struct ProgressRenderer {
+    bars: MultiProgress,
+    stages: Mutex<HashMap<Stage, Bar>>,
+}
+
+impl ProgressRenderer {
+    fn update(&self, stage: Stage, done: usize, skipped: usize, total: usize) {
+        let mut stages = self.stages.lock().unwrap();
+        // Nothing to show for a stage with nothing to do
+        if total == 0 && !stages.contains_key(&stage) {
+            return;
+        }
+
+        let bar = stages.entry(stage).or_insert_with(|| Bar {
+            bar: self.bars.add(new_bar(total)),
+            skipped: 0,
+            bytes: 0,
+        });
+        bar.skipped = skipped;
+        bar.bar.set_length(total as u64);
+        bar.bar.set_position(done as u64);
+        bar.update_message(stage);
+
+        // Finished stages leave their bar behind, and start a new one if they run again
+        if done >= total {
+            bar.bar.finish();
+            stages.remove(&stage);
+        }
+    }
+
+    fn bytes(&self, stage: Stage, bytes: u64) {
+        let mut stages = self.stages.lock().unwrap();
+        if let Some(bar) = stages.get_mut(&stage) {
+            bar.bytes += bytes;
+            bar.update_message(stage);
+        }
+    }
+}
+
+impl Progress for ProgressRenderer {
+    fn event(&self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Transfer(TransferProgress {
+                kind,
+                done,
+                skipped,
+                total,
+            }) => self.update(Stage::Transfer(kind), done, skipped, total),
+            ProgressEvent::Bytes { kind, bytes } => self.bytes(Stage::Transfer(kind), bytes),
+            ProgressEvent::Hashed { done, total } => self.update(Stage::Hash, done, 0, total),
+        }
+    }
+}
+
+fn new_bar(total: usize) -> ProgressBar {
+    let style = ProgressStyle::with_template("{msg:30} [{bar:30}] {pos}/{len} ({eta})")
+        .expect("valid progress template")
+        .progress_chars("=> ");
+    ProgressBar::new(total as u64).with_style(style)
+}
+
+#[cfg(test)]
+mod test {
+    use indicatif::ProgressDrawTarget;
+
+    use super::*;
+
+    fn renderer() -> ProgressRenderer {
+        ProgressRenderer {
+            bars: MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+            stages: Mutex::default(),
+        }
+    }
+
+    fn transfer(kind: TransferKind, done: usize, skipped: usize, total: usize) -> ProgressEvent {
+        ProgressEvent::Transfer(TransferProgress {
+            kind,
+            done,
+            skipped,
+            total,
+        })
+    }
+
+    fn bar(renderer: &ProgressRenderer, stage: Stage) -> Option<ProgressBar> {
+        let stages = renderer.stages.lock().unwrap();
+        stages.get(&stage).map(|bar| bar.bar.clone())
+    }
+
+    #[test]
+    fn stages_with_nothing_to_do_get_no_bar() {
+        let renderer = renderer();
+        renderer.event(ProgressEvent::Hashed { done: 0, total: 0 });
+        renderer.event(transfer(TransferKind::PushNodes, 0, 0, 0));
+        assert!(renderer.stages.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn bar_follows_a_transfer_until_it_finishes() {
+        let renderer = renderer();
+        let stage = Stage::Transfer(TransferKind::PushData);
+        renderer.event(transfer(TransferKind::PushData, 0, 0, 3));
+        let progress = bar(&renderer, stage).unwrap();
+        assert_eq!(progress.length(), Some(3));
+        assert_eq!(progress.position(), 0);
+        assert_eq!(progress.message(), "uploading files");
+
+        renderer.event(ProgressEvent::Bytes {
+            kind: TransferKind::PushData,
+            bytes: 2048,
+        });
+        renderer.event(transfer(TransferKind::PushData, 2, 1, 3));
+        assert_eq!(progress.position(), 2);
+        assert_eq!(
+            progress.message(),
+            "uploading files, 2.00 KiB, 1 already there"
+        );
+        assert!(!progress.is_finished());
+
+        renderer.event(transfer(TransferKind::PushData, 3, 1, 3));
+        assert!(progress.is_finished());
+        assert!(bar(&renderer, stage).is_none());
+
+        // Running the stage again starts a new bar
+        renderer.event(transfer(TransferKind::PushData, 0, 0, 1));
+        assert_eq!(bar(&renderer, stage).unwrap().message(), "uploading files");
+    }
+
+    #[test]
+    fn stages_get_their_own_bars() {
+        let renderer = renderer();
+        renderer.event(ProgressEvent::Hashed { done: 1, total: 4 });
+        renderer.event(transfer(TransferKind::PullNodes, 2, 0, 5));
+        // Bytes for a stage that isn't running are dropped
+        renderer.event(ProgressEvent::Bytes {
+            kind: TransferKind::PullData,
+            bytes: 10,
+        });
+
+        assert_eq!(bar(&renderer, Stage::Hash).unwrap().position(), 1);
+        assert_eq!(
+            bar(&renderer, Stage::Transfer(TransferKind::PullNodes))
+                .unwrap()
+                .message(),
+            "pulling blocks"
+        );
+        assert!(bar(&renderer, Stage::Transfer(TransferKind::PullData)).is_none());
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::types::Cid;
+
+/// Encoded DAG-CBOR blocks kept by CID. `Leaky` reads nodes it hasn't loaded yet from here, and
+/// saves the ones it makes or pulls to it. Blocks never change once written, so a store only
+/// grows, and a block written ahead of a crash does no harm.
+pub trait BlockStore: Send + Sync {
+    /// The encoded block, if the store has it
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, std::io::Error>;
+
+    /// Keep an encoded block. Keeping one the store already has does nothing.
+    fn put(&self, cid: &Cid, data: &[u8]) -> Result<(), std::io::Error>;
+
+    /// Drop a block, e.g. one that's been found to be corrupt
+    fn remove(&self, cid: &Cid) -> Result<(), std::io::Error>;
+
+    /// Every block the store has
+    fn cids(&self) -> Result<Vec<Cid>, std::io::Error>;
+}
+
+/// A block store keeping each block in a file of its own, named by its CID
+#[derive(Debug, Clone)]
+pub struct FsBlockStore {
+    dir: PathBuf,
+}
+
+impl FsBlockStore {
+    /// A store in `dir`, which is created when the first block is written
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, cid: &Cid) -> PathBuf {
+        self.dir.join(cid.to_string())
+    }
+}
+
+impl BlockStore for FsBlockStore {
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, std::io::Error> {
+        match std::fs::read(self.path(cid)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn put(&self, cid: &Cid, data: &[u8]) -> Result<(), std::io::Error> {
+        let path = self.path(cid);
+        if path.exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)?;
+
+        // Write to a temporary file of our own first, so a crash never leaves a partial block
+        // behind and concurrent writes of the same block don't trip over each other
+        let mut file = tempfile::Builder::new()
+            .suffix(".tmp")
+            .tempfile_in(&self.dir)?;
+        file.write_all(data)?;
+        file.as_file().sync_all()?;
+        match file.persist_noclobber(&path) {
+            // Another write got there first, and blocks never change
+            Err(err) if err.error.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
+            result => result.map(|_| ()).map_err(|err| err.error),
+        }
+    }
+
+    fn remove(&self, cid: &Cid) -> Result<(), std::io::Error> {
+        match std::fs::remove_file(self.path(cid)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn cids(&self) -> Result<Vec<Cid>, std::io::Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut cids = vec![];
+        for entry in entries {
+            // Anything that isn't named by a CID, like a leftover temporary file, isn't a block
+            if let Ok(cid) = Cid::from_str(&entry?.file_name().to_string_lossy()) {
+                cids.push(cid);
+            }
+        }
+        Ok(cids)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libipld::multihash::MultihashDigest;
+
+    use super::*;
+    use crate::types::MhCode;
+
+    #[test]
+    fn fs_block_store_round_trips_blocks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("blocks");
+        let store = FsBlockStore::new(&dir);
+        let cid = Cid::new_v1(0x71, MhCode::Blake3_256.digest(b"block"));
+
+        assert_eq!(store.get(&cid).unwrap(), None);
+        assert!(store.cids().unwrap().is_empty());
+
+        store.put(&cid, b"block").unwrap();
+        // Blocks never change, so a second write is ignored
+        store.put(&cid, b"other").unwrap();
+        std::fs::write(dir.join("leftover.tmp"), b"partial").unwrap();
+        assert_eq!(store.get(&cid).unwrap(), Some(b"block".to_vec()));
+        assert_eq!(store.cids().unwrap(), vec![cid]);
+
+        store.remove(&cid).unwrap();
+        assert_eq!(store.get(&cid).unwrap(), None);
+    }
+
+    #[test]
+    fn concurrent_writes_of_a_block_all_succeed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let store = FsBlockStore::new(tmp.path().join("blocks"));
+        let cid = Cid::new_v1(0x71, MhCode::Blake3_256.digest(b"block"));
+
+        std::thread::scope(|scope| {
+            let writes = (0..16)
+                .map(|_| scope.spawn(|| store.put(&cid, b"block")))
+                .collect::<Vec<_>>();
+            for write in writes {
+                write.join().unwrap().unwrap();
+            }
+        });
+        assert_eq!(store.get(&cid).unwrap(), Some(b"block".to_vec()));
+        // No temporary files are left behind
+        assert_eq!(store.cids().unwrap(), vec![cid]);
+        assert_eq!(std::fs::read_dir(&store.dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::leaky::BlockCache;
+use crate::types::Ipld;
+
+/// The blocks `Leaky` holds in memory, keyed by CID. Given a capacity, the least recently used
+/// blocks are evicted once there are more than that, since they can be read back from the block
+/// store or ipfs. Blocks made locally are pinned until they're saved, as nothing else has them.
+#[derive(Debug, Default)]
+pub(crate) struct Cache {
+    blocks: BlockCache,
+    capacity: Option<usize>,
+    // When each block was last used, and the blocks by when they were last used
+    tick: u64,
+    last_used: HashMap<String, u64>,
+    by_use: BTreeMap<u64, String>,
+    pinned: HashSet<String>,
+}
+
+impl Cache {
+    /// A cache holding blocks we don't know to be saved anywhere else, so they're all pinned
+    pub fn with_blocks(blocks: BlockCache, capacity: Option<usize>) -> Self {
+        let mut cache = Self {
+            capacity,
+            ..Default::default()
+        };
+        for (cid_str, ipld) in blocks.0 {
+            cache.insert(cid_str, ipld, true);
+        }
+        cache
+    }
+
+    pub fn blocks(&self) -> &BlockCache {
+        &self.blocks
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Bound the number of blocks held, or stop bounding it with `None`
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn get(&mut self, cid_str: &str) -> Option<Ipld> {
+        let ipld = self.blocks.get(cid_str).cloned()?;
+        self.touch(cid_str);
+        Some(ipld)
+    }
+
+    /// Hold a block, pinning it if it isn't saved anywhere else yet
+    pub fn insert(&mut self, cid_str: String, ipld: Ipld, pin: bool) {
+        self.touch(&cid_str);
+        if pin {
+            self.pinned.insert(cid_str.clone());
+        }
+        self.blocks.insert(cid_str, ipld);
+        self.evict();
+    }
+
+    /// Let every block be evicted, once they've all been saved
+    pub fn unpin_all(&mut self) {
+        self.pinned.clear();
+        self.evict();
+    }
+
+    fn touch(&mut self, cid_str: &str) {
+        if let Some(tick) = self.last_used.remove(cid_str) {
+            self.by_use.remove(&tick);
+        }
+        self.tick += 1;
+        self.last_used.insert(cid_str.to_string(), self.tick);
+        self.by_use.insert(self.tick, cid_str.to_string());
+    }
+
+    /// Drop the least recently used blocks that aren't pinned until we're back within capacity
+    fn evict(&mut self) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+        let over = self.blocks.len().saturating_sub(capacity);
+        if over == 0 {
+            return;
+        }
+        let evicted = self
+            .by_use
+            .values()
+            .filter(|cid_str| !self.pinned.contains(*cid_str))
+            .take(over)
+            .cloned()
+            .collect::<Vec<_>>();
+        for cid_str in evicted {
+            if let Some(tick) = self.last_used.remove(&cid_str) {
+                self.by_use.remove(&tick);
+            }
+            self.blocks.remove(&cid_str);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache_evicts_least_recently_used_unpinned_blocks() {
+        let mut cache = Cache::default();
+        cache.set_capacity(Some(2));
+        cache.insert("pinned".to_string(), Ipld::Integer(0), true);
+        cache.insert("a".to_string(), Ipld::Integer(1), false);
+        cache.insert("b".to_string(), Ipld::Integer(2), false);
+        // Pinned blocks stay however old they are, so the oldest unpinned one goes
+        assert!(cache.get("pinned").is_some());
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+
+        // Using a block keeps it around for longer
+        cache.unpin_all();
+        cache.get("pinned");
+        cache.insert("c".to_string(), Ipld::Integer(3), false);
+        assert_eq!(cache.blocks().len(), 2);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("pinned").is_some());
+        assert!(cache.get("c").is_some());
+    }
+}
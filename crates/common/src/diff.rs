@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use crate::leaky::is_dir_link;
+use crate::types::{Cid, Ipld, Node};
+
+/// Where a diff reads the nodes of the trees it compares. `Leaky` reads them through its cache;
+/// the server reads them from its ipfs node.
+pub trait NodeSource {
+    type Error;
+
+    fn node(&self, cid: &Cid) -> impl Future<Output = Result<Node, Self::Error>> + Send;
+}
+
+/// A link out of a node, along with the metadata of its object as it's stored. Directories only
+/// have an object if they were made or tagged since directories could have metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEntry {
+    pub link: Cid,
+    pub metadata: Option<Ipld>,
+}
+
+impl DiffEntry {
+    pub fn is_dir(&self) -> bool {
+        is_dir_link(&self.link)
+    }
+
+    /// Whether two entries have the same metadata. No object at all is the same as empty
+    /// metadata.
+    pub fn same_metadata(&self, other: &DiffEntry) -> bool {
+        let is_empty = |metadata: &Option<Ipld>| match metadata {
+            Some(Ipld::Map(metadata)) => metadata.is_empty(),
+            Some(_) => false,
+            None => true,
+        };
+        self.metadata == other.metadata || (is_empty(&self.metadata) && is_empty(&other.metadata))
+    }
+}
+
+/// A difference between two versions of a bucket, as found by [`diff_trees`]. Paths and CIDs are
+/// generic so the server, which has its own `cid` and keeps paths as strings, can share it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestChange<P = PathBuf, C = Cid> {
+    Added {
+        path: P,
+        cid: C,
+    },
+    Removed {
+        path: P,
+        cid: C,
+    },
+    /// The file's data changed
+    Modified {
+        path: P,
+        from: C,
+        to: C,
+    },
+    /// The file's data is the same, but its metadata changed. Directories whose own metadata
+    /// changed show up here too.
+    MetadataChanged {
+        path: P,
+        cid: C,
+    },
+    /// The file was removed from one path and added at another with the same data
+    Moved {
+        from: P,
+        to: P,
+        cid: C,
+    },
+}
+
+impl<P, C> ManifestChange<P, C> {
+    /// Where the change leaves the file. Moves report their destination.
+    pub fn path(&self) -> &P {
+        match self {
+            ManifestChange::Added { path, .. }
+            | ManifestChange::Removed { path, .. }
+            | ManifestChange::Modified { path, .. }
+            | ManifestChange::MetadataChanged { path, .. } => path,
+            ManifestChange::Moved { to, .. } => to,
+        }
+    }
+
+    /// The same change with its paths and CIDs converted, e.g. to the types an API uses
+    pub fn try_map<Q, D, E>(
+        self,
+        path: impl Fn(P) -> Q,
+        cid: impl Fn(C) -> Result<D, E>,
+    ) -> Result<ManifestChange<Q, D>, E> {
+        let change = match self {
+            ManifestChange::Added { path: p, cid: c } => ManifestChange::Added {
+                path: path(p),
+                cid: cid(c)?,
+            },
+            ManifestChange::Removed { path: p, cid: c } => ManifestChange::Removed {
+                path: path(p),
+                cid: cid(c)?,
+            },
+            ManifestChange::Modified { path: p, from, to } => ManifestChange::Modified {
+                path: path(p),
+                from: cid(from)?,
+                to: cid(to)?,
+            },
+            ManifestChange::MetadataChanged { path: p, cid: c } => {
+                ManifestChange::MetadataChanged {
+                    path: path(p),
+                    cid: cid(c)?,
+                }
+            }
+            ManifestChange::Moved { from, to, cid: c } => ManifestChange::Moved {
+                from: path(from),
+                to: path(to),
+                cid: cid(c)?,
+            },
+        };
+        Ok(change)
+    }
+}
+
+/// Walk two trees together from their data nodes, calling `visit` with the path and the entry on
+/// each side of every link that differs between them. Subtrees with the same CID on both sides
+/// are skipped, so only the parts that changed are read, and a missing side counts as an empty
+/// directory. A file on one side and a directory on the other are visited separately, each with
+/// nothing on the other side.
+pub async fn walk_diff<S, F>(
+    source: &S,
+    from: Option<Cid>,
+    to: Option<Cid>,
+    mut visit: F,
+) -> Result<(), S::Error>
+where
+    S: NodeSource,
+    F: FnMut(&Path, Option<&DiffEntry>, Option<&DiffEntry>),
+{
+    let mut stack = vec![(PathBuf::from("/"), from, to)];
+    while let Some((path, from, to)) = stack.pop() {
+        if from == to {
+            continue;
+        }
+        let from_entries = match from {
+            Some(cid) => node_entries(&source.node(&cid).await?),
+            None => BTreeMap::new(),
+        };
+        let to_entries = match to {
+            Some(cid) => node_entries(&source.node(&cid).await?),
+            None => BTreeMap::new(),
+        };
+        let names = from_entries
+            .keys()
+            .chain(to_entries.keys())
+            .collect::<BTreeSet<_>>();
+
+        for name in names {
+            let path = path.join(name);
+            let sides = match (from_entries.get(name), to_entries.get(name)) {
+                (Some(from), Some(to)) if from.is_dir() != to.is_dir() => {
+                    vec![(Some(from), None), (None, Some(to))]
+                }
+                sides => vec![sides],
+            };
+            for (from, to) in sides {
+                visit(&path, from, to);
+                let from_dir = from.filter(|from| from.is_dir()).map(|from| from.link);
+                let to_dir = to.filter(|to| to.is_dir()).map(|to| to.link);
+                if from_dir.is_some() || to_dir.is_some() {
+                    stack.push((path.clone(), from_dir, to_dir));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compare two trees path by path from their data nodes, logging every file that was added,
+/// removed, modified, moved or retagged, and every directory that was retagged
+pub async fn diff_trees<S>(
+    source: &S,
+    from: &Cid,
+    to: &Cid,
+) -> Result<Vec<ManifestChange>, S::Error>
+where
+    S: NodeSource,
+{
+    let mut changes = vec![];
+    walk_diff(source, Some(*from), Some(*to), |path, from, to| {
+        let path = path.to_path_buf();
+        match (from, to) {
+            (Some(from), Some(to)) if from.is_dir() => {
+                if !from.same_metadata(to) {
+                    changes.push(ManifestChange::MetadataChanged { path, cid: to.link });
+                }
+            }
+            (Some(from), Some(to)) => {
+                if from.link != to.link {
+                    changes.push(ManifestChange::Modified {
+                        path,
+                        from: from.link,
+                        to: to.link,
+                    });
+                } else if !from.same_metadata(to) {
+                    changes.push(ManifestChange::MetadataChanged { path, cid: to.link });
+                }
+            }
+            (Some(from), None) if !from.is_dir() => {
+                changes.push(ManifestChange::Removed {
+                    path,
+                    cid: from.link,
+                });
+            }
+            (None, Some(to)) if !to.is_dir() => {
+                changes.push(ManifestChange::Added { path, cid: to.link });
+            }
+            // Directories coming or going are only walked into
+            _ => {}
+        }
+    })
+    .await?;
+    Ok(pair_moves(changes))
+}
+
+/// The links of a node by name, along with the metadata of their objects
+fn node_entries(node: &Node) -> BTreeMap<String, DiffEntry> {
+    node.get_links()
+        .into_iter()
+        .map(|(name, link)| {
+            let metadata = node.get_object_metadata(&name).cloned();
+            (name, DiffEntry { link, metadata })
+        })
+        .collect()
+}
+
+/// Pair up files removed from one path and added at another with the same data as moves, and
+/// order the changes by path
+pub fn pair_moves<P, C>(changes: Vec<ManifestChange<P, C>>) -> Vec<ManifestChange<P, C>>
+where
+    P: Clone + Ord,
+    C: Copy + Ord,
+{
+    let mut removed: BTreeMap<C, VecDeque<P>> = BTreeMap::new();
+    for change in &changes {
+        if let ManifestChange::Removed { path, cid } = change {
+            removed.entry(*cid).or_default().push_back(path.clone());
+        }
+    }
+
+    let mut paired = vec![];
+    for change in changes {
+        match change {
+            ManifestChange::Added { path, cid } => {
+                match removed.get_mut(&cid).and_then(|paths| paths.pop_front()) {
+                    Some(from) => paired.push(ManifestChange::Moved {
+                        from,
+                        to: path,
+                        cid,
+                    }),
+                    None => paired.push(ManifestChange::Added { path, cid }),
+                }
+            }
+            ManifestChange::Removed { .. } => {}
+            change => paired.push(change),
+        }
+    }
+    for (cid, paths) in removed {
+        for path in paths {
+            paired.push(ManifestChange::Removed { path, cid });
+        }
+    }
+
+    paired.sort_by(|a, b| a.path().cmp(b.path()));
+    paired
+}
+
+#[cfg(test)]
+mod test {
+    use libipld::multihash::MultihashDigest;
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::types::{Block, DagCborCodec, DefaultParams, MhCode};
+
+    fn data_cid(data: &[u8]) -> Cid {
+        Cid::new_v1(0x55, MhCode::Blake3_256.digest(data))
+    }
+
+    #[test]
+    fn pair_moves_matches_removed_and_added_data() {
+        let moved = data_cid(b"moved");
+        let added = data_cid(b"added");
+        let removed = data_cid(b"removed");
+        let changes = vec![
+            ManifestChange::Removed {
+                path: PathBuf::from("/a/moved"),
+                cid: moved,
+            },
+            ManifestChange::Removed {
+                path: PathBuf::from("/b/removed"),
+                cid: removed,
+            },
+            ManifestChange::Added {
+                path: PathBuf::from("/c/added"),
+                cid: added,
+            },
+            ManifestChange::Added {
+                path: PathBuf::from("/d/moved"),
+                cid: moved,
+            },
+        ];
+        assert_eq!(
+            pair_moves(changes),
+            vec![
+                ManifestChange::Removed {
+                    path: PathBuf::from("/b/removed"),
+                    cid: removed,
+                },
+                ManifestChange::Added {
+                    path: PathBuf::from("/c/added"),
+                    cid: added,
+                },
+                ManifestChange::Moved {
+                    from: PathBuf::from("/a/moved"),
+                    to: PathBuf::from("/d/moved"),
+                    cid: moved,
+                },
+            ]
+        );
+    }
+
+    /// Nodes kept in memory by CID
+    #[derive(Default)]
+    struct MemorySource(HashMap<Cid, Node>);
+
+    impl MemorySource {
+        fn put(&mut self, node: Node) -> Cid {
+            let ipld = Ipld::from(node.clone());
+            let block =
+                Block::<DefaultParams>::encode(DagCborCodec, MhCode::Blake3_256, &ipld).unwrap();
+            self.0.insert(*block.cid(), node);
+            *block.cid()
+        }
+    }
+
+    impl NodeSource for MemorySource {
+        type Error = Cid;
+
+        async fn node(&self, cid: &Cid) -> Result<Node, Cid> {
+            self.0.get(cid).cloned().ok_or(*cid)
+        }
+    }
+
+    fn tag(value: i128) -> BTreeMap<String, Ipld> {
+        BTreeMap::from([("x".to_string(), Ipld::Integer(value))])
+    }
+
+    #[tokio::test]
+    async fn only_changed_paths_are_logged() {
+        let mut source = MemorySource::default();
+
+        let mut docs = Node::default();
+        docs.update_link("guide", Some(&data_cid(b"guide")), None);
+        docs.update_link("readme", Some(&data_cid(b"readme")), None);
+        let docs_from = source.put(docs.clone());
+        docs.update_link("readme", Some(&data_cid(b"readme v2")), None);
+        let docs_to = source.put(docs);
+
+        let mut swap = Node::default();
+        swap.update_link("inner", Some(&data_cid(b"inner")), None);
+        let swap = source.put(swap);
+
+        let mut from = Node::default();
+        from.update_link("a", Some(&data_cid(b"a")), Some(&tag(1)));
+        from.put_link("docs", &docs_from);
+        from.update_link("swap", Some(&data_cid(b"swap")), None);
+        let from = source.put(from);
+
+        let mut to = Node::default();
+        to.update_link("a", Some(&data_cid(b"a")), Some(&tag(2)));
+        to.put_link("docs", &docs_to);
+        to.put_link("swap", &swap);
+        let to = source.put(to);
+
+        assert_eq!(
+            diff_trees(&source, &from, &to).await.unwrap(),
+            vec![
+                ManifestChange::MetadataChanged {
+                    path: PathBuf::from("/a"),
+                    cid: data_cid(b"a"),
+                },
+                ManifestChange::Modified {
+                    path: PathBuf::from("/docs/readme"),
+                    from: data_cid(b"readme"),
+                    to: data_cid(b"readme v2"),
+                },
+                ManifestChange::Removed {
+                    path: PathBuf::from("/swap"),
+                    cid: data_cid(b"swap"),
+                },
+                ManifestChange::Added {
+                    path: PathBuf::from("/swap/inner"),
+                    cid: data_cid(b"inner"),
+                },
+            ]
+        );
+        assert!(diff_trees(&source, &to, &to).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_objects_have_empty_metadata() {
+        let entry = |metadata: Option<Ipld>| DiffEntry {
+            link: data_cid(b"file"),
+            metadata,
+        };
+        let tagged = || Ipld::Map(BTreeMap::from([("a".to_string(), Ipld::Integer(1))]));
+        assert!(entry(None).same_metadata(&entry(Some(Ipld::Map(BTreeMap::new())))));
+        assert!(!entry(None).same_metadata(&entry(Some(tagged()))));
+        assert!(entry(Some(tagged())).same_metadata(&entry(Some(tagged()))));
+    }
+}
@@ -3,7 +3,7 @@ use std::io::Read;
 use std::ops::Deref;
 use std::str::FromStr;
 
-use futures_util::TryStreamExt;
+use futures_util::{Stream, TryStreamExt};
 use http::uri::Scheme;
 use ipfs_api_backend_hyper::request::{Add as AddRequest, BlockPut as BlockPutRequest};
 use ipfs_api_backend_hyper::IpfsApi;
@@ -129,6 +129,22 @@ impl IpfsRpc {
         Ok(response)
     }
 
+    /// Stream raw data out of Ipfs a chunk at a time, rather than reading it all into memory
+    pub fn cat_data_stream(
+        &self,
+        cid: &Cid,
+    ) -> impl Stream<Item = Result<Vec<u8>, IpfsRpcError>> + Send {
+        self.cat(&cid.to_string())
+            .map_ok(|chunk| chunk.to_vec())
+            .map_err(IpfsRpcError::from)
+    }
+
+    /// Get the size in bytes of the data behind a CID, without fetching it
+    pub async fn data_size(&self, cid: &Cid) -> Result<u64, IpfsRpcError> {
+        let response = self.files_stat(&format!("/ipfs/{}", cid)).await?;
+        Ok(response.size)
+    }
+
     /// Put a block to the RPC endpoint. Provides control over the codec and multihash
     /// # Arguments
     /// * codec: the codec to use for the block
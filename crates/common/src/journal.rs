@@ -0,0 +1,12 @@
+use crate::types::Cid;
+
+/// Remembers which blocks a push has confirmed are on ipfs, so a push that dies part way through
+/// can pick up where it left off instead of checking and sending everything again. Blocks are
+/// confirmed from concurrent transfers, so implementations need to be shareable across them.
+pub trait PushJournal: Send + Sync {
+    /// Whether an earlier attempt already confirmed ipfs has the block
+    fn is_confirmed(&self, cid: &Cid) -> bool;
+
+    /// Record that ipfs has the block. This should be durable by the time it returns.
+    fn confirm(&self, cid: &Cid) -> Result<(), std::io::Error>;
+}
@@ -4,16 +4,27 @@ use std::convert::TryFrom;
 use std::io::Read;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use futures_util::stream::{self, StreamExt};
+use futures_util::{Stream, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::block_store::BlockStore;
+use crate::cache::Cache;
+use crate::diff::{diff_trees, ManifestChange, NodeSource};
 use crate::ipfs_rpc::{IpfsRpc, IpfsRpcError};
-use crate::leaky_api::{LeakyApi, LeakyApiError};
+use crate::journal::PushJournal;
+use crate::leaky_api::{LeakyApi, LeakyApiError, RootEvent};
+use crate::progress::{Progress, ProgressEvent};
+use crate::sparse::Sparse;
+use crate::transfer::{Transfer, TransferKind, TransferProgress};
 use crate::types::{
     Block, Cid, DagCborCodec, DefaultParams, Ipld, IpldCodec, Manifest, MhCode, Node, Object,
+    Schema, SchemaError,
 };
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
@@ -50,15 +61,47 @@ pub fn clean_path(path: &PathBuf) -> PathBuf {
         .collect::<PathBuf>();
 }
 
+const DAG_CBOR_CODEC: u64 = 0x71;
+
+/// Whether a link in a node is to a directory. Directories are DAG-CBOR nodes and anything else
+/// is file data, whether or not there's an object for the link.
+pub fn is_dir_link(link: &Cid) -> bool {
+    link.codec() == DAG_CBOR_CODEC
+}
+
+/// Refuse a manifest made by a newer leaky, which may lay out its data in ways this one would
+/// misread
+fn check_version(cid: &Cid, manifest: &Manifest) -> Result<(), LeakyError> {
+    let version = manifest.version();
+    if !version.is_supported() {
+        return Err(LeakyError::UnsupportedVersion(
+            *cid,
+            version.version().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Branch a bucket starts out on
+pub const DEFAULT_BRANCH: &str = "main";
+
 #[derive(Clone)]
 pub struct Leaky {
     ipfs_rpc: IpfsRpc,
     leaky_api: LeakyApi,
 
+    branch: String,
+
     cid: Option<Cid>,
+    // The root last pulled or pushed, which the next push builds on. `cid` moves away from it
+    // with every local change.
+    base: Option<Cid>,
     manifest: Option<Arc<Mutex<Manifest>>>,
-    // This should probably be an option
-    block_cache: Arc<Mutex<BlockCache>>,
+    // Blocks we've made or read, only the most recently used if it's been given a capacity
+    block_cache: Arc<Mutex<Cache>>,
+    // Where blocks that aren't in the cache are loaded from, and saved to
+    block_store: Option<Arc<dyn BlockStore>>,
+    transfer: Transfer,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -83,12 +126,36 @@ impl Leaky {
         Ok(Self {
             ipfs_rpc,
             leaky_api,
+            branch: DEFAULT_BRANCH.to_string(),
             cid: None,
+            base: None,
             manifest: None,
-            block_cache: Arc::new(Mutex::new(BlockCache::default())),
+            block_cache: Arc::new(Mutex::new(Cache::default())),
+            block_store: None,
+            transfer: Transfer::default(),
         })
     }
 
+    /// How many blocks or files to move at once when pushing and pulling
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.transfer.set_concurrency(concurrency);
+    }
+
+    /// Have pushes, pulls and the operations built on them report their progress
+    pub fn set_progress(&mut self, progress: Option<Arc<dyn Progress>>) {
+        self.transfer.set_progress(progress);
+    }
+
+    /// Pass an event on to whatever is watching progress, if anything
+    pub fn report(&self, event: ProgressEvent) {
+        self.transfer.report(event);
+    }
+
+    /// Settings for moving blocks and files, for transfers of file data made outside of `Leaky`
+    pub fn transfer(&self) -> &Transfer {
+        &self.transfer
+    }
+
     pub fn cid(&self) -> Result<Cid, LeakyError> {
         match self.cid {
             Some(cid) => Ok(cid),
@@ -96,12 +163,96 @@ impl Leaky {
         }
     }
 
+    /// The root last pulled or pushed, which the next push names as its previous root
+    pub fn base(&self) -> Result<Cid, LeakyError> {
+        self.base.ok_or(LeakyError::NoCid)
+    }
+
+    pub fn set_base(&mut self, cid: Cid) {
+        self.base = Some(cid);
+    }
+
+    /// The branch pushes and pulls of the latest root target
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    pub fn set_branch(&mut self, branch: &str) {
+        self.branch = branch.to_string();
+    }
+
     pub fn manifest(&self) -> Result<Manifest, LeakyError> {
-        Ok(self.manifest.as_ref().unwrap().lock().unwrap().to_owned())
+        let manifest = self.manifest.as_ref().ok_or(LeakyError::NoCid)?;
+        Ok(manifest.lock().unwrap().to_owned())
     }
 
+    /// The blocks held in memory. With a capacity set, that's only the most recently used ones.
     pub fn block_cache(&self) -> Result<BlockCache, LeakyError> {
-        Ok(self.block_cache.lock().unwrap().to_owned())
+        Ok(self.block_cache.lock().unwrap().blocks().to_owned())
+    }
+
+    /// Hold at most this many blocks in memory, evicting the least recently used once there are
+    /// more. Evicted blocks are read back from the block store or ipfs when they're next needed.
+    /// Blocks that haven't been saved yet are never evicted. `None`, the default, holds them all.
+    pub fn set_cache_capacity(&mut self, capacity: Option<usize>) {
+        self.block_cache.lock().unwrap().set_capacity(capacity);
+    }
+
+    /// Load blocks that aren't in the cache from a block store as they're needed, rather than
+    /// all up front, and save blocks to it with `save_blocks`
+    pub fn set_block_store(&mut self, block_store: Arc<dyn BlockStore>) {
+        self.block_store = Some(block_store);
+    }
+
+    /// Write the blocks in the cache to the block store. Only the ones we've touched are in the
+    /// cache, and the store skips any it already has. Once saved, they can be evicted.
+    pub fn save_blocks(&self) -> Result<(), LeakyError> {
+        if self.block_store.is_none() {
+            return Ok(());
+        }
+        let mut block_cache = self.block_cache.lock().unwrap();
+        for (cid_str, object) in block_cache.blocks().iter() {
+            let cid = Cid::from_str(cid_str).map_err(|_| LeakyError::Ipld)?;
+            self.store_block(&cid, object)?;
+        }
+        block_cache.unpin_all();
+        Ok(())
+    }
+
+    /// Write a block to the block store, if there is one
+    fn store_block(&self, cid: &Cid, object: &Ipld) -> Result<(), LeakyError> {
+        let Some(block_store) = &self.block_store else {
+            return Ok(());
+        };
+        let block = Block::<DefaultParams>::encode(DagCborCodec, MhCode::Blake3_256, object)
+            .map_err(|_| LeakyError::Ipld)?;
+        block_store
+            .put(cid, block.data())
+            .map_err(LeakyError::BlockStore)
+    }
+
+    /// The blocks in the block store that don't match their CIDs
+    pub fn corrupt_blocks(&self) -> Result<Vec<Cid>, LeakyError> {
+        let Some(block_store) = &self.block_store else {
+            return Ok(vec![]);
+        };
+        let mut corrupt = vec![];
+        for cid in block_store.cids().map_err(LeakyError::BlockStore)? {
+            if let Some(data) = block_store.get(&cid).map_err(LeakyError::BlockStore)? {
+                if decode_block::<Ipld>(&cid, data).is_err() {
+                    corrupt.push(cid);
+                }
+            }
+        }
+        Ok(corrupt)
+    }
+
+    /// Drop a block from the block store, so it's fetched again when it's next needed
+    pub fn remove_block(&self, cid: &Cid) -> Result<(), LeakyError> {
+        if let Some(block_store) = &self.block_store {
+            block_store.remove(cid).map_err(LeakyError::BlockStore)?;
+        }
+        Ok(())
     }
 
     /* Sync functions */
@@ -126,6 +277,8 @@ impl Leaky {
         let manifest_cid = self.put::<Manifest>(&manifest).await?;
 
         self.cid = Some(manifest_cid);
+        // A new bucket may start a branch from anywhere, so it builds on itself
+        self.base = Some(manifest_cid);
         self.manifest = Some(Arc::new(Mutex::new(manifest)));
         Ok(())
     }
@@ -136,8 +289,9 @@ impl Leaky {
         manifest: &Manifest,
         block_cache: BlockCache,
     ) -> Result<(), LeakyError> {
-        // Set the block cache
-        self.block_cache = Arc::new(Mutex::new(block_cache));
+        // Set the block cache, keeping its capacity
+        let capacity = self.block_cache.lock().unwrap().capacity();
+        self.block_cache = Arc::new(Mutex::new(Cache::with_blocks(block_cache, capacity)));
         // Set the manifest
         self.manifest = Some(Arc::new(Mutex::new(manifest.clone())));
         // Set the cid
@@ -146,45 +300,146 @@ impl Leaky {
         Ok(())
     }
 
+    /// The manifest of another root, e.g. to compare against the one loaded
+    pub async fn manifest_at(&self, cid: &Cid) -> Result<Manifest, LeakyError> {
+        self.get::<Manifest>(cid).await
+    }
+
     pub async fn pull_root_cid(&mut self) -> Result<Cid, LeakyError> {
-        let cid = self.leaky_api.pull_root().await?;
+        let cid = self.leaky_api.pull_root(&self.branch).await?;
         Ok(cid)
     }
 
+    /// Resolve a revision, either a CID or the name of a ref, to the root it points at
+    pub async fn resolve_revision(&self, revision: &str) -> Result<Cid, LeakyError> {
+        if let Ok(cid) = Cid::from_str(revision) {
+            return Ok(cid);
+        }
+        let cid = self.leaky_api.read_ref(revision).await?;
+        Ok(cid)
+    }
+
+    /// Follow new heads pushed to the current branch
+    pub async fn subscribe(
+        &self,
+    ) -> Result<impl Stream<Item = Result<RootEvent, LeakyApiError>>, LeakyError> {
+        let events = self.leaky_api.subscribe(Some(&self.branch)).await?;
+        Ok(events)
+    }
+
+    /// Start a branch on the server at an already pushed root
+    pub async fn create_branch(&self, name: &str, cid: &Cid) -> Result<(), LeakyError> {
+        self.leaky_api.create_branch(name, cid).await?;
+        Ok(())
+    }
+
+    pub async fn branches(&self) -> Result<Vec<(String, Cid)>, LeakyError> {
+        let branches = self.leaky_api.list_branches().await?;
+        Ok(branches)
+    }
+
+    pub async fn create_ref(&self, name: &str, cid: &Cid) -> Result<(), LeakyError> {
+        self.leaky_api.create_ref(name, cid).await?;
+        Ok(())
+    }
+
+    pub async fn refs(&self) -> Result<Vec<(String, Cid)>, LeakyError> {
+        let refs = self.leaky_api.list_refs().await?;
+        Ok(refs)
+    }
+
+    pub async fn delete_ref(&self, name: &str) -> Result<(), LeakyError> {
+        self.leaky_api.delete_ref(name).await?;
+        Ok(())
+    }
+
+    /// Switch to a root, only fetching its manifest. Nodes are fetched as they're needed, so
+    /// this takes the same time however big the bucket is.
     pub async fn pull(&mut self, cid: &Cid) -> Result<(), LeakyError> {
         // Try to pull the manifest from our ipfs_rpc
         let manifest = self.get::<Manifest>(cid).await?;
-        // Cool! now recurse on the data of the manifest
-        // and pull all the links into our local cache
-
-        self.pull_links(manifest.data()).await?;
+        check_version(cid, &manifest)?;
 
         // Now just update the internal state and return
         self.cid = Some(*cid);
+        self.base = Some(*cid);
+        self.manifest = Some(Arc::new(Mutex::new(manifest)));
+        Ok(())
+    }
+
+    /// Pull a root along with the nodes of every directory the sparse patterns could select,
+    /// fetching them concurrently rather than one at a time as a walk of the tree needs them
+    pub async fn pull_sparse(&mut self, cid: &Cid, sparse: &Sparse) -> Result<(), LeakyError> {
+        let manifest = self.get::<Manifest>(cid).await?;
+        check_version(cid, &manifest)?;
+        self.pull_links(manifest.data(), sparse).await?;
+
+        self.cid = Some(*cid);
+        self.base = Some(*cid);
         self.manifest = Some(Arc::new(Mutex::new(manifest)));
         Ok(())
     }
 
     // TODO: pushing should not affect the local state
     pub async fn push(&mut self) -> Result<(), LeakyError> {
-        // Iterate over the block cache and push all the blocks to ipfs_rpc
-        for (cid_str, object) in self.block_cache.lock().unwrap().iter() {
-            let cid = self.put::<Ipld>(object).await?;
-            assert_eq!(cid_str, &cid_string(&cid));
-        }
+        self.push_with_message(None).await
+    }
+
+    /// Push, passing a message along to anyone subscribed to the server's root events
+    pub async fn push_with_message(&mut self, message: Option<&str>) -> Result<(), LeakyError> {
+        self.push_with_journal(message, None).await
+    }
+
+    /// Push, skipping blocks the journal says an earlier attempt already confirmed and recording
+    /// each block as it's confirmed. The root is only published once every block is on ipfs.
+    pub async fn push_with_journal(
+        &mut self,
+        message: Option<&str>,
+        journal: Option<&dyn PushJournal>,
+    ) -> Result<(), LeakyError> {
+        // Only the nodes of the tree being pushed go, not everything ever pulled. Nodes we don't
+        // hold, like those outside a sparse checkout, came from ipfs in the first place. Blocks
+        // are only loaded again if ipfs turns out not to have them.
+        let (cids, _) = self.walk_local_nodes(&Sparse::default())?;
+
+        // Push the blocks to ipfs_rpc, skipping any it already has
+        let this = &*self;
+        this.transfer
+            .run(TransferKind::PushNodes, cids, |cid| async move {
+                if journal.is_some_and(|journal| journal.is_confirmed(&cid)) {
+                    return Ok(false);
+                }
+                let moved = !this.has_block(&cid).await;
+                if moved {
+                    let object = this.get_cache::<Ipld>(&cid).await?;
+                    let pushed = this.put::<Ipld>(&object).await?;
+                    if pushed != cid {
+                        return Err(LeakyError::CidMismatch(cid, pushed));
+                    }
+                }
+                if let Some(journal) = journal {
+                    journal.confirm(&cid).map_err(LeakyError::Journal)?;
+                }
+                Ok::<_, LeakyError>(moved)
+            })
+            .await?;
 
-        let previous_cid = self.cid()?;
+        // Local changes move the cid along, but the branch only knows the root we started from
+        let previous_cid = self.base()?;
 
         // Push the manifest to ipfs_rpc
         let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
         manifest.set_previous(previous_cid);
         let cid = self.put::<Manifest>(&manifest).await?;
 
-        // Push the cid to the leaky_api
-        self.leaky_api.push_root(&cid, &previous_cid).await?;
+        // Push the cid to the leaky_api, which rejects it if the branch has moved on
+        self.leaky_api
+            .push_root(&cid, &previous_cid, &self.branch, message)
+            .await?;
 
         // Uhh that should be it
         self.cid = Some(cid);
+        self.base = Some(cid);
         Ok(())
     }
 
@@ -197,6 +452,39 @@ impl Leaky {
 
     /* Bucket functions */
 
+    /// The schema object metadata has to follow, if the bucket has one
+    pub fn schema(&self) -> Result<Option<Schema>, LeakyError> {
+        Ok(self.manifest()?.schema().cloned())
+    }
+
+    /// Set or clear the bucket's schema. Only metadata set from now on is checked against it, so
+    /// objects that are already there keep what they have.
+    pub async fn set_schema(&mut self, schema: Option<Schema>) -> Result<(), LeakyError> {
+        let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        if manifest.schema() == schema.as_ref() {
+            return Ok(());
+        }
+        manifest.set_schema(schema);
+        let manifest_cid = self.put::<Manifest>(&manifest).await?;
+        self.cid = Some(manifest_cid);
+        Ok(())
+    }
+
+    /// Check metadata for the object at `path` against the bucket's schema
+    fn check_schema(
+        &self,
+        path: &Path,
+        metadata: &BTreeMap<String, Ipld>,
+    ) -> Result<(), LeakyError> {
+        let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
+        match manifest.schema() {
+            Some(schema) => schema
+                .validate(metadata)
+                .map_err(|err| LeakyError::Schema(path.to_path_buf(), err)),
+            None => Ok(()),
+        }
+    }
+
     pub async fn add<R>(
         &mut self,
         path: &PathBuf,
@@ -208,6 +496,9 @@ impl Leaky {
         R: Read + Send + Sync + 'static + Unpin,
     {
         let path = clean_path(path);
+        if let Some(metadata) = maybe_metadata {
+            self.check_schema(&path, metadata)?;
+        }
 
         let data_cid;
         if hash_only {
@@ -231,12 +522,23 @@ impl Leaky {
         Ok(data_cid)
     }
 
+    /// Set the metadata of the file or directory at `path`
     pub async fn tag(
         &mut self,
         path: &PathBuf,
         metadata: &BTreeMap<String, Ipld>,
     ) -> Result<(), LeakyError> {
         let path = clean_path(path);
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err(LeakyError::TagRoot),
+        };
+        // Only tag what's there, so an object never ends up without a link
+        let parent = self.get_dir(path.parent().unwrap_or(Path::new(""))).await?;
+        if parent.get_link(&name).is_none() {
+            return Err(LeakyError::PathNotFound(path));
+        }
+        self.check_schema(&path, metadata)?;
         let mut manifest = self.manifest.as_ref().unwrap().lock().unwrap();
         let data_node_cid = manifest.data();
         let maybe_new_data_node_cid = self
@@ -283,25 +585,7 @@ impl Leaky {
         &self,
         path: &PathBuf,
     ) -> Result<Vec<(String, (Cid, Option<Object>))>, LeakyError> {
-        let path = clean_path(path);
-        let data_node_cid = {
-            let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
-            let mc = manifest.clone();
-            *mc.data()
-        };
-        let mut node = self.get_cache::<Node>(&data_node_cid).await?;
-
-        // Iterate on the remaining path
-        for part in path.iter() {
-            let next = part.to_string_lossy().to_string();
-            let next_cid = node.get_link(&next).unwrap();
-            node = match self.get_cache::<Node>(&next_cid).await {
-                Ok(node) => node,
-                Err(_) => {
-                    return Err(LeakyError::PathNotDir(path));
-                }
-            }
-        }
+        let node = self.get_dir(&clean_path(path)).await?;
 
         // Get the links from the node
         let links: Vec<_> = node
@@ -318,49 +602,99 @@ impl Leaky {
 
     /// Return all the items in the bucket in order by path name
     pub async fn items(&self) -> Result<Vec<(PathBuf, Cid)>, LeakyError> {
-        let root_items = self.recursive_items(&PathBuf::from("/")).await?;
+        self.sparse_items(&Sparse::default()).await
+    }
+
+    /// Return the items in the bucket selected by the sparse patterns in order by path name
+    pub async fn sparse_items(&self, sparse: &Sparse) -> Result<Vec<(PathBuf, Cid)>, LeakyError> {
+        let root_items = self.recursive_items(&PathBuf::from("/"), sparse).await?;
         let mut sorted_items = root_items;
         sorted_items.sort_by(|a, b| a.0.cmp(&b.0));
         Ok(sorted_items)
     }
 
     pub async fn cat(&self, path: &PathBuf) -> Result<Vec<u8>, LeakyError> {
-        let path = clean_path(path);
+        let link = self.file_link(path).await?;
+        let data = self.cat_data(&link).await?;
+        Ok(data)
+    }
+
+    /// Stream a file's data out of ipfs a chunk at a time
+    pub async fn cat_stream(
+        &self,
+        path: &PathBuf,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>, LeakyError>>, LeakyError> {
+        let link = self.file_link(path).await?;
+        Ok(self
+            .ipfs_rpc
+            .cat_data_stream(&link)
+            .map_err(LeakyError::from))
+    }
+
+    /// Size in bytes of a file's data
+    pub async fn data_size(&self, cid: &Cid) -> Result<u64, LeakyError> {
+        let size = self.ipfs_rpc.data_size(cid).await?;
+        Ok(size)
+    }
+
+    /// Compare two versions of a bucket path by path. Subtrees with the same CID on both sides
+    /// are skipped, so the cost follows the size of the change rather than of the bucket.
+    pub async fn diff_manifests(
+        &self,
+        from: &Cid,
+        to: &Cid,
+    ) -> Result<Vec<ManifestChange>, LeakyError> {
+        let from_manifest = self.get::<Manifest>(from).await?;
+        let to_manifest = self.get::<Manifest>(to).await?;
+        diff_trees(self, from_manifest.data(), to_manifest.data()).await
+    }
+
+    /* Helper functions */
+
+    /// Walk down from the data node to the directory at `path`, relative to the root of the bucket
+    async fn get_dir(&self, path: &Path) -> Result<Node, LeakyError> {
         let data_node_cid = {
             let manifest = self.manifest.as_ref().unwrap().lock().unwrap();
-            let mc = manifest.clone();
-            *mc.data()
+            *manifest.data()
         };
-        let node = self.get_cache::<Node>(&data_node_cid).await?;
-        let mut node = node;
-        // Get the dir path
-        let dir_path = path
-            .iter()
-            .take(path.iter().count() - 1)
-            .collect::<PathBuf>();
-        // Get the file name
-        let file_name = path.iter().last().unwrap().to_string_lossy().to_string();
+        let mut node = self.get_cache::<Node>(&data_node_cid).await?;
 
-        // Iterate on the remaining path
-        for part in dir_path.iter() {
+        for part in path.iter() {
             let next = part.to_string_lossy().to_string();
-            let next_cid = node.get_link(&next).unwrap();
+            let next_cid = node
+                .get_link(&next)
+                .ok_or_else(|| LeakyError::PathNotFound(path.to_path_buf()))?;
+            if !is_dir_link(&next_cid) {
+                return Err(LeakyError::PathNotDir(path.to_path_buf()));
+            }
             node = self.get_cache::<Node>(&next_cid).await?;
         }
-
-        // Get the link from the node
-        let link = node.get_link(&file_name).unwrap();
-        let data = self.cat_data(&link).await?;
-
-        Ok(data)
+        Ok(node)
     }
 
-    /* Helper functions */
+    /// Find the link to the file at `path`
+    async fn file_link(&self, path: &PathBuf) -> Result<Cid, LeakyError> {
+        let path = clean_path(path);
+        let file_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err(LeakyError::PathNotFile(path)),
+        };
+        let node = self.get_dir(path.parent().unwrap_or(Path::new(""))).await?;
+        match node.get_link(&file_name) {
+            Some(link) if !is_dir_link(&link) => Ok(link),
+            Some(_) => Err(LeakyError::PathNotFile(path)),
+            None => Err(LeakyError::PathNotFound(path)),
+        }
+    }
 
     /// Recursively bubble up all the items from a path
     ///  in sorted order
     #[async_recursion::async_recursion]
-    async fn recursive_items(&self, path: &PathBuf) -> Result<Vec<(PathBuf, Cid)>, LeakyError> {
+    async fn recursive_items(
+        &self,
+        path: &PathBuf,
+        sparse: &Sparse,
+    ) -> Result<Vec<(PathBuf, Cid)>, LeakyError> {
         let mut items = vec![];
         let links = match self.ls(path).await {
             Ok(l) => l,
@@ -371,46 +705,124 @@ impl Leaky {
                 _ => return Err(err),
             },
         };
-        for (name, (_link, object)) in links {
+        for (name, (link, _)) in links {
             // If this is a directory, recurse
-            if object.is_none() {
+            if is_dir_link(&link) {
                 let mut path = path.clone();
                 path.push(name);
-                let mut next_items = self.recursive_items(&path).await?;
+                if !sparse.includes_dir(&path) {
+                    continue;
+                }
+                let mut next_items = self.recursive_items(&path, sparse).await?;
                 items.append(&mut next_items);
             } else {
                 let mut path = path.clone();
                 path.push(name);
-                items.push((path, _link));
+                if sparse.includes_file(&path) {
+                    items.push((path, link));
+                }
             }
         }
         Ok(items)
     }
 
-    #[async_recursion::async_recursion]
-    async fn pull_links(&mut self, cid: &Cid) -> Result<(), LeakyError> {
-        let node = self.get::<Node>(cid).await?;
-        self.block_cache
-            .lock()
-            .unwrap()
-            .insert(cid_string(cid), node.clone().into());
-        // Recurse from down the data node, pulling all the nodes
-        for (_name, link) in node.clone().iter() {
-            match link {
-                Ipld::Link(cid) => {
-                    // Check if this is raw data
-                    if cid.codec() == 0x55 {
-                        return Ok(());
+    /// Pull the nodes under a data node into the block cache a level of the tree at a time,
+    /// fetching each level concurrently. Nodes we already have aren't fetched again, and file
+    /// data and directories the sparse patterns leave out are skipped. A node at several paths of
+    /// a level, like identical subtrees, is only fetched once.
+    async fn pull_links(&self, cid: &Cid, sparse: &Sparse) -> Result<(), LeakyError> {
+        let mut progress = TransferProgress {
+            kind: TransferKind::PullNodes,
+            done: 0,
+            skipped: 0,
+            total: 1,
+        };
+        self.report(ProgressEvent::Transfer(progress));
+
+        let mut level = BTreeMap::from([(*cid, vec![PathBuf::from("/")])]);
+        while !level.is_empty() {
+            let mut fetches = stream::iter(level)
+                .map(|(cid, paths)| async move {
+                    let (node, skipped) = match self.get_local(&cid) {
+                        Ok(ipld) => (ipld, true),
+                        Err(LeakyError::BlockCacheMiss(_)) => {
+                            let ipld = self.fetch::<Ipld>(&cid).await?;
+                            self.store_block(&cid, &ipld)?;
+                            (ipld, false)
+                        }
+                        Err(err) => return Err(err),
                     };
-                    self.pull_links(cid).await?;
+                    let node = Node::try_from(node).map_err(|_| LeakyError::Ipld)?;
+                    Ok::<_, LeakyError>((cid, paths, node, skipped))
+                })
+                .buffer_unordered(self.transfer.concurrency());
+
+            let mut next_level: BTreeMap<Cid, Vec<PathBuf>> = BTreeMap::new();
+            while let Some(result) = fetches.next().await {
+                let (cid, paths, node, skipped) = result?;
+                self.block_cache.lock().unwrap().insert(
+                    cid_string(&cid),
+                    node.clone().into(),
+                    false,
+                );
+                for path in paths {
+                    for (name, link) in node.get_links() {
+                        let path = path.join(name);
+                        if is_dir_link(&link) && sparse.includes_dir(&path) {
+                            let paths = next_level.entry(link).or_default();
+                            if paths.is_empty() {
+                                progress.total += 1;
+                            }
+                            paths.push(path);
+                        }
+                    }
+                }
+
+                progress.done += 1;
+                if skipped {
+                    progress.skipped += 1;
                 }
-                // Just ignore anything that's not a link
-                _ => {}
+                self.report(ProgressEvent::Transfer(progress));
             }
+            level = next_level;
         }
         Ok(())
     }
 
+    /// The nodes of the loaded tree that are neither in the block cache nor the block store,
+    /// leaving out directories the sparse patterns don't select
+    pub async fn missing_nodes(&self, sparse: &Sparse) -> Result<Vec<Cid>, LeakyError> {
+        let (_, missing) = self.walk_local_nodes(sparse)?;
+        Ok(missing)
+    }
+
+    /// Walk the loaded tree through the nodes held locally, returning those it found and those
+    /// it didn't. Directories the sparse patterns don't select aren't descended into.
+    fn walk_local_nodes(&self, sparse: &Sparse) -> Result<(Vec<Cid>, Vec<Cid>), LeakyError> {
+        let data = *self.manifest()?.data();
+        let mut found = vec![];
+        let mut missing = vec![];
+        let mut stack = vec![(data, PathBuf::from("/"))];
+        while let Some((cid, path)) = stack.pop() {
+            let node = match self.get_local(&cid) {
+                Ok(ipld) => Node::try_from(ipld).map_err(|_| LeakyError::Ipld)?,
+                Err(LeakyError::BlockCacheMiss(_)) => {
+                    missing.push(cid);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            found.push(cid);
+            for (name, link) in node.get_links() {
+                let path = path.join(name);
+                if is_dir_link(&link) && sparse.includes_dir(&path) {
+                    stack.push((link, path));
+                }
+            }
+        }
+        Ok((found, missing))
+    }
+
     // TODO: this doesn't percolate deleted directories back up
     #[async_recursion::async_recursion]
     async fn upsert_link_and_object(
@@ -460,17 +872,15 @@ impl Leaky {
                 // Get the next part of the path
                 let remaining = path.iter().skip(1).collect::<PathBuf>();
                 // Determine if the next part of the path exists within the tree
-                let next_cid = if let Some(next_cid) = node.get_link(&next) {
-                    next_cid
+                let (next_cid, is_new) = if let Some(next_cid) = node.get_link(&next) {
+                    (next_cid, false)
                 } else if !is_rm {
                     // Ok create a new node to hold this part of the path
                     let new_node = Node::default();
-                    self.put_cache::<Node>(&new_node).await?
+                    (self.put_cache::<Node>(&new_node).await?, true)
                 } else {
-                    println!("nwp");
                     return Ok(None);
                 };
-                println!("next_cid: {}", next_cid);
                 // Upsert the remaining path components into the node
                 let maybe_cid = &self
                     .upsert_link_and_object(&next_cid, &remaining, maybe_link, maybe_metadata)
@@ -488,6 +898,12 @@ impl Leaky {
                     }
                 } else {
                     node.put_link(&next, cid);
+                    // New directories get an object of their own, so they have timestamps and
+                    // can be tagged like files. Its updated_at moves whenever anything beneath
+                    // the directory changes. Directories from before they had objects go without.
+                    if is_new || node.get_object(&next).is_some() {
+                        node.put_object(&next, None);
+                    }
                 }
                 let cid = self.put_cache::<Node>(&node).await?;
                 Ok(Some(cid))
@@ -513,6 +929,13 @@ impl Leaky {
         Ok(cid)
     }
 
+    /// Whether ipfs already has a block or file pinned. Only the pin on `cid` itself is checked.
+    /// File data is added with a recursive pin, so a pinned file root means ipfs holds every
+    /// block of the file. Failing to check counts as not having it, so it gets sent anyway.
+    pub async fn has_block(&self, cid: &Cid) -> bool {
+        self.ipfs_rpc.has_block(cid).await.unwrap_or(false)
+    }
+
     async fn cat_data(&self, cid: &Cid) -> Result<Vec<u8>, LeakyError> {
         let data = self.ipfs_rpc.cat_data(cid).await?;
         Ok(data)
@@ -523,10 +946,17 @@ impl Leaky {
         B: TryFrom<Ipld>,
     {
         let data = self.ipfs_rpc.get_block_send_safe(cid).await?;
-        let block = Block::<DefaultParams>::new(*cid, data).unwrap();
-        let ipld = block.decode::<DagCborCodec, Ipld>().unwrap();
-        let object = B::try_from(ipld).map_err(|_| LeakyError::Ipld)?;
-        Ok(object)
+        decode_block(cid, data)
+    }
+
+    /// Like `get`, but without the blocking task that keeps `get` Send. Pulls fetch every node
+    /// this way, since a task per block is too costly there.
+    async fn fetch<B>(&self, cid: &Cid) -> Result<B, LeakyError>
+    where
+        B: TryFrom<Ipld>,
+    {
+        let data = self.ipfs_rpc.get_block(cid).await?;
+        decode_block(cid, data)
     }
 
     async fn put<B>(&self, object: &B) -> Result<Cid, LeakyError>
@@ -544,21 +974,50 @@ impl Leaky {
         Ok(cid)
     }
 
+    /// Read a block through the cache. Blocks that aren't in it are read from the block store,
+    /// and failing that fetched from ipfs and saved to the block store, so only what's used is
+    /// ever loaded.
     async fn get_cache<B>(&self, cid: &Cid) -> Result<B, LeakyError>
     where
         B: TryFrom<Ipld> + Send,
     {
-        let block_cache = self.block_cache.lock().unwrap();
-        let cid_str = cid_string(cid);
-        let ipld = match block_cache.get(&cid_str) {
-            Some(i) => i,
-            None => return Err(LeakyError::BlockCacheMiss(*cid)),
+        let ipld = match self.get_local(cid) {
+            Err(LeakyError::BlockCacheMiss(_)) => {
+                let ipld = self.get::<Ipld>(cid).await?;
+                self.store_block(cid, &ipld)?;
+                self.block_cache
+                    .lock()
+                    .unwrap()
+                    .insert(cid_string(cid), ipld.clone(), false);
+                ipld
+            }
+            result => result?,
         };
-        let object = B::try_from(ipld.clone()).map_err(|_| LeakyError::Ipld)?;
+        let object = B::try_from(ipld).map_err(|_| LeakyError::Ipld)?;
 
         Ok(object)
     }
 
+    /// Read a block from the cache or the block store, without going to ipfs
+    fn get_local(&self, cid: &Cid) -> Result<Ipld, LeakyError> {
+        let mut block_cache = self.block_cache.lock().unwrap();
+        let cid_str = cid_string(cid);
+        if let Some(ipld) = block_cache.get(&cid_str) {
+            return Ok(ipld);
+        }
+        // Fall back to the block store, keeping what we find in the cache
+        let data = match &self.block_store {
+            Some(block_store) => block_store.get(cid).map_err(LeakyError::BlockStore)?,
+            None => None,
+        };
+        let Some(data) = data else {
+            return Err(LeakyError::BlockCacheMiss(*cid));
+        };
+        let ipld = decode_block::<Ipld>(cid, data)?;
+        block_cache.insert(cid_str, ipld.clone(), false);
+        Ok(ipld)
+    }
+
     async fn put_cache<B>(&self, object: &B) -> Result<Cid, LeakyError>
     where
         B: Into<Ipld> + Clone,
@@ -571,14 +1030,35 @@ impl Leaky {
         .unwrap();
         let cid = block.cid();
 
+        // Nothing else has the block until it's saved or pushed, so it mustn't be evicted
         self.block_cache
             .lock()
             .unwrap()
-            .insert(cid_string(cid), object.clone().into());
+            .insert(cid_string(cid), object.clone().into(), true);
         Ok(*cid)
     }
 }
 
+impl NodeSource for Leaky {
+    type Error = LeakyError;
+
+    /// Nodes are read through the cache, so a diff only fetches what it hasn't seen
+    async fn node(&self, cid: &Cid) -> Result<Node, LeakyError> {
+        self.get_cache::<Node>(cid).await
+    }
+}
+
+fn decode_block<B>(cid: &Cid, data: Vec<u8>) -> Result<B, LeakyError>
+where
+    B: TryFrom<Ipld>,
+{
+    let block = Block::<DefaultParams>::new(*cid, data).map_err(|_| LeakyError::Ipld)?;
+    let ipld = block
+        .decode::<DagCborCodec, Ipld>()
+        .map_err(|_| LeakyError::Ipld)?;
+    B::try_from(ipld).map_err(|_| LeakyError::Ipld)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum LeakyError {
     #[error("block cache miss: {0}")]
@@ -593,15 +1073,43 @@ pub enum LeakyError {
     Ipld,
     #[error("cid is not set")]
     NoCid,
+    #[error("block {0} came back from ipfs as {1}")]
+    CidMismatch(Cid, Cid),
     #[error("path is not directory: {0}")]
     PathNotDir(PathBuf),
     #[error("path is not file: {0}")]
     PathNotFile(PathBuf),
+    #[error("path not found: {0}")]
+    PathNotFound(PathBuf),
+    #[error("the root of the bucket can't be tagged")]
+    TagRoot,
+    #[error("push journal error: {0}")]
+    Journal(std::io::Error),
+    #[error("block store error: {0}")]
+    BlockStore(std::io::Error),
+    #[error("metadata for {0} doesn't follow the bucket's schema: {1}")]
+    Schema(PathBuf, SchemaError),
+    #[error("{0} was made by leaky {1}, which is newer than this one; upgrade to read it")]
+    UnsupportedVersion(Cid, String),
 }
 
 #[cfg(test)]
 mod test {
+    use libipld::multihash::MultihashDigest;
+
     use super::*;
+    use crate::types::FieldType;
+
+    fn data_cid(data: &[u8]) -> Cid {
+        Cid::new_v1(0x55, MhCode::Blake3_256.digest(data))
+    }
+
+    #[test]
+    fn dir_links_are_nodes() {
+        let node = Cid::new_v1(DAG_CBOR_CODEC, MhCode::Blake3_256.digest(b"node"));
+        assert!(is_dir_link(&node));
+        assert!(!is_dir_link(&data_cid(b"file")));
+    }
 
     async fn empty_leaky_cid() -> Cid {
         let mut leaky = Leaky::default();
@@ -654,7 +1162,7 @@ mod test {
             .add(&PathBuf::from("/bar"), data, None, false)
             .await
             .unwrap();
-        let get_data = leaky.cat(PathBuf::from("/bar")).await.unwrap();
+        let get_data = leaky.cat(&PathBuf::from("/bar")).await.unwrap();
         assert_eq!(data, get_data);
     }
 
@@ -668,10 +1176,79 @@ mod test {
             .add(&PathBuf::from("/bar"), data, None, true)
             .await
             .unwrap();
-        let links = leaky.ls(PathBuf::from("/")).await.unwrap();
+        let links = leaky.ls(&PathBuf::from("/")).await.unwrap();
         assert_eq!(links.len(), 1);
     }
 
+    #[tokio::test]
+    async fn ls_missing_and_file_paths() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let data = "foo".as_bytes();
+        leaky
+            .add(&PathBuf::from("/foo/bar"), data, None, true)
+            .await
+            .unwrap();
+
+        assert_eq!(leaky.ls(&PathBuf::from("/foo")).await.unwrap().len(), 1);
+        assert!(matches!(
+            leaky.ls(&PathBuf::from("/buzz")).await,
+            Err(LeakyError::PathNotFound(_))
+        ));
+        assert!(matches!(
+            leaky.ls(&PathBuf::from("/foo/bar")).await,
+            Err(LeakyError::PathNotDir(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn cat_missing_and_dir_paths() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let data = "foo".as_bytes();
+        leaky
+            .add(&PathBuf::from("/foo/bar"), data, None, false)
+            .await
+            .unwrap();
+
+        let chunks: Vec<Vec<u8>> = leaky
+            .cat_stream(&PathBuf::from("/foo/bar"))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), data);
+
+        for path in ["/foo/buzz", "/buzz/bar"] {
+            assert!(matches!(
+                leaky.cat(&PathBuf::from(path)).await,
+                Err(LeakyError::PathNotFound(_))
+            ));
+            assert!(matches!(
+                leaky.cat_stream(&PathBuf::from(path)).await,
+                Err(LeakyError::PathNotFound(_))
+            ));
+        }
+        for path in ["/", "/foo"] {
+            assert!(matches!(
+                leaky.cat(&PathBuf::from(path)).await,
+                Err(LeakyError::PathNotFile(_))
+            ));
+            assert!(matches!(
+                leaky.cat_stream(&PathBuf::from(path)).await,
+                Err(LeakyError::PathNotFile(_))
+            ));
+        }
+        // A file in the middle of the path can't be walked through
+        assert!(matches!(
+            leaky.cat(&PathBuf::from("/foo/bar/buzz")).await,
+            Err(LeakyError::PathNotDir(_))
+        ));
+    }
+
     #[tokio::test]
     async fn add_deep() {
         let cid = empty_leaky_cid().await;
@@ -684,6 +1261,171 @@ mod test {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn tag_dir() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let data = "foo".as_bytes();
+        leaky
+            .add(&PathBuf::from("/foo/bar"), data, None, true)
+            .await
+            .unwrap();
+        let mut metadata = BTreeMap::new();
+        metadata.insert("foo".to_string(), Ipld::String("bar".to_string()));
+        leaky.tag(&PathBuf::from("/foo"), &metadata).await.unwrap();
+
+        let links = leaky.ls(&PathBuf::from("/")).await.unwrap();
+        let (_, (link, object)) = &links[0];
+        assert!(is_dir_link(link));
+        assert_eq!(object.as_ref().unwrap().metadata(), &metadata);
+
+        assert!(matches!(
+            leaky.tag(&PathBuf::from("/"), &metadata).await,
+            Err(LeakyError::TagRoot)
+        ));
+        assert!(matches!(
+            leaky.tag(&PathBuf::from("/buzz"), &metadata).await,
+            Err(LeakyError::PathNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn new_dirs_get_objects() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let data = "foo".as_bytes();
+        leaky
+            .add(&PathBuf::from("/foo/bar/buzz"), data, None, true)
+            .await
+            .unwrap();
+
+        let links = leaky.ls(&PathBuf::from("/foo")).await.unwrap();
+        let (name, (link, object)) = &links[0];
+        assert_eq!(name, "bar");
+        assert!(is_dir_link(link));
+        let object = object.as_ref().unwrap();
+        assert!(object.metadata().is_empty());
+
+        // Changing what's in a directory moves its updated_at along
+        let data = "bang".as_bytes();
+        leaky
+            .add(&PathBuf::from("/foo/bar/bug"), data, None, true)
+            .await
+            .unwrap();
+        let links = leaky.ls(&PathBuf::from("/foo")).await.unwrap();
+        let (_, (_, updated)) = &links[0];
+        let updated = updated.as_ref().unwrap();
+        assert_eq!(updated.created_at(), object.created_at());
+        assert!(updated.updated_at() > object.updated_at());
+    }
+
+    #[tokio::test]
+    async fn tagged_dirs_stay_dirs() {
+        let cid = empty_leaky_cid().await;
+        let mut leaky = Leaky::default();
+        leaky.pull(&cid).await.unwrap();
+        let data = "foo".as_bytes();
+        leaky
+            .add(&PathBuf::from("/foo/bar"), data, None, true)
+            .await
+            .unwrap();
+        leaky.push().await.unwrap();
+        let from = leaky.cid().unwrap();
+
+        let mut metadata = BTreeMap::new();
+        metadata.insert("foo".to_string(), Ipld::String("bar".to_string()));
+        leaky.tag(&PathBuf::from("/foo"), &metadata).await.unwrap();
+        leaky.push().await.unwrap();
+        let to = leaky.cid().unwrap();
+
+        let links = leaky.ls(&PathBuf::from("/")).await.unwrap();
+        assert_eq!(links.len(), 1);
+        assert!(is_dir_link(&links[0].1 .0));
+        assert_eq!(leaky.ls(&PathBuf::from("/foo")).await.unwrap().len(), 1);
+
+        let items = leaky.items().await.unwrap();
+        assert_eq!(
+            items.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            vec![&PathBuf::from("/foo/bar")]
+        );
+
+        let changes = leaky.diff_manifests(&from, &to).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        match &changes[0] {
+            ManifestChange::MetadataChanged { path, cid } => {
+                assert_eq!(path, Path::new("/foo"));
+                assert!(is_dir_link(cid));
+            }
+            change => panic!("unexpected change {:?}", change),
+        }
+    }
+
+    #[tokio::test]
+    async fn push_add_push() {
+        let mut leaky = Leaky::default();
+        // A branch of its own, so the pushes build on nothing but each other
+        leaky.set_branch(&format!("push-add-push-{}", rand::random::<u32>()));
+        leaky.init().await.unwrap();
+        leaky.push().await.unwrap();
+        let first = leaky.cid().unwrap();
+        assert_eq!(leaky.base().unwrap(), first);
+
+        let data = "foo".as_bytes();
+        leaky
+            .add(&PathBuf::from("/foo"), data, None, true)
+            .await
+            .unwrap();
+        assert_ne!(leaky.cid().unwrap(), first);
+        assert_eq!(leaky.base().unwrap(), first);
+
+        leaky.push().await.unwrap();
+        let second = leaky.cid().unwrap();
+        assert_eq!(leaky.base().unwrap(), second);
+        assert_eq!(leaky.pull_root_cid().await.unwrap(), second);
+        let manifest = leaky.manifest_at(&second).await.unwrap();
+        assert_eq!(manifest.previous(), &first);
+    }
+
+    #[tokio::test]
+    async fn push_that_drops_the_schema_is_checked_against_the_head() {
+        let mut leaky = Leaky::default();
+        leaky.set_branch(&format!("drop-schema-{}", rand::random::<u32>()));
+        leaky.init().await.unwrap();
+        let schema = Schema {
+            fields: [("author".to_string(), FieldType::String)]
+                .into_iter()
+                .collect(),
+            open: false,
+        };
+        leaky.set_schema(Some(schema)).await.unwrap();
+        leaky.push().await.unwrap();
+
+        // Without a schema the client lets anything through, but the server doesn't
+        leaky.set_schema(None).await.unwrap();
+        let metadata = [("autor".to_string(), Ipld::String("al".to_string()))]
+            .into_iter()
+            .collect();
+        leaky
+            .add(
+                &PathBuf::from("/foo"),
+                "foo".as_bytes(),
+                Some(&metadata),
+                true,
+            )
+            .await
+            .unwrap();
+        let err = leaky.push().await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                LeakyError::LeakyApi(LeakyApiError::Api(status, _)) if status.as_u16() == 422
+            ),
+            "{err}"
+        );
+    }
+
     #[tokio::test]
     async fn add_rm() {
         let cid = empty_leaky_cid().await;
@@ -711,7 +1453,7 @@ mod test {
         let cid = leaky.cid().unwrap();
         let mut leaky = Leaky::default();
         leaky.pull(&cid).await.unwrap();
-        assert_eq!(leaky.ls(PathBuf::from("/")).await.unwrap().len(), 1);
+        assert_eq!(leaky.ls(&PathBuf::from("/")).await.unwrap().len(), 1);
     }
 
     #[tokio::test]
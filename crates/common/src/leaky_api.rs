@@ -1,5 +1,6 @@
 use std::str::FromStr;
 
+use futures_util::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
@@ -38,19 +39,66 @@ impl TryFrom<Url> for LeakyApi {
 pub struct PushRootRequest {
     cid: String,
     previous_cid: String,
+    branch: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PullRootResponse {
+    branch: String,
+    cid: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RootEventResponse {
+    branch: String,
+    cid: String,
+    previous_cid: String,
+    message: Option<String>,
+}
+
+/// A new head pushed to a branch on the server
+#[derive(Debug, Clone)]
+pub struct RootEvent {
+    pub branch: String,
+    pub cid: Cid,
+    pub previous_cid: Cid,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateBranchRequest {
+    name: String,
+    cid: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateRefRequest {
+    name: String,
+    cid: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefResponse {
+    name: String,
     cid: String,
 }
 
 impl LeakyApi {
-    pub async fn push_root(&self, cid: &Cid, previous_cid: &Cid) -> Result<(), LeakyApiError> {
+    pub async fn push_root(
+        &self,
+        cid: &Cid,
+        previous_cid: &Cid,
+        branch: &str,
+        message: Option<&str>,
+    ) -> Result<(), LeakyApiError> {
         let url = self.base_url.join("api/v0/root")?;
         let body = serde_json::to_string(&PushRootRequest {
             cid: cid.to_string(),
             previous_cid: previous_cid.to_string(),
+            branch: branch.to_string(),
+            message: message.map(String::from),
         })?;
         // push_root
         let response = self.client.post(url).body(body).send().await?;
@@ -63,15 +111,230 @@ impl LeakyApi {
         Ok(())
     }
 
-    pub async fn pull_root(&self) -> Result<Cid, LeakyApiError> {
+    pub async fn pull_root(&self, branch: &str) -> Result<Cid, LeakyApiError> {
         let url = self.base_url.join("api/v0/root")?;
-        let response = self.client.get(url).send().await?;
+        let response = self
+            .client
+            .get(url)
+            .query(&[("branch", branch)])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
         let response = response.text().await?;
         let response: PullRootResponse = serde_json::from_str(&response)?;
-        Ok(Cid::from_str(&response.cid).unwrap())
+        Ok(Cid::from_str(&response.cid)?)
+    }
+
+    /// Subscribe to new heads pushed to the server, optionally only those of one branch. If the
+    /// subscription falls behind, a `Lagged` error is yielded and pulling is the way to catch up.
+    pub async fn subscribe(
+        &self,
+        branch: Option<&str>,
+    ) -> Result<impl Stream<Item = Result<RootEvent, LeakyApiError>>, LeakyApiError> {
+        let url = self.base_url.join("api/v0/events")?;
+        let mut request = self.client.get(url).header("accept", "text/event-stream");
+        if let Some(branch) = branch {
+            request = request.query(&[("branch", branch)]);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
+
+        let bytes = Box::pin(response.bytes_stream());
+        Ok(futures_util::stream::unfold(
+            (bytes, Vec::<u8>::new()),
+            |(mut bytes, mut buffer)| async move {
+                loop {
+                    if let Some(end) = event_end(&buffer) {
+                        let raw = buffer.drain(..end).collect::<Vec<_>>();
+                        match parse_event(&String::from_utf8_lossy(&raw)) {
+                            Some(event) => return Some((event, (bytes, buffer))),
+                            // Keep-alives and anything we don't know about
+                            None => continue,
+                        }
+                    }
+                    match bytes.next().await {
+                        Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                        Some(Err(err)) => return Some((Err(err.into()), (bytes, buffer))),
+                        None => return None,
+                    }
+                }
+            },
+        ))
+    }
+
+    pub async fn create_branch(&self, name: &str, cid: &Cid) -> Result<(), LeakyApiError> {
+        let url = self.base_url.join("api/v0/branches")?;
+        let body = serde_json::to_string(&CreateBranchRequest {
+            name: name.to_string(),
+            cid: cid.to_string(),
+        })?;
+        let response = self.client.post(url).body(body).send().await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
+        Ok(())
+    }
+
+    /// The head of every branch on the server
+    pub async fn list_branches(&self) -> Result<Vec<(String, Cid)>, LeakyApiError> {
+        let url = self.base_url.join("api/v0/branches")?;
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
+        let response: Vec<PullRootResponse> = serde_json::from_str(&response.text().await?)?;
+        response
+            .into_iter()
+            .map(|r| Ok((r.branch, Cid::from_str(&r.cid)?)))
+            .collect()
+    }
+
+    pub async fn create_ref(&self, name: &str, cid: &Cid) -> Result<(), LeakyApiError> {
+        let url = self.base_url.join("api/v0/refs")?;
+        let body = serde_json::to_string(&CreateRefRequest {
+            name: name.to_string(),
+            cid: cid.to_string(),
+        })?;
+        let response = self.client.post(url).body(body).send().await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn list_refs(&self) -> Result<Vec<(String, Cid)>, LeakyApiError> {
+        let url = self.base_url.join("api/v0/refs")?;
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
+        let response: Vec<RefResponse> = serde_json::from_str(&response.text().await?)?;
+        response
+            .into_iter()
+            .map(|r| Ok((r.name, Cid::from_str(&r.cid)?)))
+            .collect()
+    }
+
+    pub async fn read_ref(&self, name: &str) -> Result<Cid, LeakyApiError> {
+        let url = self.ref_url(name)?;
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
+        let response: RefResponse = serde_json::from_str(&response.text().await?)?;
+        Ok(Cid::from_str(&response.cid)?)
+    }
+
+    pub async fn delete_ref(&self, name: &str) -> Result<(), LeakyApiError> {
+        let url = self.ref_url(name)?;
+        let response = self.client.delete(url).send().await?;
+        if !response.status().is_success() {
+            return Err(LeakyApiError::Api(
+                response.status(),
+                response.text().await?,
+            ));
+        }
+        Ok(())
+    }
+
+    /// The URL of a ref. The name is percent-encoded as a single path segment, so a `/`, `?` or
+    /// `#` in it can't point the request somewhere else.
+    fn ref_url(&self, name: &str) -> Result<Url, LeakyApiError> {
+        // These would be dropped or resolved away rather than encoded
+        if matches!(name, "" | "." | "..") {
+            return Err(LeakyApiError::InvalidRefName(name.to_string()));
+        }
+        let mut url = self.base_url.join("api/v0/refs/")?;
+        url.path_segments_mut()
+            .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+            .pop_if_empty()
+            .push(name);
+        Ok(url)
+    }
+}
+
+/// Where the first server-sent event in `buffer` ends, just past the blank line terminating it.
+/// Lines can end in `\r\n`, `\r` or `\n`.
+fn event_end(buffer: &[u8]) -> Option<usize> {
+    let mut line_start = 0;
+    let mut i = 0;
+    while i < buffer.len() {
+        let line_end = match buffer[i] {
+            b'\n' => i + 1,
+            b'\r' if buffer.get(i + 1) == Some(&b'\n') => i + 2,
+            // The \n of a \r\n might not have arrived yet
+            b'\r' if i + 1 == buffer.len() => return None,
+            b'\r' => i + 1,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        if i == line_start {
+            return Some(line_end);
+        }
+        line_start = line_end;
+        i = line_end;
+    }
+    None
+}
+
+/// Parse a single server-sent event, skipping comments and event types other than `root` and
+/// `lagged`
+fn parse_event(raw: &str) -> Option<Result<RootEvent, LeakyApiError>> {
+    let mut event_type = "message";
+    let mut data = Vec::new();
+    for line in raw.split(['\r', '\n']) {
+        if let Some(value) = line.strip_prefix("event:") {
+            event_type = value.trim();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    let data = data.join("\n");
+
+    match event_type {
+        "root" => Some(parse_root_event(&data)),
+        "lagged" => Some(Err(LeakyApiError::Lagged(data.parse().unwrap_or(0)))),
+        _ => None,
     }
 }
 
+fn parse_root_event(data: &str) -> Result<RootEvent, LeakyApiError> {
+    let event: RootEventResponse = serde_json::from_str(data)?;
+    Ok(RootEvent {
+        branch: event.branch,
+        cid: Cid::from_str(&event.cid)?,
+        previous_cid: Cid::from_str(&event.previous_cid)?,
+        message: event.message,
+    })
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum LeakyApiError {
     #[error("default error: {0}")]
@@ -86,11 +349,71 @@ pub enum LeakyApiError {
     Client(#[from] reqwest::Error),
     #[error("Serde error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("Cid error: {0}")]
+    Cid(#[from] libipld::cid::Error),
     #[error("Api error: {0} {1}")]
     Api(reqwest::StatusCode, String),
+    #[error("missed {0} root events")]
+    Lagged(u64),
+    #[error("invalid ref name: {0:?}")]
+    InvalidRefName(String),
 }
 
-/*
 #[cfg(test)]
-mod tests {}
-*/
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        let cid = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku";
+        let raw = format!(
+            "event: root\ndata: {{\"branch\":\"main\",\"cid\":\"{cid}\",\"previous_cid\":\"{cid}\",\"message\":\"hi\"}}\n\n"
+        );
+        let event = parse_event(&raw).unwrap().unwrap();
+        assert_eq!(event.branch, "main");
+        assert_eq!(event.cid.to_string(), cid);
+        assert_eq!(event.message.as_deref(), Some("hi"));
+
+        match parse_event("event: lagged\ndata: 3\n\n") {
+            Some(Err(LeakyApiError::Lagged(3))) => {}
+            other => panic!("expected a lagged event, got {:?}", other),
+        }
+
+        // Keep-alives are comments
+        assert!(parse_event(":\n\n").is_none());
+
+        let raw = raw.replace('\n', "\r\n");
+        let event = parse_event(&raw).unwrap().unwrap();
+        assert_eq!(event.message.as_deref(), Some("hi"));
+    }
+
+    #[test]
+    fn test_event_end() {
+        assert_eq!(event_end(b"data: 1\n\ndata: 2"), Some(9));
+        assert_eq!(event_end(b"data: 1\r\n\r\ndata: 2"), Some(11));
+        assert_eq!(event_end(b"data: 1\r\rdata: 2"), Some(9));
+        assert_eq!(event_end(b"event: root\r\ndata: 1\n\r\n"), Some(23));
+        assert_eq!(event_end(b"data: 1\r\n"), None);
+        // Could be the first half of a \r\n, so wait for more
+        assert_eq!(event_end(b"data: 1\r\n\r"), None);
+    }
+
+    #[test]
+    fn test_ref_url() {
+        let api = LeakyApi::default();
+        assert_eq!(
+            api.ref_url("v1.2").unwrap().as_str(),
+            "http://localhost:3000/api/v0/refs/v1.2"
+        );
+        assert_eq!(
+            api.ref_url("a/../b?c#d").unwrap().as_str(),
+            "http://localhost:3000/api/v0/refs/a%2F..%2Fb%3Fc%23d"
+        );
+        for name in ["", ".", ".."] {
+            assert!(matches!(
+                api.ref_url(name),
+                Err(LeakyApiError::InvalidRefName(_))
+            ));
+        }
+    }
+}
@@ -1,11 +1,30 @@
+mod block_store;
+mod cache;
+mod diff;
 #[allow(unused_imports)]
 #[allow(dead_code)]
 mod ipfs_rpc;
+mod journal;
 mod leaky;
 mod leaky_api;
+mod progress;
+mod sparse;
+mod transfer;
 mod types;
 
 pub mod prelude {
-    pub use crate::leaky::{BlockCache, Leaky, LeakyError};
-    pub use crate::types::{Cid, Ipld, Manifest, Object, Version};
+    pub use crate::block_store::{BlockStore, FsBlockStore};
+    pub use crate::diff::{
+        diff_trees, pair_moves, walk_diff, DiffEntry, ManifestChange, NodeSource,
+    };
+    pub use crate::journal::PushJournal;
+    pub use crate::leaky::{is_dir_link, BlockCache, Leaky, LeakyError, DEFAULT_BRANCH};
+    pub use crate::leaky_api::{LeakyApiError, RootEvent};
+    pub use crate::progress::{Progress, ProgressEvent};
+    pub use crate::sparse::{Sparse, SparseError};
+    pub use crate::transfer::{Transfer, TransferKind, TransferProgress, DEFAULT_CONCURRENCY};
+    pub use crate::types::{
+        Cid, Codec, FieldType, Ipld, IpldCodec, Manifest, Node, Object, Schema, SchemaError,
+        Version,
+    };
 }
@@ -0,0 +1,27 @@
+use crate::transfer::{TransferKind, TransferProgress};
+
+/// Something that happened during a long running operation, for showing how far along it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A transfer started, or an item in it finished
+    Transfer(TransferProgress),
+    /// Some bytes of file data were moved as part of a transfer
+    Bytes { kind: TransferKind, bytes: u64 },
+    /// Files in the working tree hashed so far, out of all of them
+    Hashed { done: usize, total: usize },
+}
+
+/// Receives progress events from `Leaky` and the operations built on it. Events can arrive from
+/// concurrent transfers, so implementations need to be shareable across them.
+pub trait Progress: Send + Sync {
+    fn event(&self, event: ProgressEvent);
+}
+
+impl<F> Progress for F
+where
+    F: Fn(ProgressEvent) + Send + Sync,
+{
+    fn event(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
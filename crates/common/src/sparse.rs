@@ -0,0 +1,134 @@
+use std::path::{Component, Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Glob patterns selecting the parts of a bucket a sparse checkout works with, e.g.
+/// `images/train/**`. A pattern matching a directory selects everything under it. With no
+/// patterns the whole bucket is selected.
+///
+/// Paths are relative to the root of the bucket; a leading `/` is ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Sparse {
+    globs: Option<GlobSet>,
+    /// The literal leading directories of each pattern, used to tell which directories could
+    /// hold a match without listing them
+    prefixes: Vec<PathBuf>,
+}
+
+impl Sparse {
+    pub fn new(patterns: &[String]) -> Result<Self, SparseError> {
+        if patterns.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let mut builder = GlobSetBuilder::new();
+        let mut prefixes = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| SparseError::Pattern(pattern.to_string(), err))?;
+            builder.add(glob);
+            prefixes.push(literal_prefix(pattern));
+        }
+        let globs = builder
+            .build()
+            .map_err(|err| SparseError::Pattern(patterns.join(", "), err))?;
+
+        Ok(Self {
+            globs: Some(globs),
+            prefixes,
+        })
+    }
+
+    /// Whether the whole bucket is selected
+    pub fn is_full(&self) -> bool {
+        self.globs.is_none()
+    }
+
+    /// Whether the file at `path` is selected, either directly or by one of its directories
+    pub fn includes_file(&self, path: &Path) -> bool {
+        let globs = match &self.globs {
+            Some(globs) => globs,
+            None => return true,
+        };
+        relative(path)
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .any(|path| globs.is_match(path))
+    }
+
+    /// Whether anything under the directory at `path` could be selected
+    pub fn includes_dir(&self, path: &Path) -> bool {
+        if self.is_full() {
+            return true;
+        }
+        let path = relative(path);
+        self.prefixes
+            .iter()
+            .any(|prefix| prefix.starts_with(path) || path.starts_with(prefix))
+    }
+}
+
+fn relative(path: &Path) -> &Path {
+    path.strip_prefix("/").unwrap_or(path)
+}
+
+/// The directories at the start of a pattern before any glob syntax
+fn literal_prefix(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| match component {
+            Component::Normal(part) => !part
+                .to_string_lossy()
+                .contains(['*', '?', '[', ']', '{', '}']),
+            _ => false,
+        })
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SparseError {
+    #[error("invalid sparse pattern {0}: {1}")]
+    Pattern(String, globset::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sparse(patterns: &[&str]) -> Sparse {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        Sparse::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn empty_selects_everything() {
+        let sparse = sparse(&[]);
+        assert!(sparse.is_full());
+        assert!(sparse.includes_file(Path::new("/anything/at/all")));
+        assert!(sparse.includes_dir(Path::new("/anything")));
+    }
+
+    #[test]
+    fn includes_files_under_matching_patterns() {
+        let sparse = sparse(&["images/train/**", "docs", "*.md"]);
+        assert!(sparse.includes_file(Path::new("/images/train/cats/1.jpg")));
+        assert!(sparse.includes_file(Path::new("docs/guide/intro.txt")));
+        assert!(sparse.includes_file(Path::new("README.md")));
+        assert!(!sparse.includes_file(Path::new("images/test/1.jpg")));
+        // `*` doesn't cross directories
+        assert!(!sparse.includes_file(Path::new("notes/todo.md")));
+    }
+
+    #[test]
+    fn includes_dirs_that_could_hold_matches() {
+        let sparse = sparse(&["images/train/**"]);
+        assert!(sparse.includes_dir(Path::new("/")));
+        assert!(sparse.includes_dir(Path::new("/images")));
+        assert!(sparse.includes_dir(Path::new("/images/train/cats")));
+        assert!(!sparse.includes_dir(Path::new("/images/test")));
+        assert!(!sparse.includes_dir(Path::new("/videos")));
+    }
+}
@@ -0,0 +1,163 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use futures_util::stream::{self, StreamExt};
+
+use crate::progress::{Progress, ProgressEvent};
+
+/// How many blocks or files are moved at once unless configured otherwise
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// What a transfer is moving, and in which direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    /// Node blocks going to ipfs
+    PushNodes,
+    /// Node blocks coming from ipfs
+    PullNodes,
+    /// File data going to ipfs
+    PushData,
+    /// File data coming from ipfs
+    PullData,
+}
+
+/// How far along a transfer is. Reported once when it starts and again as each item finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub kind: TransferKind,
+    /// Items finished so far, including skipped ones
+    pub done: usize,
+    /// Items that didn't need moving, since the other side already had them
+    pub skipped: usize,
+    /// Items known about so far. Pulls find more nodes as they go, so this can grow.
+    pub total: usize,
+}
+
+/// Moves items with bounded concurrency, reporting progress to an optional `Progress`
+#[derive(Clone)]
+pub struct Transfer {
+    concurrency: usize,
+    progress: Option<Arc<dyn Progress>>,
+}
+
+impl Default for Transfer {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            progress: None,
+        }
+    }
+}
+
+impl Transfer {
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    pub fn set_progress(&mut self, progress: Option<Arc<dyn Progress>>) {
+        self.progress = progress;
+    }
+
+    pub fn report(&self, event: ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            progress.event(event);
+        }
+    }
+
+    /// Run `transfer` over every item, at most `concurrency` at a time. `transfer` returns whether
+    /// it moved anything, as opposed to finding the other side already had the item. Stops at the
+    /// first error.
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        kind: TransferKind,
+        items: Vec<T>,
+        transfer: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(T) -> Fut,
+        Fut: Future<Output = Result<bool, E>>,
+    {
+        let mut progress = TransferProgress {
+            kind,
+            done: 0,
+            skipped: 0,
+            total: items.len(),
+        };
+        self.report(ProgressEvent::Transfer(progress));
+
+        let mut transfers = stream::iter(items)
+            .map(transfer)
+            .buffer_unordered(self.concurrency);
+        while let Some(result) = transfers.next().await {
+            if !result? {
+                progress.skipped += 1;
+            }
+            progress.done += 1;
+            self.report(ProgressEvent::Transfer(progress));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn run_bounds_concurrency_and_reports_progress() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let mut transfer = Transfer::default();
+        transfer.set_concurrency(2);
+        let sink = reports.clone();
+        transfer.set_progress(Some(Arc::new(move |event| {
+            if let ProgressEvent::Transfer(progress) = event {
+                sink.lock().unwrap().push(progress);
+            }
+        })));
+
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        transfer
+            .run(TransferKind::PushNodes, (0..6).collect(), |i: usize| {
+                let (running, peak) = (&running, &peak);
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::task::yield_now().await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    // Pretend the other side already has the first few
+                    Ok::<_, ()>(i >= 3)
+                }
+            })
+            .await
+            .unwrap();
+
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 7);
+        let last = reports.last().unwrap();
+        assert_eq!((last.done, last.skipped, last.total), (6, 3, 6));
+    }
+
+    #[tokio::test]
+    async fn run_stops_at_the_first_error() {
+        let transfer = Transfer::default();
+        let result = transfer
+            .run(TransferKind::PullData, vec![1, 2, 3], |i| async move {
+                if i == 2 {
+                    Err("failed")
+                } else {
+                    Ok(true)
+                }
+            })
+            .await;
+        assert_eq!(result, Err("failed"));
+    }
+}
@@ -2,6 +2,7 @@ use std::convert::TryFrom;
 
 use serde::{Deserialize, Serialize};
 
+use super::schema::{Schema, SchemaError};
 use super::version::Version;
 use super::{Cid, Ipld};
 
@@ -14,6 +15,9 @@ pub struct Manifest {
     previous: Cid,
     /// data node CID
     data: Cid,
+    /// Schema the metadata of objects in the bucket must follow, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema: Option<Schema>,
 }
 
 impl Into<Ipld> for Manifest {
@@ -22,6 +26,10 @@ impl Into<Ipld> for Manifest {
         map.insert("version".to_string(), self.version.clone().into());
         map.insert("previous".to_string(), Ipld::Link(self.previous().clone()));
         map.insert("data".to_string(), Ipld::Link(self.data.clone()));
+        // Left out when there isn't one, so manifests from before schemas keep their CIDs
+        if let Some(schema) = self.schema {
+            map.insert("schema".to_string(), schema.into());
+        }
         Ipld::Map(map)
     }
 }
@@ -43,11 +51,16 @@ impl TryFrom<Ipld> for Manifest {
                     Some(Ipld::Link(cid)) => *cid,
                     _ => return Err(ManifestError::MissingField("data link".to_string())),
                 };
+                let schema = match map.get("schema") {
+                    Some(ipld) => Some(Schema::try_from(ipld.clone())?),
+                    None => None,
+                };
 
                 Ok(Manifest {
                     version,
                     previous,
                     data,
+                    schema,
                 })
             }
             _ => Err(ManifestError::MissingField("map".to_string())),
@@ -68,6 +81,10 @@ impl Manifest {
         &self.data
     }
 
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
     pub fn set_data(&mut self, cid: Cid) {
         self.data = cid;
     }
@@ -75,6 +92,10 @@ impl Manifest {
     pub fn set_previous(&mut self, cid: Cid) {
         self.previous = cid;
     }
+
+    pub fn set_schema(&mut self, schema: Option<Schema>) {
+        self.schema = schema;
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -83,4 +104,6 @@ pub enum ManifestError {
     VersionError(#[from] super::version::VersionError),
     #[error("missing field: {0}")]
     MissingField(String),
+    #[error("schema error: {0}")]
+    Schema(#[from] SchemaError),
 }
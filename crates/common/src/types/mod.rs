@@ -2,10 +2,12 @@ mod ipld;
 mod manifest;
 mod node;
 mod object;
+mod schema;
 mod version;
 
-pub use ipld::{Block, Cid, DagCborCodec, DefaultParams, Ipld, IpldCodec, MhCode};
+pub use ipld::{Block, Cid, Codec, DagCborCodec, DefaultParams, Ipld, IpldCodec, MhCode};
 pub use manifest::Manifest;
 pub use node::Node;
 pub use object::Object;
+pub use schema::{FieldType, Schema, SchemaError};
 pub use version::Version;
@@ -3,7 +3,7 @@ use std::convert::TryFrom;
 use std::ops::Deref;
 
 use super::ipld::{Cid, Ipld};
-use super::object::Object;
+use super::object::{Object, OBJECT_METADATA_LABEL};
 
 // Reserved metadata key for detailing what links
 //  within have visible metatdata attached to them
@@ -88,7 +88,8 @@ impl Node {
     }
 
     // Remove a link from the node. Should return the CID of the link, as well as the fully
-    // constructed object that was attached to the link if it exists (in the case of a file).
+    // constructed object that was attached to the link if it exists. Files always have one,
+    // directories do if they were made or tagged since directories could have metadata.
     pub fn del(&mut self, name: &str) -> (Option<Cid>, Option<Object>) {
         let metadata_ipld = self.0.get(METADATA_KEY).unwrap().clone();
         let mut metadata_map = match metadata_ipld {
@@ -153,6 +154,18 @@ impl Node {
             .map(|object_ipld| Object::try_from(object_ipld.clone()).unwrap())
     }
 
+    // Get the metadata of an object as it's stored, without constructing the object. Unlike
+    // `get_object` this doesn't panic on a malformed node, so it's safe on nodes from anywhere.
+    pub fn get_object_metadata(&self, name: &str) -> Option<&Ipld> {
+        match self.0.get(METADATA_KEY) {
+            Some(Ipld::Map(objects)) => match objects.get(name) {
+                Some(Ipld::Map(object)) => object.get(OBJECT_METADATA_LABEL),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     // Get all the metadata objects from the node
     pub fn get_objects(&self) -> BTreeMap<String, Object> {
         let metadata_ipld = self.0.get(METADATA_KEY).unwrap();
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::Ipld;
+
+/// The type a metadata key's values must have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// Anything at all, for keys that only need to be allowed
+    Any,
+    Null,
+    Bool,
+    Integer,
+    /// Floats, or integers, which are just as good
+    Float,
+    String,
+    Bytes,
+    List,
+    Map,
+    Link,
+}
+
+impl FieldType {
+    const ALL: [FieldType; 10] = [
+        FieldType::Any,
+        FieldType::Null,
+        FieldType::Bool,
+        FieldType::Integer,
+        FieldType::Float,
+        FieldType::String,
+        FieldType::Bytes,
+        FieldType::List,
+        FieldType::Map,
+        FieldType::Link,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::Any => "any",
+            FieldType::Null => "null",
+            FieldType::Bool => "bool",
+            FieldType::Integer => "integer",
+            FieldType::Float => "float",
+            FieldType::String => "string",
+            FieldType::Bytes => "bytes",
+            FieldType::List => "list",
+            FieldType::Map => "map",
+            FieldType::Link => "link",
+        }
+    }
+
+    /// The type of a value
+    pub fn of(ipld: &Ipld) -> Self {
+        match ipld {
+            Ipld::Null => FieldType::Null,
+            Ipld::Bool(_) => FieldType::Bool,
+            Ipld::Integer(_) => FieldType::Integer,
+            Ipld::Float(_) => FieldType::Float,
+            Ipld::String(_) => FieldType::String,
+            Ipld::Bytes(_) => FieldType::Bytes,
+            Ipld::List(_) => FieldType::List,
+            Ipld::Map(_) => FieldType::Map,
+            Ipld::Link(_) => FieldType::Link,
+        }
+    }
+
+    pub fn matches(&self, ipld: &Ipld) -> bool {
+        match (self, FieldType::of(ipld)) {
+            (FieldType::Any, _) | (FieldType::Float, FieldType::Integer) => true,
+            (expected, found) => *expected == found,
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The metadata keys objects in a bucket may have and the types of their values, kept in the
+/// manifest. Objects don't have to set every key, but unless the schema is `open` they can't
+/// set keys it doesn't list, which catches misspelt ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schema {
+    pub fields: BTreeMap<String, FieldType>,
+    /// Allow keys the schema doesn't list, with values of any type
+    #[serde(default)]
+    pub open: bool,
+}
+
+const SCHEMA_FIELDS_LABEL: &str = "fields";
+const SCHEMA_OPEN_LABEL: &str = "open";
+
+impl Schema {
+    /// Check an object's metadata against the schema
+    pub fn validate(&self, metadata: &BTreeMap<String, Ipld>) -> Result<(), SchemaError> {
+        match self.violations(metadata).into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Everything wrong with an object's metadata, key by key
+    pub fn violations(&self, metadata: &BTreeMap<String, Ipld>) -> Vec<SchemaError> {
+        metadata
+            .iter()
+            .filter_map(|(key, value)| match self.fields.get(key) {
+                Some(expected) if !expected.matches(value) => Some(SchemaError::WrongType {
+                    key: key.clone(),
+                    expected: *expected,
+                    found: FieldType::of(value),
+                }),
+                None if !self.open => Some(SchemaError::UnknownKey(key.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl From<Schema> for Ipld {
+    fn from(schema: Schema) -> Self {
+        let fields = schema
+            .fields
+            .iter()
+            .map(|(key, field)| (key.clone(), Ipld::String(field.name().to_string())))
+            .collect();
+        let mut map = BTreeMap::new();
+        map.insert(SCHEMA_FIELDS_LABEL.to_string(), Ipld::Map(fields));
+        map.insert(SCHEMA_OPEN_LABEL.to_string(), Ipld::Bool(schema.open));
+        Ipld::Map(map)
+    }
+}
+
+impl TryFrom<Ipld> for Schema {
+    type Error = SchemaError;
+    fn try_from(ipld: Ipld) -> Result<Self, SchemaError> {
+        let map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(SchemaError::Invalid("not a map".to_string())),
+        };
+        let fields = match map.get(SCHEMA_FIELDS_LABEL) {
+            Some(Ipld::Map(fields)) => fields
+                .iter()
+                .map(|(key, field)| {
+                    let field = FieldType::ALL
+                        .into_iter()
+                        .find(|ty| matches!(field, Ipld::String(name) if name == ty.name()))
+                        .ok_or_else(|| SchemaError::Invalid(format!("bad type for {}", key)))?;
+                    Ok((key.clone(), field))
+                })
+                .collect::<Result<_, SchemaError>>()?,
+            _ => return Err(SchemaError::Invalid("missing fields".to_string())),
+        };
+        let open = matches!(map.get(SCHEMA_OPEN_LABEL), Some(Ipld::Bool(true)));
+        Ok(Self { fields, open })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error("{0} isn't a key the schema allows")]
+    UnknownKey(String),
+    #[error("{key} should be {expected}, not {found}")]
+    WrongType {
+        key: String,
+        expected: FieldType,
+        found: FieldType,
+    },
+    #[error("invalid schema: {0}")]
+    Invalid(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schema(open: bool) -> Schema {
+        Schema {
+            fields: [
+                ("author".to_string(), FieldType::String),
+                ("score".to_string(), FieldType::Float),
+            ]
+            .into_iter()
+            .collect(),
+            open,
+        }
+    }
+
+    fn metadata(entries: &[(&str, Ipld)]) -> BTreeMap<String, Ipld> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn schema_validates_keys_and_types() {
+        let closed = schema(false);
+        assert!(closed
+            .validate(&metadata(&[
+                ("author", Ipld::String("al".to_string())),
+                ("score", Ipld::Integer(3)),
+            ]))
+            .is_ok());
+        assert!(matches!(
+            closed.validate(&metadata(&[("autor", Ipld::String("al".to_string()))])),
+            Err(SchemaError::UnknownKey(key)) if key == "autor"
+        ));
+        assert!(matches!(
+            closed.validate(&metadata(&[("score", Ipld::String("high".to_string()))])),
+            Err(SchemaError::WrongType {
+                expected: FieldType::Float,
+                found: FieldType::String,
+                ..
+            })
+        ));
+        assert!(schema(true)
+            .validate(&metadata(&[("other", Ipld::Null)]))
+            .is_ok());
+    }
+
+    #[test]
+    fn schema_reports_every_violation() {
+        let violations = schema(false).violations(&metadata(&[
+            ("autor", Ipld::String("al".to_string())),
+            ("score", Ipld::Bytes(b"hello".to_vec())),
+        ]));
+        assert_eq!(
+            violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "autor isn't a key the schema allows".to_string(),
+                "score should be float, not bytes".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn schema_round_trips_through_ipld() {
+        let schema = schema(true);
+        let ipld: Ipld = schema.clone().into();
+        assert_eq!(Schema::try_from(ipld).unwrap(), schema);
+    }
+}
@@ -11,6 +11,8 @@ pub struct Version {
     build_profile: String,
     build_features: String,
     repo_version: String,
+    /// Version of leaky-common that made the manifest. Its minor version goes up whenever the
+    /// layout of a bucket changes, as when directories got objects in 0.2.
     version: String,
 }
 
@@ -102,6 +104,20 @@ impl Version {
     pub fn version(&self) -> &str {
         &self.version
     }
+
+    /// Whether this build can read a bucket made by this version. Anything up to the same minor
+    /// version can be read; a later one may lay out the bucket in ways this build would misread.
+    pub fn is_supported(&self) -> bool {
+        release(&self.version) <= release(env!("CARGO_PKG_VERSION"))
+    }
+}
+
+/// The major and minor parts of a version
+fn release(version: &str) -> (u64, u64) {
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse::<u64>().unwrap_or(0));
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -109,3 +125,25 @@ pub enum VersionError {
     #[error("missing metadata: {0}")]
     MissingMetadata(String),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version {
+            version: version.to_string(),
+            ..Version::new()
+        }
+    }
+
+    #[test]
+    fn newer_releases_are_not_supported() {
+        let (major, minor) = release(env!("CARGO_PKG_VERSION"));
+        assert!(Version::new().is_supported());
+        assert!(version("0.1.0").is_supported());
+        assert!(version(&format!("{}.{}.99", major, minor)).is_supported());
+        assert!(!version(&format!("{}.{}.0", major, minor + 1)).is_supported());
+        assert!(!version(&format!("{}.0.0", major + 1)).is_supported());
+    }
+}
@@ -0,0 +1,11 @@
+/* Pin status of each pushed root on the server's IPFS node */
+
+CREATE TABLE root_pins (
+    cid VARCHAR(255) PRIMARY KEY NOT NULL,
+    status VARCHAR(32) NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX root_pins_status ON root_pins (status);
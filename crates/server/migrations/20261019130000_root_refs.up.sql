@@ -0,0 +1,9 @@
+/* Named, immutable refs to pushed roots */
+
+CREATE TABLE root_refs (
+    name VARCHAR(255) PRIMARY KEY NOT NULL,
+    cid VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX root_refs_cid ON root_refs (cid);
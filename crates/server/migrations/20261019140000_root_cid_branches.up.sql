@@ -0,0 +1,8 @@
+/* Scope the root chain by branch, each with its own head */
+
+ALTER TABLE root_cids ADD COLUMN branch VARCHAR(255) NOT NULL DEFAULT 'main';
+
+DROP INDEX root_cids_cid_previous_cid;
+
+CREATE UNIQUE INDEX root_cids_branch_cid_previous_cid ON root_cids (branch, cid, previous_cid);
+CREATE INDEX root_cids_branch_created_at ON root_cids (branch, created_at);
@@ -0,0 +1,11 @@
+/* Webhook deliveries still being retried, so they can be resumed after a restart */
+
+CREATE TABLE pending_webhook_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id INTEGER NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    branch VARCHAR(255) NOT NULL,
+    cid VARCHAR(255) NOT NULL,
+    body BLOB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
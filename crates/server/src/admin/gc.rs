@@ -0,0 +1,73 @@
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+use crate::app::AppState;
+use crate::gc::{self, GcError, GcPlan};
+use crate::ipfs::PinMode;
+
+#[derive(Serialize)]
+pub struct GcPlanResponse {
+    enabled: bool,
+    expired_roots: Vec<String>,
+    direct_blocks: Vec<String>,
+    recursive_blocks: Vec<String>,
+}
+
+impl GcPlanResponse {
+    fn new(enabled: bool, plan: GcPlan) -> Self {
+        let (recursive, direct): (Vec<_>, Vec<_>) = plan
+            .blocks
+            .into_iter()
+            .partition(|(_, mode)| *mode == PinMode::Recursive);
+        GcPlanResponse {
+            enabled,
+            expired_roots: plan
+                .expired_roots
+                .iter()
+                .map(|cid| cid.to_string())
+                .collect(),
+            direct_blocks: direct.iter().map(|(cid, _)| cid.to_string()).collect(),
+            recursive_blocks: recursive.iter().map(|(cid, _)| cid.to_string()).collect(),
+        }
+    }
+}
+
+/// Dry run of garbage collection: report what the next pass would unpin, without unpinning it
+pub async fn plan_handler(State(state): State<AppState>) -> Result<impl IntoResponse, GcPlanError> {
+    let plan = gc::plan(&state).await?;
+    let enabled = state.retention_policy().is_enabled();
+    Ok((
+        http::StatusCode::OK,
+        Json(GcPlanResponse::new(enabled, plan)),
+    ))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GcPlanError {
+    #[error("gc error: {0}")]
+    Gc(#[from] GcError),
+}
+
+impl IntoResponse for GcPlanError {
+    fn into_response(self) -> Response {
+        match self {
+            GcPlanError::Gc(GcError::Ipfs(err)) => {
+                tracing::error!("failed to walk roots: {}", err);
+                (
+                    http::StatusCode::BAD_GATEWAY,
+                    "failed to walk roots on the ipfs node",
+                )
+                    .into_response()
+            }
+            GcPlanError::Gc(err) => {
+                tracing::error!("database error: {}", err);
+                (
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "unknown server error",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
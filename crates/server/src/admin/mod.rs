@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get};
+use axum::Router;
+use axum_extra::headers::authorization::Bearer;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+
+use crate::app::AppState;
+
+mod gc;
+mod webhooks;
+
+/// Operator endpoints, only reachable with the configured admin token
+pub fn router(state: AppState) -> Router<AppState> {
+    let admin_token = state.admin_token().map(Arc::from);
+
+    Router::new()
+        .route("/gc", get(gc::plan_handler))
+        .route(
+            "/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route("/webhooks/:id", delete(webhooks::delete_webhook))
+        .route("/webhooks/:id/deliveries", get(webhooks::list_deliveries))
+        .route_layer(axum::middleware::from_fn_with_state(
+            admin_token,
+            require_admin,
+        ))
+        .with_state(state)
+}
+
+async fn require_admin(
+    State(admin_token): State<Option<Arc<str>>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AdminAuthError> {
+    let admin_token = admin_token.ok_or(AdminAuthError::Disabled)?;
+    match bearer {
+        Some(TypedHeader(Authorization(bearer)))
+            if constant_time_eq(bearer.token().as_bytes(), admin_token.as_bytes()) =>
+        {
+            Ok(next.run(request).await)
+        }
+        _ => Err(AdminAuthError::Unauthorized),
+    }
+}
+
+/// Compare tokens without bailing out at the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdminAuthError {
+    #[error("no admin token is configured")]
+    Disabled,
+    #[error("missing or invalid admin token")]
+    Unauthorized,
+}
+
+impl IntoResponse for AdminAuthError {
+    fn into_response(self) -> Response {
+        match self {
+            AdminAuthError::Disabled => {
+                (http::StatusCode::FORBIDDEN, "admin api disabled").into_response()
+            }
+            AdminAuthError::Unauthorized => {
+                (http::StatusCode::UNAUTHORIZED, "unauthorized").into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn router(admin_token: Option<&str>) -> Router {
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(
+                admin_token.map(Arc::from),
+                require_admin,
+            ))
+    }
+
+    async fn status(router: Router, token: Option<&str>) -> StatusCode {
+        let mut request = http::Request::get("/");
+        if let Some(token) = token {
+            request = request.header(http::header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let request = request.body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_require_admin() {
+        assert_eq!(
+            status(router(Some("secret")), Some("secret")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(router(Some("secret")), Some("guess")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(router(Some("secret")), None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(router(None), Some("secret")).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::app::AppState;
+use crate::database::models::{Webhook, WebhookDelivery, WebhookError};
+
+const DEFAULT_DELIVERY_LIMIT: i64 = 100;
+
+#[derive(Serialize)]
+pub struct WebhookResponse {
+    id: i64,
+    url: String,
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(webhook: Webhook) -> Self {
+        WebhookResponse {
+            id: webhook.id(),
+            url: webhook.url().to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    url: String,
+    /// Key used to sign each payload, so the receiver can check it came from this server
+    secret: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeliveriesQuery {
+    limit: Option<i64>,
+}
+
+/// List registered webhooks. Secrets are never returned. A server hosts a single bucket, so a
+/// webhook hears about every branch of that bucket; there's no per-bucket registration.
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, WebhookAdminError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    let webhooks = Webhook::list(&mut conn).await?;
+    let webhooks = webhooks
+        .into_iter()
+        .map(WebhookResponse::from)
+        .collect::<Vec<_>>();
+    Ok((http::StatusCode::OK, Json(webhooks)))
+}
+
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<impl IntoResponse, WebhookAdminError> {
+    let url = Url::parse(&request.url).map_err(|_| WebhookAdminError::InvalidUrl)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(WebhookAdminError::InvalidUrl);
+    }
+    if request.secret.is_empty() {
+        return Err(WebhookAdminError::MissingSecret);
+    }
+
+    let mut conn = state.sqlite_database().acquire().await?;
+    let webhook = Webhook::create(url.as_str(), &request.secret, &mut conn).await?;
+    Ok((
+        http::StatusCode::CREATED,
+        Json(WebhookResponse::from(webhook)),
+    ))
+}
+
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, WebhookAdminError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    if Webhook::delete(id, &mut conn).await? {
+        Ok(http::StatusCode::NO_CONTENT)
+    } else {
+        Err(WebhookAdminError::NotFound(id))
+    }
+}
+
+/// The delivery log of a webhook, most recent attempts first
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Result<impl IntoResponse, WebhookAdminError> {
+    let limit = query.limit.unwrap_or(DEFAULT_DELIVERY_LIMIT).max(1);
+    let mut conn = state.sqlite_database().acquire().await?;
+    let deliveries = WebhookDelivery::list(id, limit, &mut conn).await?;
+    Ok((http::StatusCode::OK, Json(deliveries)))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookAdminError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("webhook error: {0}")]
+    Webhook(#[from] WebhookError),
+    #[error("invalid webhook url")]
+    InvalidUrl,
+    #[error("missing webhook secret")]
+    MissingSecret,
+    #[error("no such webhook: {0}")]
+    NotFound(i64),
+}
+
+impl IntoResponse for WebhookAdminError {
+    fn into_response(self) -> Response {
+        match self {
+            WebhookAdminError::InvalidUrl => (
+                http::StatusCode::BAD_REQUEST,
+                "webhook url must be an absolute http(s) url",
+            )
+                .into_response(),
+            WebhookAdminError::MissingSecret => (
+                http::StatusCode::BAD_REQUEST,
+                "webhook secret must not be empty",
+            )
+                .into_response(),
+            WebhookAdminError::NotFound(id) => (
+                http::StatusCode::NOT_FOUND,
+                format!("no such webhook: {id}"),
+            )
+                .into_response(),
+            err => {
+                tracing::error!("database error: {}", err);
+                (
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "unknown server error",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use cid::Cid;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::database::models::{RootCid, RootCidError};
+
+use super::PullRootResponse;
+
+/// Branch used by clients that don't name one, and the one every root pushed before branches
+/// existed belongs to
+pub const DEFAULT_BRANCH: &str = "main";
+
+const MAX_BRANCH_NAME_LENGTH: usize = 255;
+
+pub fn default_branch() -> String {
+    DEFAULT_BRANCH.to_string()
+}
+
+#[derive(Deserialize)]
+pub struct BranchQuery {
+    #[serde(default = "default_branch")]
+    pub branch: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateBranchRequest {
+    name: String,
+    cid: String,
+}
+
+/// The head of every branch
+pub async fn list_branches(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, BranchError> {
+    let db = state.sqlite_database();
+    let mut conn = db.acquire().await?;
+    let heads = RootCid::branches(&mut conn)
+        .await?
+        .into_iter()
+        .map(PullRootResponse::from)
+        .collect::<Vec<_>>();
+    Ok((http::StatusCode::OK, Json(heads)))
+}
+
+/// Start a branch at a root that has already been pushed
+pub async fn create_branch(
+    State(state): State<AppState>,
+    Json(request): Json<CreateBranchRequest>,
+) -> Result<impl IntoResponse, BranchError> {
+    if !is_valid_branch_name(&request.name) {
+        return Err(BranchError::InvalidName(request.name));
+    }
+    let cid = Cid::from_str(&request.cid)?;
+
+    let db = state.sqlite_database();
+    let mut conn = db.begin().await?;
+    if !RootCid::exists(&cid, &mut conn).await? {
+        return Err(BranchError::UnknownRoot(cid));
+    }
+    let head = RootCid::create_branch(&request.name, &cid, &mut conn).await?;
+    conn.commit().await?;
+
+    Ok((
+        http::StatusCode::CREATED,
+        Json(PullRootResponse::from(head)),
+    ))
+}
+
+/// Branch names travel in query strings and request bodies, so unlike ref names they may be
+/// namespaced with '/'
+pub fn is_valid_branch_name(name: &str) -> bool {
+    let Some(first) = name.chars().next() else {
+        return false;
+    };
+    name.len() <= MAX_BRANCH_NAME_LENGTH
+        && first.is_ascii_alphanumeric()
+        && !name.ends_with('/')
+        && !name.contains("//")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/'))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BranchError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("invalid CID: {0}")]
+    Cid(#[from] cid::Error),
+    #[error("root CID error: {0}")]
+    RootCid(#[from] RootCidError),
+    #[error("invalid branch name: {0}")]
+    InvalidName(String),
+    #[error("root was never pushed: {0}")]
+    UnknownRoot(Cid),
+}
+
+impl IntoResponse for BranchError {
+    fn into_response(self) -> Response {
+        match self {
+            BranchError::RootCid(RootCidError::BranchExists(name)) => (
+                http::StatusCode::CONFLICT,
+                format!("branch already exists: {name}"),
+            )
+                .into_response(),
+            BranchError::Cid(_) => (http::StatusCode::BAD_REQUEST, "invalid cid").into_response(),
+            BranchError::InvalidName(_) => {
+                (http::StatusCode::BAD_REQUEST, "invalid branch name").into_response()
+            }
+            BranchError::UnknownRoot(cid) => (
+                http::StatusCode::NOT_FOUND,
+                format!("root was never pushed: {cid}"),
+            )
+                .into_response(),
+            err => {
+                tracing::error!("database error: {}", err);
+                (
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "unknown server error",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_branch_name() {
+        assert!(is_valid_branch_name(DEFAULT_BRANCH));
+        assert!(is_valid_branch_name("experiment/relabel-v2"));
+        assert!(is_valid_branch_name("dataset_2026.10"));
+
+        assert!(!is_valid_branch_name(""));
+        assert!(!is_valid_branch_name("/leading"));
+        assert!(!is_valid_branch_name("trailing/"));
+        assert!(!is_valid_branch_name("double//slash"));
+        assert!(!is_valid_branch_name("with space"));
+        assert!(!is_valid_branch_name(
+            &"a".repeat(MAX_BRANCH_NAME_LENGTH + 1)
+        ));
+    }
+}
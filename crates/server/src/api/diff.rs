@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+use axum::extract::{Json, Query, State};
+use axum::response::{IntoResponse, Response};
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::database::models::{RootCid, RootCidError, RootRef, RootRefError};
+use crate::database::DatabaseConnection;
+use crate::ipfs::{diff_manifests, IpfsRpcError, ManifestChange};
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    /// CID or ref name of the older root
+    from: String,
+    /// CID or ref name of the newer root
+    to: String,
+}
+
+#[derive(Serialize)]
+pub struct DiffResponse {
+    from: String,
+    to: String,
+    changes: Vec<ChangeResponse>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum ChangeResponse {
+    Added {
+        path: String,
+        cid: String,
+    },
+    Removed {
+        path: String,
+        cid: String,
+    },
+    Modified {
+        path: String,
+        from: String,
+        to: String,
+    },
+    MetadataChanged {
+        path: String,
+        cid: String,
+    },
+    Moved {
+        from: String,
+        to: String,
+        cid: String,
+    },
+}
+
+impl From<ManifestChange> for ChangeResponse {
+    fn from(change: ManifestChange) -> Self {
+        match change {
+            ManifestChange::Added { path, cid } => ChangeResponse::Added {
+                path,
+                cid: cid.to_string(),
+            },
+            ManifestChange::Removed { path, cid } => ChangeResponse::Removed {
+                path,
+                cid: cid.to_string(),
+            },
+            ManifestChange::Modified { path, from, to } => ChangeResponse::Modified {
+                path,
+                from: from.to_string(),
+                to: to.to_string(),
+            },
+            ManifestChange::MetadataChanged { path, cid } => ChangeResponse::MetadataChanged {
+                path,
+                cid: cid.to_string(),
+            },
+            ManifestChange::Moved { from, to, cid } => ChangeResponse::Moved {
+                from,
+                to,
+                cid: cid.to_string(),
+            },
+        }
+    }
+}
+
+/// Compare two pushed roots path by path
+pub async fn diff_roots(
+    State(state): State<AppState>,
+    Query(query): Query<DiffQuery>,
+) -> Result<impl IntoResponse, DiffError> {
+    let db = state.sqlite_database();
+    let mut conn = db.acquire().await?;
+    let from = resolve_root(&query.from, &mut conn).await?;
+    let to = resolve_root(&query.to, &mut conn).await?;
+
+    let changes = diff_manifests(state.ipfs_rpc(), &from, &to).await?;
+    Ok((
+        http::StatusCode::OK,
+        Json(DiffResponse {
+            from: from.to_string(),
+            to: to.to_string(),
+            changes: changes.into_iter().map(ChangeResponse::from).collect(),
+        }),
+    ))
+}
+
+/// Resolve a CID or ref name to a root that was pushed to this server
+async fn resolve_root(revision: &str, conn: &mut DatabaseConnection) -> Result<Cid, DiffError> {
+    let cid = match Cid::from_str(revision) {
+        Ok(cid) => cid,
+        Err(_) => match RootRef::read(revision, conn).await? {
+            Some(root_ref) => return Ok(root_ref.cid()),
+            None => return Err(DiffError::UnknownRevision(revision.to_string())),
+        },
+    };
+    if !RootCid::exists(&cid, conn).await? {
+        return Err(DiffError::UnknownRevision(revision.to_string()));
+    }
+    Ok(cid)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiffError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("root CID error: {0}")]
+    RootCid(#[from] RootCidError),
+    #[error("root ref error: {0}")]
+    RootRef(#[from] RootRefError),
+    #[error("ipfs error: {0}")]
+    Ipfs(#[from] IpfsRpcError),
+    #[error("no pushed root or ref: {0}")]
+    UnknownRevision(String),
+}
+
+impl IntoResponse for DiffError {
+    fn into_response(self) -> Response {
+        match self {
+            DiffError::UnknownRevision(revision) => (
+                http::StatusCode::NOT_FOUND,
+                format!("no pushed root or ref: {revision}"),
+            )
+                .into_response(),
+            DiffError::Ipfs(IpfsRpcError::Timeout(method)) => {
+                tracing::warn!("ipfs {method} timed out");
+                (
+                    http::StatusCode::GATEWAY_TIMEOUT,
+                    "timed out reading roots from the ipfs node",
+                )
+                    .into_response()
+            }
+            DiffError::Ipfs(err) => {
+                tracing::error!("failed to diff roots: {}", err);
+                (
+                    http::StatusCode::BAD_GATEWAY,
+                    "failed to read roots from the ipfs node",
+                )
+                    .into_response()
+            }
+            err => {
+                tracing::error!("database error: {}", err);
+                (
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "unknown server error",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use axum::extract::{Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+
+use crate::app::AppState;
+use crate::events::RootEvent;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Only report new heads of this branch, rather than of every branch
+    branch: Option<String>,
+}
+
+/// Stream a `root` event whenever a branch gets a new head. Subscribers that fall too far behind
+/// get a `lagged` event carrying how many they missed, and should pull to catch up.
+pub async fn subscribe(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.root_events().subscribe();
+    let stream = root_event_stream(receiver, state.shutdown_signal(), query.branch);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// Forward events from the broadcast channel until it closes or the server starts shutting down,
+/// since an open stream would otherwise hold up graceful shutdown
+fn root_event_stream(
+    receiver: broadcast::Receiver<RootEvent>,
+    shutdown_rx: watch::Receiver<()>,
+    branch: Option<String>,
+) -> impl Stream<Item = Result<Event, axum::Error>> {
+    futures::stream::unfold(
+        (receiver, shutdown_rx, branch),
+        |(mut receiver, mut shutdown_rx, branch)| async move {
+            loop {
+                let result = tokio::select! {
+                    result = receiver.recv() => result,
+                    _ = shutdown_rx.changed() => return None,
+                };
+                let event = match result {
+                    Ok(event) if branch.as_ref().is_some_and(|b| *b != event.branch) => continue,
+                    Ok(event) => Event::default().event("root").json_data(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        Ok(Event::default().event("lagged").data(missed.to_string()))
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((event, (receiver, shutdown_rx, branch)));
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn root_event(branch: &str, cid: &str) -> RootEvent {
+        RootEvent {
+            branch: branch.to_string(),
+            cid: cid.to_string(),
+            previous_cid: "previous".to_string(),
+            message: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_root_event_stream_filters_by_branch() {
+        let (sender, receiver) = broadcast::channel(8);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(());
+        let stream = root_event_stream(receiver, shutdown_rx, Some("main".to_string()));
+        futures::pin_mut!(stream);
+
+        sender.send(root_event("experiment", "skipped")).unwrap();
+        sender.send(root_event("main", "kept")).unwrap();
+        drop(sender);
+
+        let event = stream.next().await.unwrap().unwrap();
+        let rendered = format!("{:?}", event);
+        assert!(rendered.contains("kept"));
+        assert!(!rendered.contains("skipped"));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_root_event_stream_ends_on_shutdown() {
+        let (_sender, receiver) = broadcast::channel::<RootEvent>(8);
+        let (shutdown_tx, shutdown_rx) = watch::channel(());
+        let stream = root_event_stream(receiver, shutdown_rx, None);
+        futures::pin_mut!(stream);
+
+        shutdown_tx.send(()).unwrap();
+        assert!(stream.next().await.is_none());
+    }
+}
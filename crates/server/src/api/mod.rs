@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use axum::extract::{Json, State};
+use axum::extract::{Json, Query, State};
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::Router;
@@ -11,7 +11,19 @@ use serde::{Deserialize, Serialize};
 use tower_http::cors::{Any, CorsLayer};
 
 use crate::app::AppState;
-use crate::database::models::RootCid;
+use crate::database::models::{RootCid, RootCidError, RootPin};
+use crate::events::RootEvent;
+use crate::ipfs::{schema_violations, IpfsRpcError};
+
+use crate::replication::spawn_replication;
+use crate::webhooks::spawn_webhooks;
+use branches::{default_branch, is_valid_branch_name, BranchQuery};
+
+mod branches;
+mod diff;
+mod events;
+mod pins;
+mod refs;
 
 pub fn router(state: AppState) -> Router<AppState> {
     let cors_layer = CorsLayer::new()
@@ -22,12 +34,22 @@ pub fn router(state: AppState) -> Router<AppState> {
 
     Router::new()
         .route("/root", get(pull_root).post(push_root))
+        .route(
+            "/branches",
+            get(branches::list_branches).post(branches::create_branch),
+        )
+        .route("/diff", get(diff::diff_roots))
+        .route("/events", get(events::subscribe))
+        .route("/pins/:cid", get(pins::pin_status))
+        .route("/refs", get(refs::list_refs).post(refs::create_ref))
+        .route("/refs/:name", get(refs::read_ref).delete(refs::delete_ref))
         .with_state(state)
         .layer(cors_layer)
 }
 
 #[derive(Serialize)]
 pub struct PullRootResponse {
+    branch: String,
     previous_cid: String,
     cid: String,
 }
@@ -35,16 +57,31 @@ pub struct PullRootResponse {
 impl From<RootCid> for PullRootResponse {
     fn from(root_cid: RootCid) -> Self {
         PullRootResponse {
+            branch: root_cid.branch().to_string(),
             previous_cid: root_cid.previous_cid().to_string(),
             cid: root_cid.cid().to_string(),
         }
     }
 }
 
-pub async fn pull_root(State(state): State<AppState>) -> Result<impl IntoResponse, PullRootError> {
+pub async fn pull_root(
+    State(state): State<AppState>,
+    Query(query): Query<BranchQuery>,
+) -> Result<impl IntoResponse, PullRootError> {
+    let result = try_pull_root(&state, &query.branch).await;
+    let outcome = match &result {
+        Ok(_) => "found",
+        Err(PullRootError::NotFound) => "not_found",
+        Err(_) => "error",
+    };
+    state.metrics().record_pull(outcome);
+    result
+}
+
+async fn try_pull_root(state: &AppState, branch: &str) -> Result<Response, PullRootError> {
     let db = state.sqlite_database();
     let mut conn = db.acquire().await?;
-    let maybe_root_cid = RootCid::pull(&mut conn).await?;
+    let maybe_root_cid = RootCid::pull(branch, &mut conn).await?;
     match maybe_root_cid {
         Some(root_cid) => {
             Ok((http::StatusCode::OK, Json(PullRootResponse::from(root_cid))).into_response())
@@ -58,7 +95,7 @@ pub enum PullRootError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
     #[error("root CID error: {0}")]
-    RootCid(#[from] crate::database::models::RootCidError),
+    RootCid(#[from] RootCidError),
     #[error("No root CID found")]
     NotFound,
 }
@@ -87,22 +124,86 @@ impl IntoResponse for PullRootError {
 pub struct PushRootRequest {
     cid: String,
     previous_cid: String,
+    #[serde(default = "default_branch")]
+    branch: String,
+    /// Passed along to anyone subscribed to root events
+    message: Option<String>,
 }
 
 pub async fn push_root(
     State(state): State<AppState>,
     Json(push_root): Json<PushRootRequest>,
 ) -> Result<impl IntoResponse, PushRootError> {
+    let result = try_push_root(&state, push_root).await;
+    let outcome = match &result {
+        Ok(_) => "accepted",
+        Err(PushRootError::Cid(_) | PushRootError::InvalidBranch(_) | PushRootError::Schema(_)) => {
+            "invalid"
+        }
+        Err(PushRootError::RootCid(
+            RootCidError::Conflict(_, _) | RootCidError::InvalidLink(_, _),
+        )) => "conflict",
+        Err(_) => "error",
+    };
+    state.metrics().record_push(outcome);
+    result
+}
+
+async fn try_push_root(
+    state: &AppState,
+    push_root: PushRootRequest,
+) -> Result<Response, PushRootError> {
+    if !is_valid_branch_name(&push_root.branch) {
+        return Err(PushRootError::InvalidBranch(push_root.branch));
+    }
     let cid = Cid::from_str(&push_root.cid)?;
     let previous_cid = Cid::from_str(&push_root.previous_cid)?;
 
+    // Turn away a push that doesn't build on the head before reading anything from ipfs. The
+    // push below checks again, in case the branch moves on in the meantime.
+    let head = {
+        let db = state.sqlite_database();
+        let mut conn = db.acquire().await?;
+        RootCid::pull(&push_root.branch, &mut conn).await?
+    };
+    let from = match head {
+        Some(head) if head.cid() != previous_cid => {
+            return Err(RootCidError::InvalidLink(head.cid(), previous_cid).into());
+        }
+        Some(_) => Some(previous_cid),
+        // The first root of a branch may start from anywhere, so all of it is checked
+        None => None,
+    };
+
+    // Objects the root adds or changes have to follow its schema, if it has one
+    let violations = schema_violations(state.ipfs_rpc(), from.as_ref(), &cid).await?;
+    if !violations.is_empty() {
+        return Err(PushRootError::Schema(violations));
+    }
+
     let db = state.sqlite_database();
     let mut conn = db.begin().await?;
 
-    let root_cid = RootCid::push(&cid, &previous_cid, &mut conn).await?;
+    let root_cid = RootCid::push(&push_root.branch, &cid, &previous_cid, &mut conn).await?;
+    let needs_pin = RootPin::create(&cid, &mut conn).await?;
 
     conn.commit().await?;
 
+    // Roots already pending have a job running, and pinned ones don't need one
+    if needs_pin {
+        spawn_replication(state.clone(), cid);
+    }
+
+    let event = RootEvent {
+        branch: root_cid.branch().to_string(),
+        cid: root_cid.cid().to_string(),
+        previous_cid: root_cid.previous_cid().to_string(),
+        message: push_root.message,
+    };
+    spawn_webhooks(state.clone(), cid, previous_cid, event.clone());
+    // Nobody may be listening, which isn't an error
+    let _ = state.root_events().send(event);
+
     Ok((http::StatusCode::OK, Json(PullRootResponse::from(root_cid))).into_response())
 }
 
@@ -112,8 +213,16 @@ pub enum PushRootError {
     Database(#[from] sqlx::Error),
     #[error("invalid CID: {0}")]
     Cid(#[from] cid::Error),
+    #[error("invalid branch name: {0}")]
+    InvalidBranch(String),
     #[error("root CID error: {0}")]
-    RootCid(#[from] crate::database::models::RootCidError),
+    RootCid(#[from] RootCidError),
+    #[error("root pin error: {0}")]
+    RootPin(#[from] crate::database::models::RootPinError),
+    #[error("ipfs error: {0}")]
+    Ipfs(#[from] IpfsRpcError),
+    #[error("metadata doesn't follow the schema: {}", .0.join("; "))]
+    Schema(Vec<String>),
 }
 
 impl IntoResponse for PushRootError {
@@ -127,11 +236,46 @@ impl IntoResponse for PushRootError {
                 )
                     .into_response()
             }
+            PushRootError::RootPin(err) => {
+                tracing::error!("root pin error: {}", err);
+                (
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "unknown server error",
+                )
+                    .into_response()
+            }
+            PushRootError::Ipfs(IpfsRpcError::Timeout(method)) => {
+                tracing::warn!("ipfs {method} timed out");
+                (
+                    http::StatusCode::GATEWAY_TIMEOUT,
+                    "timed out reading the root from ipfs",
+                )
+                    .into_response()
+            }
+            PushRootError::Ipfs(err) => {
+                tracing::error!("ipfs error: {}", err);
+                (
+                    http::StatusCode::BAD_GATEWAY,
+                    "could not read the root from ipfs",
+                )
+                    .into_response()
+            }
+            PushRootError::Schema(violations) => (
+                http::StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "metadata doesn't follow the schema: {}",
+                    violations.join("; ")
+                ),
+            )
+                .into_response(),
             PushRootError::Cid(_err) => {
                 (http::StatusCode::BAD_REQUEST, "invalid cid").into_response()
             }
+            PushRootError::InvalidBranch(_) => {
+                (http::StatusCode::BAD_REQUEST, "invalid branch name").into_response()
+            }
             PushRootError::RootCid(ref err) => match err {
-                crate::database::models::RootCidError::Sqlx(err) => {
+                RootCidError::Sqlx(err) => {
                     tracing::error!("database error: {}", err);
                     (
                         http::StatusCode::INTERNAL_SERVER_ERROR,
@@ -139,12 +283,12 @@ impl IntoResponse for PushRootError {
                     )
                         .into_response()
                 }
-                /*
-                crate::database::models::RootCidError::InvalidLink(_, _) => {
-                    (http::StatusCode::BAD_REQUEST, "invalid link").into_response()
-                }
-                */
-                crate::database::models::RootCidError::Conflict(_, _) => {
+                RootCidError::InvalidLink(head, _) => (
+                    http::StatusCode::CONFLICT,
+                    format!("branch has moved on, its head is now {head}"),
+                )
+                    .into_response(),
+                RootCidError::Conflict(_, _) | RootCidError::BranchExists(_) => {
                     (http::StatusCode::CONFLICT, "conflict").into_response()
                 }
             },
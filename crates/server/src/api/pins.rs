@@ -0,0 +1,79 @@
+use std::str::FromStr;
+
+use axum::extract::{Json, Path, State};
+use axum::response::{IntoResponse, Response};
+use cid::Cid;
+use serde::Serialize;
+
+use crate::app::AppState;
+use crate::database::models::{PinStatus, RootPin, RootPinError};
+
+#[derive(Serialize)]
+pub struct PinStatusResponse {
+    cid: String,
+    status: PinStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<RootPin> for PinStatusResponse {
+    fn from(root_pin: RootPin) -> Self {
+        PinStatusResponse {
+            cid: root_pin.cid().to_string(),
+            status: root_pin.status(),
+            error: root_pin.error().map(String::from),
+        }
+    }
+}
+
+/// Report whether a pushed root has been fully pinned by the server
+pub async fn pin_status(
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+) -> Result<impl IntoResponse, PinStatusError> {
+    let cid = Cid::from_str(&cid)?;
+
+    let db = state.sqlite_database();
+    let mut conn = db.acquire().await?;
+    match RootPin::read(&cid, &mut conn).await? {
+        Some(root_pin) => Ok((
+            http::StatusCode::OK,
+            Json(PinStatusResponse::from(root_pin)),
+        )
+            .into_response()),
+        None => Err(PinStatusError::NotFound),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PinStatusError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("invalid CID: {0}")]
+    Cid(#[from] cid::Error),
+    #[error("root pin error: {0}")]
+    RootPin(#[from] RootPinError),
+    #[error("root was never pushed")]
+    NotFound,
+}
+
+impl IntoResponse for PinStatusError {
+    fn into_response(self) -> Response {
+        match self {
+            PinStatusError::Database(err) | PinStatusError::RootPin(RootPinError::Sqlx(err)) => {
+                tracing::error!("database error: {}", err);
+                (
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "unknown server error",
+                )
+                    .into_response()
+            }
+            PinStatusError::Cid(_) => {
+                (http::StatusCode::BAD_REQUEST, "invalid cid").into_response()
+            }
+            PinStatusError::NotFound => {
+                (http::StatusCode::NOT_FOUND, "root was never pushed").into_response()
+            }
+        }
+    }
+}
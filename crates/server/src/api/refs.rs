@@ -0,0 +1,190 @@
+use std::str::FromStr;
+
+use axum::extract::{Json, Path, State};
+use axum::response::{IntoResponse, Response};
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::database::models::{
+    RootCid, RootCidError, RootPin, RootPinError, RootRef, RootRefError,
+};
+use crate::replication::spawn_replication;
+
+const MAX_REF_NAME_LENGTH: usize = 255;
+
+#[derive(Serialize)]
+pub struct RefResponse {
+    name: String,
+    cid: String,
+}
+
+impl From<RootRef> for RefResponse {
+    fn from(root_ref: RootRef) -> Self {
+        RefResponse {
+            name: root_ref.name().to_string(),
+            cid: root_ref.cid().to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateRefRequest {
+    name: String,
+    cid: String,
+}
+
+pub async fn list_refs(State(state): State<AppState>) -> Result<impl IntoResponse, RefError> {
+    let db = state.sqlite_database();
+    let mut conn = db.acquire().await?;
+    let root_refs = RootRef::list(&mut conn).await?;
+    let root_refs = root_refs
+        .into_iter()
+        .map(RefResponse::from)
+        .collect::<Vec<_>>();
+    Ok((http::StatusCode::OK, Json(root_refs)))
+}
+
+/// Name a pushed root. The root is pinned again if garbage collection already reclaimed it.
+pub async fn create_ref(
+    State(state): State<AppState>,
+    Json(request): Json<CreateRefRequest>,
+) -> Result<impl IntoResponse, RefError> {
+    if !is_valid_ref_name(&request.name) {
+        return Err(RefError::InvalidName(request.name));
+    }
+    let cid = Cid::from_str(&request.cid)?;
+
+    let db = state.sqlite_database();
+    let mut conn = db.begin().await?;
+
+    if !RootCid::exists(&cid, &mut conn).await? {
+        return Err(RefError::UnknownRoot(cid));
+    }
+    let root_ref = RootRef::create(&request.name, &cid, &mut conn).await?;
+    let needs_pin = RootPin::create(&cid, &mut conn).await?;
+
+    conn.commit().await?;
+
+    // Roots already pending have a job running, and pinned ones don't need one
+    if needs_pin {
+        spawn_replication(state.clone(), cid);
+    }
+
+    Ok((http::StatusCode::CREATED, Json(RefResponse::from(root_ref))))
+}
+
+pub async fn read_ref(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, RefError> {
+    let db = state.sqlite_database();
+    let mut conn = db.acquire().await?;
+    match RootRef::read(&name, &mut conn).await? {
+        Some(root_ref) => Ok((http::StatusCode::OK, Json(RefResponse::from(root_ref)))),
+        None => Err(RefError::NotFound(name)),
+    }
+}
+
+pub async fn delete_ref(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, RefError> {
+    let db = state.sqlite_database();
+    let mut conn = db.acquire().await?;
+    if RootRef::delete(&name, &mut conn).await? {
+        Ok(http::StatusCode::NO_CONTENT)
+    } else {
+        Err(RefError::NotFound(name))
+    }
+}
+
+/// Ref names live alongside CIDs wherever a revision is accepted, so they're kept to a
+/// conservative character set and may not themselves parse as a CID.
+fn is_valid_ref_name(name: &str) -> bool {
+    let Some(first) = name.chars().next() else {
+        return false;
+    };
+    name.len() <= MAX_REF_NAME_LENGTH
+        && first.is_ascii_alphanumeric()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        && Cid::from_str(name).is_err()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RefError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("invalid CID: {0}")]
+    Cid(#[from] cid::Error),
+    #[error("root CID error: {0}")]
+    RootCid(#[from] RootCidError),
+    #[error("root pin error: {0}")]
+    RootPin(#[from] RootPinError),
+    #[error("root ref error: {0}")]
+    RootRef(#[from] RootRefError),
+    #[error("invalid ref name: {0}")]
+    InvalidName(String),
+    #[error("root was never pushed: {0}")]
+    UnknownRoot(Cid),
+    #[error("no such ref: {0}")]
+    NotFound(String),
+}
+
+impl IntoResponse for RefError {
+    fn into_response(self) -> Response {
+        match self {
+            RefError::RootRef(RootRefError::Conflict(name)) => (
+                http::StatusCode::CONFLICT,
+                format!("ref already exists: {name}"),
+            )
+                .into_response(),
+            RefError::Cid(_) => (http::StatusCode::BAD_REQUEST, "invalid cid").into_response(),
+            RefError::InvalidName(_) => (
+                http::StatusCode::BAD_REQUEST,
+                "ref names must start with a letter or digit, contain only letters, digits, '.', '-' and '_', and not be a CID",
+            )
+                .into_response(),
+            RefError::UnknownRoot(cid) => (
+                http::StatusCode::NOT_FOUND,
+                format!("root was never pushed: {cid}"),
+            )
+                .into_response(),
+            RefError::NotFound(name) => {
+                (http::StatusCode::NOT_FOUND, format!("no such ref: {name}")).into_response()
+            }
+            err => {
+                tracing::error!("database error: {}", err);
+                (
+                    http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "unknown server error",
+                )
+                    .into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_ref_name() {
+        assert!(is_valid_ref_name("v1.2"));
+        assert!(is_valid_ref_name("training-set-2026-10"));
+        assert!(is_valid_ref_name("release_3"));
+
+        assert!(!is_valid_ref_name(""));
+        assert!(!is_valid_ref_name(".hidden"));
+        assert!(!is_valid_ref_name("-flag"));
+        assert!(!is_valid_ref_name("nested/ref"));
+        assert!(!is_valid_ref_name("with space"));
+        assert!(!is_valid_ref_name(&"a".repeat(MAX_REF_NAME_LENGTH + 1)));
+        assert!(!is_valid_ref_name(
+            "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku"
+        ));
+    }
+}
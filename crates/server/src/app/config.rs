@@ -1,6 +1,7 @@
 use std::env;
 use std::net::SocketAddr;
 use std::str::FromStr;
+use std::time::Duration;
 
 use dotenvy::dotenv;
 
@@ -19,6 +20,18 @@ pub struct Config {
 
     // Logging Level
     log_level: tracing::Level,
+
+    // Retention Config
+    retention_keep_last: Option<usize>,
+    retention_keep_days: Option<u64>,
+    gc_interval: Duration,
+
+    // Admin API Config
+    admin_token: Option<String>,
+
+    // Webhook Config
+    bucket_name: String,
+    webhook_max_attempts: u32,
 }
 
 impl Config {
@@ -69,11 +82,59 @@ impl Config {
             }
         };
 
+        // Retention is opt-in: without either limit every root is kept forever
+        let retention_keep_last = match env::var("RETENTION_KEEP_LAST") {
+            Ok(count) => Some(count.parse()?),
+            Err(_e) => None,
+        };
+        let retention_keep_days = match env::var("RETENTION_KEEP_DAYS") {
+            Ok(days) => Some(days.parse()?),
+            Err(_e) => None,
+        };
+
+        let gc_interval_str = match env::var("GC_INTERVAL_SECS") {
+            Ok(secs) => secs,
+            Err(_e) => "3600".to_string(),
+        };
+        let gc_interval_secs = gc_interval_str.parse()?;
+        // The collector runs on a fixed period, which can't be zero
+        if gc_interval_secs == 0 {
+            return Err(ConfigError::NotPositive("GC_INTERVAL_SECS"));
+        }
+        let gc_interval = Duration::from_secs(gc_interval_secs);
+
+        let admin_token = match env::var("ADMIN_TOKEN") {
+            Ok(token) if !token.is_empty() => Some(token),
+            _ => {
+                tracing::warn!("No ADMIN_TOKEN found in .env. Admin API disabled");
+                None
+            }
+        };
+
+        // The one bucket this server hosts. Metrics are labelled with it and webhook payloads
+        // name it, so scrapers and receivers can tell servers apart
+        let bucket_name = match env::var("BUCKET_NAME") {
+            Ok(name) => name,
+            Err(_e) => "leaky".to_string(),
+        };
+
+        let webhook_max_attempts_str = match env::var("WEBHOOK_MAX_ATTEMPTS") {
+            Ok(attempts) => attempts,
+            Err(_e) => "5".to_string(),
+        };
+        let webhook_max_attempts = webhook_max_attempts_str.parse()?;
+
         Ok(Config {
             listen_addr,
             sqlite_database_url,
             ipfs_api_url,
             log_level,
+            retention_keep_last,
+            retention_keep_days,
+            gc_interval,
+            admin_token,
+            bucket_name,
+            webhook_max_attempts,
         })
     }
 
@@ -92,6 +153,30 @@ impl Config {
     pub fn listen_addr(&self) -> &SocketAddr {
         &self.listen_addr
     }
+
+    pub fn retention_keep_last(&self) -> Option<usize> {
+        self.retention_keep_last
+    }
+
+    pub fn retention_keep_days(&self) -> Option<u64> {
+        self.retention_keep_days
+    }
+
+    pub fn gc_interval(&self) -> Duration {
+        self.gc_interval
+    }
+
+    pub fn admin_token(&self) -> Option<&str> {
+        self.admin_token.as_deref()
+    }
+
+    pub fn bucket_name(&self) -> &str {
+        &self.bucket_name
+    }
+
+    pub fn webhook_max_attempts(&self) -> u32 {
+        self.webhook_max_attempts
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -102,4 +187,8 @@ pub enum ConfigError {
     Env(#[from] env::VarError),
     #[error("Invalid Socket Address: {0}")]
     ListenAddr(#[from] std::net::AddrParseError),
+    #[error("Invalid Number: {0}")]
+    Number(#[from] std::num::ParseIntError),
+    #[error("{0} must be greater than zero")]
+    NotPositive(&'static str),
 }
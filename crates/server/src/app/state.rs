@@ -1,13 +1,27 @@
+use std::sync::Arc;
+
 use axum::extract::FromRef;
-use url::Url;
+use tokio::sync::{broadcast, watch, RwLock};
 
 use super::config::Config;
 use crate::database::Database;
+use crate::events::{root_event_channel, RootEvent};
+use crate::gc::RetentionPolicy;
+use crate::ipfs::{IpfsRpc, IpfsRpcError};
+use crate::metrics::Metrics;
+use crate::webhooks::WebhookSender;
 
 #[derive(Clone)]
 pub struct AppState {
     sqlite_database: Database,
-    ipfs_api_url: Url,
+    ipfs_rpc: IpfsRpc,
+    metrics: Metrics,
+    retention_policy: RetentionPolicy,
+    gc_lock: Arc<RwLock<()>>,
+    admin_token: Option<String>,
+    root_events: broadcast::Sender<RootEvent>,
+    webhooks: WebhookSender,
+    shutdown_rx: watch::Receiver<()>,
     // TODO: better proxy solution
     //    ipfs_api_proxy: IpfsApiProxy,
 }
@@ -18,8 +32,46 @@ impl AppState {
         &self.sqlite_database
     }
 
-    pub fn ipfs_api_url(&self) -> &Url {
-        &self.ipfs_api_url
+    pub fn ipfs_rpc(&self) -> &IpfsRpc {
+        &self.ipfs_rpc
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn retention_policy(&self) -> &RetentionPolicy {
+        &self.retention_policy
+    }
+
+    /// Held for reading while pinning and for writing while garbage collecting, so the two never
+    /// race over the same blocks
+    pub fn gc_lock(&self) -> &RwLock<()> {
+        &self.gc_lock
+    }
+
+    pub fn admin_token(&self) -> Option<&str> {
+        self.admin_token.as_deref()
+    }
+
+    /// Published to whenever a branch gets a new head
+    pub fn root_events(&self) -> &broadcast::Sender<RootEvent> {
+        &self.root_events
+    }
+
+    pub fn webhooks(&self) -> &WebhookSender {
+        &self.webhooks
+    }
+
+    /// A receiver notified when the graceful shutdown signal fires, for work that needs to wind
+    /// down on its own
+    pub fn shutdown_signal(&self) -> watch::Receiver<()> {
+        self.shutdown_rx.clone()
+    }
+
+    /// Whether the graceful shutdown signal has fired (or its sender has gone away)
+    pub fn is_shutting_down(&self) -> bool {
+        !matches!(self.shutdown_rx.has_changed(), Ok(false))
     }
 
     /*
@@ -27,13 +79,25 @@ impl AppState {
             &self.ipfs_api_proxy
         }
     */
-    pub async fn from_config(config: &Config) -> Result<Self, AppStateSetupError> {
+    pub async fn from_config(
+        config: &Config,
+        shutdown_rx: watch::Receiver<()>,
+    ) -> Result<Self, AppStateSetupError> {
+        let metrics = Metrics::new(config.bucket_name())?;
         let sqlite_database = Database::connect(config.sqlite_database_url()).await?;
-        let ipfs_api_url = config.ipfs_api_url().clone();
+        let ipfs_rpc = IpfsRpc::new(config.ipfs_api_url().clone(), metrics.clone())?;
 
         Ok(Self {
             sqlite_database,
-            ipfs_api_url,
+            ipfs_rpc,
+            metrics,
+            retention_policy: RetentionPolicy::from_config(config),
+            gc_lock: Arc::new(RwLock::new(())),
+            admin_token: config.admin_token().map(String::from),
+            root_events: root_event_channel(),
+            webhooks: WebhookSender::from_config(config)
+                .map_err(AppStateSetupError::WebhookSetup)?,
+            shutdown_rx,
         })
     }
 }
@@ -55,6 +119,12 @@ impl FromRef<AppState> for IpfsApiProxy {
 pub enum AppStateSetupError {
     #[error("failed to setup the database: {0}")]
     DatabaseSetup(#[from] crate::database::DatabaseSetupError),
+    #[error("failed to setup the ipfs rpc client: {0}")]
+    IpfsRpcSetup(#[from] IpfsRpcError),
+    #[error("failed to setup the webhook client: {0}")]
+    WebhookSetup(reqwest::Error),
+    #[error("failed to setup metrics: {0}")]
+    MetricsSetup(#[from] prometheus::Error),
     #[error("leptos config error")]
     LeptosConfigError(#[from] leptos_config::errors::LeptosConfigError),
 }
@@ -1,95 +1,242 @@
+mod root_pin;
+mod root_ref;
+mod webhook;
+
 use cid::Cid;
 use sqlx::FromRow;
 
 use crate::database::types::DCid;
 use crate::database::DatabaseConnection;
 
+pub use root_pin::{PinStatus, RootPin, RootPinError};
+pub use root_ref::{RootRef, RootRefError};
+pub use webhook::{PendingWebhookDelivery, Webhook, WebhookDelivery, WebhookError};
+
 /*
 CREATE TABLE root_cids (
     id SERIAL PRIMARY KEY,
     cid VARCHAR(255) NOT NULL,
     previous_cid VARCHAR(255) NOT NULL,
-    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    branch VARCHAR(255) NOT NULL DEFAULT 'main'
 );
 
-CREATE UNIQUE INDEX root_cids_cid_previous_cid ON root_cids (cid, previous_cid);
+CREATE UNIQUE INDEX root_cids_branch_cid_previous_cid ON root_cids (branch, cid, previous_cid);
 */
 
 #[derive(FromRow, Debug)]
 pub struct RootCid {
+    branch: String,
     cid: DCid,
     previous_cid: DCid,
 }
 
 impl RootCid {
+    /// Move the head of a branch from `previous_cid` to `cid`. This is a compare-and-swap: if the
+    /// branch has moved on since `previous_cid` the push is rejected. The first push to a branch
+    /// may start from any root.
     pub async fn push(
+        branch: &str,
         cid: &Cid,
         previous_cid: &Cid,
         conn: &mut DatabaseConnection,
     ) -> Result<RootCid, RootCidError> {
-        // Read the current root cid
-        /*
-        let maybe_root_cid = RootCid::pull(conn).await?;
-        if let Some(root_cid) = maybe_root_cid {
-            if root_cid.cid() != *previous_cid {
-                return Err(RootCidError::InvalidLink(root_cid.cid(), *previous_cid));
-            }
-        }
-        */
-
         let dcid: DCid = (*cid).into();
         let dprevious_cid: DCid = (*previous_cid).into();
-        let root_cid = sqlx::query_as!(
+        // Checking the head and inserting in one statement keeps concurrent pushes from both
+        // building on the same head
+        let maybe_root_cid = sqlx::query_as!(
             RootCid,
             r#"
             INSERT INTO root_cids (
+                branch,
                 cid,
                 previous_cid,
                 created_at
             )
-            VALUES (
+            SELECT
                 $1,
                 $2,
+                $3,
                 CURRENT_TIMESTAMP
-            )
-            RETURNING cid as "cid: DCid", previous_cid as "previous_cid: DCid"
+            WHERE COALESCE(
+                (
+                    SELECT cid
+                    FROM root_cids
+                    WHERE branch = $1
+                    ORDER BY
+                        created_at DESC,
+                        id DESC
+                    LIMIT 1
+                ),
+                $3
+            ) = $3
+            RETURNING
+                branch as "branch!",
+                cid as "cid!: DCid",
+                previous_cid as "previous_cid!: DCid"
             "#,
+            branch,
             dcid,
             dprevious_cid
         )
-        .fetch_one(conn)
+        .fetch_optional(&mut *conn)
         .await
         .map_err(|e| match e {
-            sqlx::Error::Database(ref db_error) => {
-                if db_error.constraint().unwrap_or("") == "root_cids_cid_previous_cid" {
-                    RootCidError::Conflict(*cid, *previous_cid)
-                } else {
-                    e.into()
-                }
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                RootCidError::Conflict(*cid, *previous_cid)
             }
             _ => e.into(),
         })?;
-        Ok(root_cid)
+
+        match maybe_root_cid {
+            Some(root_cid) => Ok(root_cid),
+            None => {
+                let head = RootCid::pull(branch, conn)
+                    .await?
+                    .ok_or(RootCidError::Sqlx(sqlx::Error::RowNotFound))?;
+                Err(RootCidError::InvalidLink(head.cid(), *previous_cid))
+            }
+        }
+    }
+
+    /// Start a new branch at an existing root
+    pub async fn create_branch(
+        branch: &str,
+        cid: &Cid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<RootCid, RootCidError> {
+        let dcid: DCid = (*cid).into();
+        let root_cid = sqlx::query_as!(
+            RootCid,
+            r#"
+            INSERT INTO root_cids (
+                branch,
+                cid,
+                previous_cid,
+                created_at
+            )
+            SELECT
+                $1,
+                $2,
+                $2,
+                CURRENT_TIMESTAMP
+            WHERE NOT EXISTS (
+                SELECT 1
+                FROM root_cids
+                WHERE branch = $1
+            )
+            RETURNING
+                branch as "branch!",
+                cid as "cid!: DCid",
+                previous_cid as "previous_cid!: DCid"
+            "#,
+            branch,
+            dcid
+        )
+        .fetch_optional(conn)
+        .await?;
+        root_cid.ok_or_else(|| RootCidError::BranchExists(branch.to_string()))
     }
 
-    pub async fn pull(conn: &mut DatabaseConnection) -> Result<Option<RootCid>, RootCidError> {
+    /// Read the head of a branch
+    pub async fn pull(
+        branch: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Option<RootCid>, RootCidError> {
         let root_cid = sqlx::query_as!(
             RootCid,
             r#"
-            SELECT 
-                cid as "cid: DCid", 
+            SELECT
+                branch,
+                cid as "cid: DCid",
                 previous_cid as "previous_cid: DCid"
             FROM root_cids
-            ORDER BY 
-                created_at DESC 
+            WHERE branch = $1
+            ORDER BY
+                created_at DESC,
+                id DESC
             LIMIT 1
-            "#
+            "#,
+            branch
         )
         .fetch_optional(conn)
         .await?;
         Ok(root_cid)
     }
 
+    /// The head of every branch
+    pub async fn branches(conn: &mut DatabaseConnection) -> Result<Vec<RootCid>, RootCidError> {
+        let root_cids = sqlx::query_as!(
+            RootCid,
+            r#"
+            SELECT
+                branch,
+                cid as "cid: DCid",
+                previous_cid as "previous_cid: DCid"
+            FROM root_cids AS heads
+            WHERE id = (
+                SELECT id
+                FROM root_cids
+                WHERE branch = heads.branch
+                ORDER BY
+                    created_at DESC,
+                    id DESC
+                LIMIT 1
+            )
+            ORDER BY branch ASC
+            "#
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(root_cids)
+    }
+
+    /// Whether a root has ever been pushed
+    pub async fn exists(cid: &Cid, conn: &mut DatabaseConnection) -> Result<bool, RootCidError> {
+        let dcid: DCid = (*cid).into();
+        let exists = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1
+                FROM root_cids
+                WHERE cid = $1
+            ) as "exists!: bool"
+            "#,
+            dcid
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(exists)
+    }
+
+    /// Every root ever pushed, newest first, alongside when it was (last) pushed as a unix
+    /// timestamp
+    pub async fn history(conn: &mut DatabaseConnection) -> Result<Vec<(Cid, i64)>, RootCidError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                cid as "cid!: DCid",
+                CAST(strftime('%s', MAX(created_at)) AS INTEGER) as "pushed_at!: i64"
+            FROM root_cids
+            GROUP BY cid
+            ORDER BY
+                MAX(created_at) DESC,
+                MAX(id) DESC
+            "#
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.cid.into(), row.pushed_at))
+            .collect())
+    }
+
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
     pub fn cid(&self) -> Cid {
         self.cid.into()
     }
@@ -103,8 +250,10 @@ impl RootCid {
 pub enum RootCidError {
     #[error("sqlx: {0}")]
     Sqlx(#[from] sqlx::Error),
-    // #[error("wrong previous cid: {0:?} != {1:?}")]
-    // InvalidLink(Cid, Cid),
+    #[error("wrong previous cid: {0:?} != {1:?}")]
+    InvalidLink(Cid, Cid),
     #[error("conflicting Update: {0:?} -> {1:?}")]
     Conflict(Cid, Cid),
+    #[error("branch already exists: {0}")]
+    BranchExists(String),
 }
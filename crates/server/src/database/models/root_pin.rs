@@ -0,0 +1,147 @@
+use cid::Cid;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::database::types::DCid;
+use crate::database::DatabaseConnection;
+
+/*
+CREATE TABLE root_pins (
+    cid VARCHAR(255) PRIMARY KEY NOT NULL,
+    status VARCHAR(32) NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum PinStatus {
+    /// Accepted, but not yet fully pinned on the server's IPFS node
+    Pending,
+    /// Every block of the root is pinned, so the push is durable
+    Pinned,
+    /// Pinning gave up; pushing the same root again retries it
+    Failed,
+    /// Expired under the retention policy and unpinned by garbage collection
+    Reclaimed,
+}
+
+#[derive(FromRow, Debug)]
+pub struct RootPin {
+    cid: DCid,
+    status: PinStatus,
+    error: Option<String>,
+}
+
+impl RootPin {
+    /// Mark a root as pending. Roots that previously failed or were reclaimed are reset, while
+    /// pending and pinned roots keep their status. Returns whether the root was marked, i.e.
+    /// whether a replication job needs to run for it.
+    pub async fn create(cid: &Cid, conn: &mut DatabaseConnection) -> Result<bool, RootPinError> {
+        let dcid: DCid = (*cid).into();
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO root_pins (
+                cid,
+                status
+            )
+            VALUES (
+                $1,
+                'pending'
+            )
+            ON CONFLICT (cid) DO UPDATE SET
+                status = 'pending',
+                error = NULL,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE root_pins.status IN ('failed', 'reclaimed')
+            "#,
+            dcid
+        )
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn read(
+        cid: &Cid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Option<RootPin>, RootPinError> {
+        let dcid: DCid = (*cid).into();
+        let root_pin = sqlx::query_as!(
+            RootPin,
+            r#"
+            SELECT
+                cid as "cid: DCid",
+                status as "status: PinStatus",
+                error
+            FROM root_pins
+            WHERE cid = $1
+            "#,
+            dcid
+        )
+        .fetch_optional(conn)
+        .await?;
+        Ok(root_pin)
+    }
+
+    pub async fn update(
+        cid: &Cid,
+        status: PinStatus,
+        error: Option<String>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), RootPinError> {
+        let dcid: DCid = (*cid).into();
+        sqlx::query!(
+            r#"
+            UPDATE root_pins
+            SET
+                status = $2,
+                error = $3,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE cid = $1
+            "#,
+            dcid,
+            status,
+            error
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Roots still waiting on a pin, e.g. because the server restarted mid-job
+    pub async fn pending(conn: &mut DatabaseConnection) -> Result<Vec<Cid>, RootPinError> {
+        let cids = sqlx::query_scalar!(
+            r#"
+            SELECT cid as "cid: DCid"
+            FROM root_pins
+            WHERE status = 'pending'
+            ORDER BY created_at ASC
+            "#
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(cids.into_iter().map(Cid::from).collect())
+    }
+
+    pub fn cid(&self) -> Cid {
+        self.cid.into()
+    }
+
+    pub fn status(&self) -> PinStatus {
+        self.status
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RootPinError {
+    #[error("sqlx: {0}")]
+    Sqlx(#[from] sqlx::Error),
+}
@@ -0,0 +1,117 @@
+use cid::Cid;
+use sqlx::FromRow;
+
+use crate::database::types::DCid;
+use crate::database::DatabaseConnection;
+
+/*
+CREATE TABLE root_refs (
+    name VARCHAR(255) PRIMARY KEY NOT NULL,
+    cid VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+*/
+
+#[derive(FromRow, Debug)]
+pub struct RootRef {
+    name: String,
+    cid: DCid,
+}
+
+impl RootRef {
+    /// Name a root. Refs are immutable, so naming one that already exists is a conflict rather
+    /// than an update.
+    pub async fn create(
+        name: &str,
+        cid: &Cid,
+        conn: &mut DatabaseConnection,
+    ) -> Result<RootRef, RootRefError> {
+        let dcid: DCid = (*cid).into();
+        let root_ref = sqlx::query_as!(
+            RootRef,
+            r#"
+            INSERT INTO root_refs (
+                name,
+                cid
+            )
+            VALUES (
+                $1,
+                $2
+            )
+            ON CONFLICT (name) DO NOTHING
+            RETURNING name as "name!", cid as "cid!: DCid"
+            "#,
+            name,
+            dcid
+        )
+        .fetch_optional(conn)
+        .await?;
+        root_ref.ok_or_else(|| RootRefError::Conflict(name.to_string()))
+    }
+
+    pub async fn read(
+        name: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Option<RootRef>, RootRefError> {
+        let root_ref = sqlx::query_as!(
+            RootRef,
+            r#"
+            SELECT
+                name,
+                cid as "cid: DCid"
+            FROM root_refs
+            WHERE name = $1
+            "#,
+            name
+        )
+        .fetch_optional(conn)
+        .await?;
+        Ok(root_ref)
+    }
+
+    pub async fn list(conn: &mut DatabaseConnection) -> Result<Vec<RootRef>, RootRefError> {
+        let root_refs = sqlx::query_as!(
+            RootRef,
+            r#"
+            SELECT
+                name,
+                cid as "cid: DCid"
+            FROM root_refs
+            ORDER BY name ASC
+            "#
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(root_refs)
+    }
+
+    /// Remove a ref, returning whether it existed. The root it named is left alone.
+    pub async fn delete(name: &str, conn: &mut DatabaseConnection) -> Result<bool, RootRefError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM root_refs
+            WHERE name = $1
+            "#,
+            name
+        )
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cid(&self) -> Cid {
+        self.cid.into()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RootRefError {
+    #[error("sqlx: {0}")]
+    Sqlx(#[from] sqlx::Error),
+    #[error("ref already exists: {0}")]
+    Conflict(String),
+}
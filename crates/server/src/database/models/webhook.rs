@@ -0,0 +1,350 @@
+use cid::Cid;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use crate::database::types::DCid;
+use crate::database::DatabaseConnection;
+
+/*
+CREATE TABLE webhooks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE webhook_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id INTEGER NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    branch VARCHAR(255) NOT NULL,
+    cid VARCHAR(255) NOT NULL,
+    attempt INTEGER NOT NULL,
+    status_code INTEGER,
+    error TEXT,
+    succeeded BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE pending_webhook_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id INTEGER NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    branch VARCHAR(255) NOT NULL,
+    cid VARCHAR(255) NOT NULL,
+    body BLOB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+*/
+
+#[derive(FromRow, Debug, Clone)]
+pub struct Webhook {
+    id: i64,
+    url: String,
+    secret: String,
+}
+
+impl Webhook {
+    pub async fn create(
+        url: &str,
+        secret: &str,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Webhook, WebhookError> {
+        let webhook = sqlx::query_as!(
+            Webhook,
+            r#"
+            INSERT INTO webhooks (
+                url,
+                secret
+            )
+            VALUES (
+                $1,
+                $2
+            )
+            RETURNING id as "id!", url as "url!", secret as "secret!"
+            "#,
+            url,
+            secret
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(webhook)
+    }
+
+    pub async fn list(conn: &mut DatabaseConnection) -> Result<Vec<Webhook>, WebhookError> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            r#"
+            SELECT
+                id as "id!",
+                url,
+                secret
+            FROM webhooks
+            ORDER BY id ASC
+            "#
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(webhooks)
+    }
+
+    /// Remove a webhook along with its delivery log, returning whether it existed
+    pub async fn delete(id: i64, conn: &mut DatabaseConnection) -> Result<bool, WebhookError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM webhooks
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+}
+
+/// One attempt at notifying a webhook of a new head
+#[derive(FromRow, Debug, Serialize)]
+pub struct WebhookDelivery {
+    id: i64,
+    branch: String,
+    cid: String,
+    attempt: i64,
+    status_code: Option<i64>,
+    error: Option<String>,
+    succeeded: bool,
+    delivered_at: i64,
+}
+
+impl WebhookDelivery {
+    /// Log an attempt. It succeeded if the webhook answered with a 2xx status.
+    pub async fn create(
+        webhook_id: i64,
+        branch: &str,
+        cid: &Cid,
+        attempt: u32,
+        status_code: Option<u16>,
+        error: Option<String>,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), WebhookError> {
+        let dcid: DCid = (*cid).into();
+        let succeeded = status_code.is_some_and(|code| (200..300).contains(&code));
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries (
+                webhook_id,
+                branch,
+                cid,
+                attempt,
+                status_code,
+                error,
+                succeeded
+            )
+            VALUES (
+                $1,
+                $2,
+                $3,
+                $4,
+                $5,
+                $6,
+                $7
+            )
+            "#,
+            webhook_id,
+            branch,
+            dcid,
+            attempt,
+            status_code,
+            error,
+            succeeded
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// The most recent attempts at notifying a webhook, newest first
+    pub async fn list(
+        webhook_id: i64,
+        limit: i64,
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let deliveries = sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+            SELECT
+                id as "id!",
+                branch,
+                cid,
+                attempt,
+                status_code,
+                error,
+                succeeded as "succeeded: bool",
+                CAST(strftime('%s', created_at) AS INTEGER) as "delivered_at!: i64"
+            FROM webhook_deliveries
+            WHERE webhook_id = $1
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            webhook_id,
+            limit
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(deliveries)
+    }
+}
+
+/// A delivery that hasn't succeeded or given up yet. The payload is kept as it was built, so a
+/// delivery resumed after a restart sends the same body.
+#[derive(FromRow, Debug)]
+pub struct PendingWebhookDelivery {
+    id: i64,
+    webhook_id: i64,
+    branch: String,
+    cid: DCid,
+    body: Vec<u8>,
+    attempts: i64,
+}
+
+impl PendingWebhookDelivery {
+    pub async fn create(
+        webhook_id: i64,
+        branch: &str,
+        cid: &Cid,
+        body: &[u8],
+        conn: &mut DatabaseConnection,
+    ) -> Result<PendingWebhookDelivery, WebhookError> {
+        let dcid: DCid = (*cid).into();
+        let delivery = sqlx::query_as!(
+            PendingWebhookDelivery,
+            r#"
+            INSERT INTO pending_webhook_deliveries (
+                webhook_id,
+                branch,
+                cid,
+                body
+            )
+            VALUES (
+                $1,
+                $2,
+                $3,
+                $4
+            )
+            RETURNING
+                id as "id!",
+                webhook_id as "webhook_id!",
+                branch as "branch!",
+                cid as "cid!: DCid",
+                body as "body!",
+                attempts as "attempts!"
+            "#,
+            webhook_id,
+            branch,
+            dcid,
+            body
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(delivery)
+    }
+
+    /// Every delivery still to be made, oldest first
+    pub async fn list(
+        conn: &mut DatabaseConnection,
+    ) -> Result<Vec<PendingWebhookDelivery>, WebhookError> {
+        let deliveries = sqlx::query_as!(
+            PendingWebhookDelivery,
+            r#"
+            SELECT
+                id as "id!",
+                webhook_id,
+                branch,
+                cid as "cid: DCid",
+                body,
+                attempts
+            FROM pending_webhook_deliveries
+            ORDER BY id ASC
+            "#
+        )
+        .fetch_all(conn)
+        .await?;
+        Ok(deliveries)
+    }
+
+    /// Record how many attempts have been made so far
+    pub async fn update(
+        id: i64,
+        attempts: u32,
+        conn: &mut DatabaseConnection,
+    ) -> Result<(), WebhookError> {
+        sqlx::query!(
+            r#"
+            UPDATE pending_webhook_deliveries
+            SET attempts = $2
+            WHERE id = $1
+            "#,
+            id,
+            attempts
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Stop tracking a delivery once it succeeded or gave up
+    pub async fn delete(id: i64, conn: &mut DatabaseConnection) -> Result<(), WebhookError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM pending_webhook_deliveries
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn webhook_id(&self) -> i64 {
+        self.webhook_id
+    }
+
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    pub fn cid(&self) -> Cid {
+        self.cid.into()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// How many attempts were made before, e.g. before a restart
+    pub fn attempts(&self) -> u32 {
+        self.attempts.try_into().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("sqlx: {0}")]
+    Sqlx(#[from] sqlx::Error),
+}
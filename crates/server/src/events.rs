@@ -0,0 +1,20 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// How many root events a slow subscriber can fall behind by before it starts missing them
+const ROOT_EVENT_CAPACITY: usize = 64;
+
+/// A new head pushed to a branch
+#[derive(Clone, Debug, Serialize)]
+pub struct RootEvent {
+    pub branch: String,
+    pub cid: String,
+    pub previous_cid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+pub fn root_event_channel() -> broadcast::Sender<RootEvent> {
+    let (sender, _) = broadcast::channel(ROOT_EVENT_CAPACITY);
+    sender
+}
@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+
+use cid::Cid;
+use time::OffsetDateTime;
+
+use crate::app::{AppState, Config};
+use crate::database::models::{
+    PinStatus, RootCid, RootCidError, RootPin, RootPinError, RootRef, RootRefError,
+};
+use crate::database::DatabaseConnection;
+use crate::ipfs::{walk_manifest, IpfsRpcError, PinMode};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Which roots the server keeps pinned. A root is kept if it satisfies either limit, and the
+/// most recent root is always kept, as is the head of every branch and any root named by a ref.
+/// Without any limits every root is kept forever.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetentionPolicy {
+    keep_last: Option<usize>,
+    keep_days: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn new(keep_last: Option<usize>, keep_days: Option<u64>) -> Self {
+        Self {
+            keep_last,
+            keep_days,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.retention_keep_last(), config.retention_keep_days())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.keep_last.is_some() || self.keep_days.is_some()
+    }
+
+    /// Split a history of roots, newest first and paired with when they were pushed, into the
+    /// roots to keep and the roots that have expired
+    pub fn partition(&self, history: &[(Cid, i64)], now: i64) -> (Vec<Cid>, Vec<Cid>) {
+        if !self.is_enabled() {
+            return (history.iter().map(|(cid, _)| *cid).collect(), Vec::new());
+        }
+
+        let mut kept = Vec::new();
+        let mut expired = Vec::new();
+        for (index, (cid, pushed_at)) in history.iter().enumerate() {
+            let within_last = self.keep_last.is_some_and(|count| index < count);
+            let within_days = self
+                .keep_days
+                .is_some_and(|days| now - pushed_at < days as i64 * SECONDS_PER_DAY);
+            if index == 0 || within_last || within_days {
+                kept.push(*cid);
+            } else {
+                expired.push(*cid);
+            }
+        }
+        (kept, expired)
+    }
+}
+
+/// What a garbage collection pass would reclaim
+#[derive(Debug, Default)]
+pub struct GcPlan {
+    /// Pinned roots that have expired under the retention policy
+    pub expired_roots: Vec<Cid>,
+    /// Blocks only reachable from expired roots, and how they were pinned
+    pub blocks: Vec<(Cid, PinMode)>,
+}
+
+/// Work out which blocks could be unpinned without touching anything a kept root still needs
+pub async fn plan(state: &AppState) -> Result<GcPlan, GcError> {
+    let policy = state.retention_policy();
+    if !policy.is_enabled() {
+        return Ok(GcPlan::default());
+    }
+
+    let mut conn = state.sqlite_database().acquire().await?;
+    let history = RootCid::history(&mut conn).await?;
+    let (kept, expired) = policy.partition(&history, OffsetDateTime::now_utc().unix_timestamp());
+
+    // Branch heads are still in use, and refs are how a version gets marked as worth keeping,
+    // whatever their age
+    let mut named = RootRef::list(&mut conn)
+        .await?
+        .iter()
+        .map(RootRef::cid)
+        .collect::<HashSet<_>>();
+    named.extend(RootCid::branches(&mut conn).await?.iter().map(RootCid::cid));
+    let (named_expired, expired): (Vec<_>, Vec<_>) =
+        expired.into_iter().partition(|cid| named.contains(cid));
+    let kept = kept.into_iter().chain(named_expired);
+
+    // Only roots we're holding pins for matter. A kept root that's still pending may share blocks
+    // with an expired one, so it needs protecting as much as a pinned one does.
+    let mut protected = Vec::new();
+    for cid in kept {
+        if let Some(PinStatus::Pinned | PinStatus::Pending) = pin_status(&cid, &mut conn).await? {
+            protected.push(cid);
+        }
+    }
+    let mut pinned_expired = Vec::new();
+    for cid in expired {
+        if let Some(PinStatus::Pinned) = pin_status(&cid, &mut conn).await? {
+            pinned_expired.push(cid);
+        }
+    }
+    drop(conn);
+
+    let mut plan = GcPlan::default();
+    if pinned_expired.is_empty() {
+        return Ok(plan);
+    }
+
+    // Anything a protected root can reach is off limits. If any of them can't be walked we have
+    // no way of knowing what it needs, so nothing is safe to reclaim.
+    let ipfs_rpc = state.ipfs_rpc();
+    let mut seen = HashSet::new();
+    let mut kept_blocks = Vec::new();
+    for cid in &protected {
+        walk_manifest(ipfs_rpc, cid, &mut seen, &mut kept_blocks).await?;
+    }
+
+    // Walking the expired roots with the kept blocks already seen leaves only what they alone
+    // reference
+    for cid in pinned_expired {
+        let mut root_seen = seen.clone();
+        let mut root_blocks = Vec::new();
+        match walk_manifest(ipfs_rpc, &cid, &mut root_seen, &mut root_blocks).await {
+            Ok(()) => {
+                seen = root_seen;
+                plan.expired_roots.push(cid);
+                plan.blocks.extend(root_blocks);
+            }
+            Err(err) => tracing::warn!(%cid, "skipping expired root: {err}"),
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Run a garbage collection pass, unpinning every block only reachable from expired roots
+pub async fn collect(state: &AppState) -> Result<GcPlan, GcError> {
+    // Hold off replication for the duration, so a new push can't pin a block we're about to
+    // unpin out from under it
+    let _guard = state.gc_lock().write().await;
+
+    let plan = plan(state).await?;
+    let ipfs_rpc = state.ipfs_rpc();
+    for (cid, mode) in &plan.blocks {
+        // A block may have already been unpinned by hand, which isn't worth failing over
+        if let Err(err) = ipfs_rpc.pin_rm(cid, *mode == PinMode::Recursive).await {
+            tracing::warn!(%cid, "failed to unpin block: {err}");
+        }
+    }
+
+    let mut conn = state.sqlite_database().acquire().await?;
+    for cid in &plan.expired_roots {
+        RootPin::update(cid, PinStatus::Reclaimed, None, &mut conn).await?;
+    }
+
+    Ok(plan)
+}
+
+async fn pin_status(
+    cid: &Cid,
+    conn: &mut DatabaseConnection,
+) -> Result<Option<PinStatus>, GcError> {
+    let root_pin = RootPin::read(cid, conn).await?;
+    Ok(root_pin.map(|root_pin| root_pin.status()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GcError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("root CID error: {0}")]
+    RootCid(#[from] RootCidError),
+    #[error("root pin error: {0}")]
+    RootPin(#[from] RootPinError),
+    #[error("root ref error: {0}")]
+    RootRef(#[from] RootRefError),
+    #[error("ipfs error: {0}")]
+    Ipfs(#[from] IpfsRpcError),
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Multihash;
+
+    use super::*;
+
+    fn history(ages_in_days: &[i64], now: i64) -> Vec<(Cid, i64)> {
+        ages_in_days
+            .iter()
+            .enumerate()
+            .map(|(index, age)| {
+                let hash = Multihash::wrap(0x12, &[index as u8; 32]).unwrap();
+                (Cid::new_v1(0x71, hash), now - age * SECONDS_PER_DAY)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_partition_disabled_keeps_everything() {
+        let now = 1_000 * SECONDS_PER_DAY;
+        let history = history(&[0, 10, 100], now);
+        let (kept, expired) = RetentionPolicy::default().partition(&history, now);
+        assert_eq!(kept.len(), 3);
+        assert!(expired.is_empty());
+    }
+
+    #[test]
+    fn test_partition_by_count_and_age() {
+        let now = 1_000 * SECONDS_PER_DAY;
+        let history = history(&[0, 1, 2, 5, 40, 90], now);
+
+        let (kept, expired) = RetentionPolicy::new(Some(2), None).partition(&history, now);
+        assert_eq!(kept, vec![history[0].0, history[1].0]);
+        assert_eq!(expired.len(), 4);
+
+        let (kept, expired) = RetentionPolicy::new(None, Some(30)).partition(&history, now);
+        assert_eq!(kept.len(), 4);
+        assert_eq!(expired, vec![history[4].0, history[5].0]);
+
+        // Either limit is enough to keep a root
+        let (kept, _) = RetentionPolicy::new(Some(5), Some(3)).partition(&history, now);
+        assert_eq!(kept.len(), 5);
+    }
+
+    #[test]
+    fn test_partition_always_keeps_current_root() {
+        let now = 1_000 * SECONDS_PER_DAY;
+        let history = history(&[400, 500], now);
+        let (kept, expired) = RetentionPolicy::new(Some(0), Some(1)).partition(&history, now);
+        assert_eq!(kept, vec![history[0].0]);
+        assert_eq!(expired, vec![history[1].0]);
+    }
+}
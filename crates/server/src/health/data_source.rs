@@ -1,24 +1,43 @@
 #![allow(dead_code)]
 
+use std::collections::BTreeMap;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::async_trait;
 use axum::extract::{FromRef, FromRequestParts};
 use http::request::Parts;
+use serde::Serialize;
 
+use crate::app::AppState;
 use crate::database::Database;
+use crate::ipfs::IpfsRpc;
+
+/// How long we're willing to wait on the IPFS node before declaring it unavailable
+const IPFS_READINESS_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyStatus {
+    Ok,
+    Failure,
+    Timeout,
+}
+
+/// Status of each dependency a data source checked, keyed by dependency name
+pub type DependencyReport = BTreeMap<&'static str, DependencyStatus>;
 
 #[async_trait]
 pub trait DataSource {
     /// Perform various checks on the system to ensure its healthy and ready to accept requests.
-    async fn is_ready(&self) -> Result<(), DataSourceError>;
+    async fn is_ready(&self) -> Result<DependencyReport, DataSourceError>;
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum DataSourceError {
     #[error("one or more dependent services aren't available")]
-    DependencyFailure,
+    DependencyFailure(DependencyReport),
 
     #[error("service has received signal indicating it should shutdown")]
     ShuttingDown,
@@ -43,33 +62,122 @@ impl Deref for StateDataSource {
     }
 }
 
+/// Wrap the status of a single named dependency into a report
+fn report(
+    name: &'static str,
+    status: DependencyStatus,
+) -> Result<DependencyReport, DataSourceError> {
+    let report = DependencyReport::from([(name, status)]);
+    match status {
+        DependencyStatus::Ok => Ok(report),
+        _ => Err(DataSourceError::DependencyFailure(report)),
+    }
+}
+
 struct DbSource {
     db: Database,
 }
 
 #[async_trait]
 impl DataSource for DbSource {
-    async fn is_ready(&self) -> Result<(), DataSourceError> {
-        let _ = sqlx::query("SELECT 1 as id;")
+    async fn is_ready(&self) -> Result<DependencyReport, DataSourceError> {
+        let status = match sqlx::query("SELECT 1 as id;")
             .fetch_one(self.db.deref())
             .await
-            .map_err(|_| DataSourceError::DependencyFailure)?;
+        {
+            Ok(_) => DependencyStatus::Ok,
+            Err(err) => {
+                tracing::warn!("database readiness check failed: {err}");
+                DependencyStatus::Failure
+            }
+        };
+
+        report("database", status)
+    }
+}
+
+struct IpfsSource {
+    ipfs_rpc: IpfsRpc,
+}
+
+#[async_trait]
+impl DataSource for IpfsSource {
+    async fn is_ready(&self) -> Result<DependencyReport, DataSourceError> {
+        let status =
+            match tokio::time::timeout(IPFS_READINESS_TIMEOUT, self.ipfs_rpc.version()).await {
+                Ok(Ok(_)) => DependencyStatus::Ok,
+                Ok(Err(err)) => {
+                    tracing::warn!("ipfs readiness check failed: {err}");
+                    DependencyStatus::Failure
+                }
+                Err(_) => {
+                    tracing::warn!("ipfs readiness check timed out");
+                    DependencyStatus::Timeout
+                }
+            };
+
+        report("ipfs", status)
+    }
+}
+
+/// Checks all of its sources concurrently, unless the service is already on its way out
+struct CompositeSource {
+    sources: Vec<DynDataSource>,
+    shutting_down: bool,
+}
 
-        Ok(())
+#[async_trait]
+impl DataSource for CompositeSource {
+    async fn is_ready(&self) -> Result<DependencyReport, DataSourceError> {
+        if self.shutting_down {
+            return Err(DataSourceError::ShuttingDown);
+        }
+
+        let results =
+            futures::future::join_all(self.sources.iter().map(|source| source.is_ready())).await;
+
+        let mut dependencies = DependencyReport::new();
+        let mut healthy = true;
+        for result in results {
+            match result {
+                Ok(report) => dependencies.extend(report),
+                Err(DataSourceError::DependencyFailure(report)) => {
+                    healthy = false;
+                    dependencies.extend(report);
+                }
+                Err(DataSourceError::ShuttingDown) => return Err(DataSourceError::ShuttingDown),
+            }
+        }
+
+        if healthy {
+            Ok(dependencies)
+        } else {
+            Err(DataSourceError::DependencyFailure(dependencies))
+        }
     }
 }
 
 #[async_trait]
 impl<S> FromRequestParts<S> for StateDataSource
 where
-    Database: FromRef<S>,
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = ();
 
     async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        Ok(StateDataSource(Arc::new(DbSource {
-            db: Database::from_ref(state),
+        let state = AppState::from_ref(state);
+
+        Ok(StateDataSource(Arc::new(CompositeSource {
+            sources: vec![
+                Arc::new(DbSource {
+                    db: state.sqlite_database().clone(),
+                }),
+                Arc::new(IpfsSource {
+                    ipfs_rpc: state.ipfs_rpc().clone(),
+                }),
+            ],
+            shutting_down: state.is_shutting_down(),
         })))
     }
 }
@@ -87,14 +195,61 @@ pub(crate) mod tests {
 
     #[async_trait]
     impl DataSource for MockReadiness {
-        async fn is_ready(&self) -> Result<(), DataSourceError> {
+        async fn is_ready(&self) -> Result<DependencyReport, DataSourceError> {
             use MockReadiness::*;
 
             match self {
-                DependencyFailure => Err(DataSourceError::DependencyFailure),
-                Ready => Ok(()),
+                DependencyFailure => report("mock", DependencyStatus::Failure),
+                Ready => report("mock", DependencyStatus::Ok),
                 ShuttingDown => Err(DataSourceError::ShuttingDown),
             }
         }
     }
+
+    struct NamedMock(&'static str, DependencyStatus);
+
+    #[async_trait]
+    impl DataSource for NamedMock {
+        async fn is_ready(&self) -> Result<DependencyReport, DataSourceError> {
+            report(self.0, self.1)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_composite_source() {
+        let ready = CompositeSource {
+            sources: vec![
+                Arc::new(NamedMock("database", DependencyStatus::Ok)),
+                Arc::new(NamedMock("ipfs", DependencyStatus::Ok)),
+            ],
+            shutting_down: false,
+        };
+        let dependencies = ready.is_ready().await.unwrap();
+        assert_eq!(dependencies.get("database"), Some(&DependencyStatus::Ok));
+        assert_eq!(dependencies.get("ipfs"), Some(&DependencyStatus::Ok));
+
+        let degraded = CompositeSource {
+            sources: vec![
+                Arc::new(NamedMock("database", DependencyStatus::Ok)),
+                Arc::new(NamedMock("ipfs", DependencyStatus::Timeout)),
+            ],
+            shutting_down: false,
+        };
+        match degraded.is_ready().await {
+            Err(DataSourceError::DependencyFailure(dependencies)) => {
+                assert_eq!(dependencies.get("database"), Some(&DependencyStatus::Ok));
+                assert_eq!(dependencies.get("ipfs"), Some(&DependencyStatus::Timeout));
+            }
+            _ => panic!("expected a dependency failure"),
+        }
+
+        let shutting_down = CompositeSource {
+            sources: vec![Arc::new(NamedMock("database", DependencyStatus::Ok))],
+            shutting_down: true,
+        };
+        assert!(matches!(
+            shutting_down.is_ready().await,
+            Err(DataSourceError::ShuttingDown)
+        ));
+    }
 }
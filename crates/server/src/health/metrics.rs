@@ -0,0 +1,25 @@
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use http::header::CONTENT_TYPE;
+use http::StatusCode;
+
+use crate::app::AppState;
+
+/// Exposes the server's metrics in the Prometheus text format for scraping
+pub async fn handler(State(state): State<AppState>) -> Response {
+    let metrics = state.metrics();
+    metrics.observe_db_pool(state.sqlite_database());
+
+    match metrics.encode() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            body,
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::error!("failed to encode metrics: {err}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "unknown server error").into_response()
+        }
+    }
+}
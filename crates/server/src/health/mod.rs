@@ -7,7 +7,7 @@ use tower_http::limit::RequestBodyLimitLayer;
 
 mod data_source;
 mod liveness;
-// TODO: readiness for ipfs as well as database
+mod metrics;
 mod readiness;
 mod version;
 
@@ -27,6 +27,7 @@ pub fn router(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/healthz", get(liveness::handler))
         .route("/readyz", get(readiness::handler))
+        .route("/metrics", get(metrics::handler))
         .route("/version", get(version::handler))
         .with_state(state)
         .layer(cors_layer)
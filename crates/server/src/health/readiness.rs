@@ -6,12 +6,16 @@ use super::data_source::*;
 
 pub async fn handler(data_src: StateDataSource) -> Response {
     match data_src.is_ready().await {
-        Ok(_) => {
-            let msg = serde_json::json!({"status": "ok"});
+        Ok(dependencies) => {
+            let msg = serde_json::json!({"status": "ok", "dependencies": dependencies});
             (StatusCode::OK, Json(msg)).into_response()
         }
-        Err(DataSourceError::DependencyFailure) => {
-            let msg = serde_json::json!({"status": "failure", "message": "one or more dependencies aren't available"});
+        Err(DataSourceError::DependencyFailure(dependencies)) => {
+            let msg = serde_json::json!({
+                "status": "failure",
+                "message": "one or more dependencies aren't available",
+                "dependencies": dependencies,
+            });
             (StatusCode::SERVICE_UNAVAILABLE, Json(msg)).into_response()
         }
         Err(DataSourceError::ShuttingDown) => {
@@ -41,6 +45,12 @@ mod tests {
         .await;
         assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
 
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["dependencies"]["mock"], "failure");
+
         let response = handler(StateDataSource::new(Arc::new(MockReadiness::ShuttingDown))).await;
         assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
     }
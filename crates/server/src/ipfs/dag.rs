@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use cid::Cid;
+use leaky_common::prelude::{Codec, Ipld, IpldCodec};
+use serde_json::Value;
+
+use super::{IpfsRpc, IpfsRpcError};
+
+/// Multicodec for DAG-CBOR, which is what manifests and nodes are encoded with. Links to any other
+/// codec point at file data (raw leaves, or dag-pb for chunked files).
+pub(super) const DAG_CBOR_CODEC: u64 = 0x71;
+
+/// Reserved node key holding the objects attached to a node's links
+pub(super) const METADATA_KEY: &str = ".metadata";
+
+/// How a block needs to be pinned in order to keep a bucket available
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinMode {
+    /// Manifests and nodes. Their links are walked explicitly, since pinning a manifest
+    /// recursively would drag in every previous version of the bucket.
+    Direct,
+    /// File data, which should be pinned along with all of its chunks
+    Recursive,
+}
+
+/// Walk the DAG of a manifest, returning every block needed to read the bucket at that version:
+/// the manifest itself, its nodes, and the file data the nodes link to.
+pub async fn manifest_blocks(
+    ipfs_rpc: &IpfsRpc,
+    manifest_cid: &Cid,
+) -> Result<Vec<(Cid, PinMode)>, IpfsRpcError> {
+    let mut blocks = Vec::new();
+    walk_manifest(ipfs_rpc, manifest_cid, &mut HashSet::new(), &mut blocks).await?;
+    Ok(blocks)
+}
+
+/// Like [`manifest_blocks`], but skipping any block already in `seen` along with everything
+/// beneath it. Versions of a bucket share most of their DAG, so threading one set through several
+/// walks only visits the parts that changed.
+pub async fn walk_manifest(
+    ipfs_rpc: &IpfsRpc,
+    manifest_cid: &Cid,
+    seen: &mut HashSet<Cid>,
+    blocks: &mut Vec<(Cid, PinMode)>,
+) -> Result<(), IpfsRpcError> {
+    if !seen.insert(*manifest_cid) {
+        return Ok(());
+    }
+
+    let data_cid = manifest_data(ipfs_rpc, manifest_cid).await?;
+
+    blocks.push((*manifest_cid, PinMode::Direct));
+    let mut stack = vec![data_cid];
+
+    while let Some(node_cid) = stack.pop() {
+        if !seen.insert(node_cid) {
+            continue;
+        }
+        blocks.push((node_cid, PinMode::Direct));
+
+        let node = ipfs_rpc.dag_get(&node_cid).await?;
+        let links = node
+            .as_object()
+            .ok_or(IpfsRpcError::MalformedBlock(node_cid, "node is not a map"))?;
+        for (name, value) in links {
+            if name == METADATA_KEY {
+                continue;
+            }
+            let link = match as_link(value) {
+                Some(link) => link?,
+                None => continue,
+            };
+            if link.codec() == DAG_CBOR_CODEC {
+                stack.push(link);
+            } else if seen.insert(link) {
+                blocks.push((link, PinMode::Recursive));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch a manifest and return the CID of its root node
+pub(super) async fn manifest_data(
+    ipfs_rpc: &IpfsRpc,
+    manifest_cid: &Cid,
+) -> Result<Cid, IpfsRpcError> {
+    let manifest = ipfs_rpc.dag_get(manifest_cid).await?;
+    match manifest.get("data").and_then(as_link) {
+        Some(link) => link,
+        None => Err(IpfsRpcError::MalformedBlock(
+            *manifest_cid,
+            "missing data link",
+        )),
+    }
+}
+
+/// Read a DAG-JSON value the way leaky reads the block it came from
+pub(super) fn to_ipld(value: &Value) -> Option<Ipld> {
+    let bytes = serde_json::to_vec(value).ok()?;
+    IpldCodec::DagJson.decode(&bytes).ok()
+}
+
+/// Read a DAG-JSON link, which is encoded as `{"/": "<cid>"}`
+pub(super) fn as_link(value: &Value) -> Option<Result<Cid, IpfsRpcError>> {
+    let object = value.as_object()?;
+    if object.len() != 1 {
+        return None;
+    }
+    let cid = object.get("/")?.as_str()?;
+    Some(Cid::from_str(cid).map_err(IpfsRpcError::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_as_link() {
+        let cid = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku";
+        let link = serde_json::json!({ "/": cid });
+        assert_eq!(as_link(&link).unwrap().unwrap().to_string(), cid);
+
+        // DAG-JSON bytes share the "/" key but aren't links
+        let bytes = serde_json::json!({ "/": { "bytes": "aGVsbG8" } });
+        assert!(as_link(&bytes).is_none());
+
+        let map = serde_json::json!({ "/": cid, "other": 1 });
+        assert!(as_link(&map).is_none());
+    }
+}
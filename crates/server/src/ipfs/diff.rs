@@ -0,0 +1,69 @@
+use cid::Cid;
+use leaky_common::prelude::{diff_trees, Cid as LeakyCid, Node, NodeSource};
+
+use super::dag::{manifest_data, to_ipld};
+use super::{IpfsRpc, IpfsRpcError};
+
+/// A difference between two versions of a bucket, with paths as they're written in the API
+pub type ManifestChange = leaky_common::prelude::ManifestChange<String, Cid>;
+
+/// Nodes are read as DAG-JSON and decoded the way leaky reads the blocks they came from
+impl NodeSource for IpfsRpc {
+    type Error = IpfsRpcError;
+
+    async fn node(&self, cid: &LeakyCid) -> Result<Node, IpfsRpcError> {
+        let cid = from_leaky(cid)?;
+        let node = self.dag_get(&cid).await?;
+        to_ipld(&node)
+            .and_then(|node| Node::try_from(node).ok())
+            .ok_or(IpfsRpcError::MalformedBlock(cid, "node is not a map"))
+    }
+}
+
+/// Leaky's own CIDs come from an older version of the `cid` crate, so they cross over as bytes
+pub(super) fn to_leaky(cid: &Cid) -> Result<LeakyCid, IpfsRpcError> {
+    LeakyCid::try_from(cid.to_bytes().as_slice())
+        .map_err(|_| IpfsRpcError::MalformedBlock(*cid, "unsupported cid"))
+}
+
+pub(super) fn from_leaky(cid: &LeakyCid) -> Result<Cid, IpfsRpcError> {
+    Ok(Cid::try_from(cid.to_bytes().as_slice())?)
+}
+
+/// Compare two versions of a bucket path by path, walking both trees together. Subtrees with the
+/// same CID on both sides are skipped, so only the parts that changed are fetched.
+pub async fn diff_manifests(
+    ipfs_rpc: &IpfsRpc,
+    from: &Cid,
+    to: &Cid,
+) -> Result<Vec<ManifestChange>, IpfsRpcError> {
+    let from_data = to_leaky(&manifest_data(ipfs_rpc, from).await?)?;
+    let to_data = to_leaky(&manifest_data(ipfs_rpc, to).await?)?;
+
+    diff_trees(ipfs_rpc, &from_data, &to_data)
+        .await?
+        .into_iter()
+        .map(|change| {
+            change.try_map(
+                |path| path.to_string_lossy().into_owned(),
+                |cid| from_leaky(&cid),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_cid_conversion() {
+        let cid =
+            Cid::from_str("bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku").unwrap();
+        let leaky_cid = to_leaky(&cid).unwrap();
+        assert_eq!(leaky_cid.to_string(), cid.to_string());
+        assert_eq!(from_leaky(&leaky_cid).unwrap(), cid);
+    }
+}
@@ -0,0 +1,150 @@
+mod dag;
+mod diff;
+mod schema;
+
+use std::time::{Duration, Instant};
+
+use cid::Cid;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use url::Url;
+
+use crate::metrics::Metrics;
+
+pub use dag::{manifest_blocks, walk_manifest, PinMode};
+pub use diff::{diff_manifests, ManifestChange};
+pub use schema::schema_violations;
+
+/// How long a read may take. Without a limit, asking for a block the node can't find keeps the
+/// request open for as long as the node searches the network for it.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Minimal client for the Kubo RPC API of the IPFS node backing this server
+#[derive(Clone)]
+pub struct IpfsRpc {
+    base_url: Url,
+    client: reqwest::Client,
+    metrics: Metrics,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    #[serde(rename = "Version")]
+    version: String,
+}
+
+impl IpfsRpc {
+    pub fn new(base_url: Url, metrics: Metrics) -> Result<Self, IpfsRpcError> {
+        let client = reqwest::Client::builder()
+            .build()
+            .map_err(IpfsRpcError::Client)?;
+        Ok(Self {
+            base_url,
+            client,
+            metrics,
+        })
+    }
+
+    /// Report the version of the IPFS node. Cheap enough to be used as a probe
+    pub async fn version(&self) -> Result<String, IpfsRpcError> {
+        let response: VersionResponse = self.call("version", &[], Some(READ_TIMEOUT)).await?;
+        Ok(response.version)
+    }
+
+    /// Fetch a block from the network and decode it as DAG-JSON
+    pub async fn dag_get(&self, cid: &Cid) -> Result<serde_json::Value, IpfsRpcError> {
+        let cid = cid.to_string();
+        self.call(
+            "dag/get",
+            &[("arg", &cid), ("output-codec", "dag-json")],
+            Some(READ_TIMEOUT),
+        )
+        .await
+    }
+
+    /// Pin a block on the node, along with everything it links to if `recursive` is set. Pinning a
+    /// large root takes a while, so pins aren't given a timeout.
+    pub async fn pin_add(&self, cid: &Cid, recursive: bool) -> Result<(), IpfsRpcError> {
+        let cid = cid.to_string();
+        let recursive = recursive.to_string();
+        let _: serde_json::Value = self
+            .call("pin/add", &[("arg", &cid), ("recursive", &recursive)], None)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove a pin from the node. The blocks stay in its repo until the node garbage collects.
+    pub async fn pin_rm(&self, cid: &Cid, recursive: bool) -> Result<(), IpfsRpcError> {
+        let cid = cid.to_string();
+        let recursive = recursive.to_string();
+        let _: serde_json::Value = self
+            .call("pin/rm", &[("arg", &cid), ("recursive", &recursive)], None)
+            .await?;
+        Ok(())
+    }
+
+    /// Call an RPC method, returning its JSON response. All Kubo RPC methods are POSTs.
+    async fn call<T>(
+        &self,
+        method: &str,
+        args: &[(&str, &str)],
+        timeout: Option<Duration>,
+    ) -> Result<T, IpfsRpcError>
+    where
+        T: DeserializeOwned,
+    {
+        let start = Instant::now();
+        let result = self.call_inner(method, args, timeout).await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        self.metrics
+            .record_ipfs_request(method, outcome, start.elapsed());
+        result
+    }
+
+    async fn call_inner<T>(
+        &self,
+        method: &str,
+        args: &[(&str, &str)],
+        timeout: Option<Duration>,
+    ) -> Result<T, IpfsRpcError>
+    where
+        T: DeserializeOwned,
+    {
+        let url = self.base_url.join(&format!("api/v0/{}", method))?;
+        let mut request = self.client.post(url).query(args);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let timed_out = |err: reqwest::Error| {
+            if err.is_timeout() {
+                IpfsRpcError::Timeout(method.to_string())
+            } else {
+                IpfsRpcError::Client(err)
+            }
+        };
+        let response = request.send().await.map_err(timed_out)?;
+        if !response.status().is_success() {
+            return Err(IpfsRpcError::Api(
+                response.status(),
+                response.text().await.map_err(timed_out)?,
+            ));
+        }
+        response.json::<T>().await.map_err(timed_out)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IpfsRpcError {
+    #[error("url parse error: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("http client error: {0}")]
+    Client(#[from] reqwest::Error),
+    #[error("ipfs rpc timed out: {0}")]
+    Timeout(String),
+    #[error("ipfs rpc error: {0} {1}")]
+    Api(reqwest::StatusCode, String),
+    #[error("invalid cid: {0}")]
+    Cid(#[from] cid::Error),
+    #[error("malformed block {0}: {1}")]
+    MalformedBlock(Cid, &'static str),
+}
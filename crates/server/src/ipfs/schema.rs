@@ -0,0 +1,148 @@
+use cid::Cid;
+use serde_json::Value;
+
+use leaky_common::prelude::{walk_diff, Ipld, Schema};
+
+use super::dag::{manifest_data, to_ipld};
+use super::diff::to_leaky;
+use super::{IpfsRpc, IpfsRpcError};
+
+/// The schema a manifest carries, if it has one
+fn manifest_schema(manifest_cid: &Cid, manifest: &Value) -> Result<Option<Schema>, IpfsRpcError> {
+    let Some(schema) = manifest.get("schema") else {
+        return Ok(None);
+    };
+    to_ipld(schema)
+        .and_then(|schema| Schema::try_from(schema).ok())
+        .map(Some)
+        .ok_or(IpfsRpcError::MalformedBlock(
+            *manifest_cid,
+            "invalid schema",
+        ))
+}
+
+/// What's wrong with the metadata of the object at `path` under any of `schemas`, if anything
+fn violations_of(schemas: &[Schema], path: &str, metadata: &Ipld) -> Vec<String> {
+    let Ipld::Map(metadata) = metadata else {
+        return vec![format!("{path}: metadata is not a map")];
+    };
+    let mut violations = Vec::new();
+    for schema in schemas {
+        for err in schema.violations(metadata) {
+            let violation = format!("{path}: {err}");
+            if !violations.contains(&violation) {
+                violations.push(violation);
+            }
+        }
+    }
+    violations
+}
+
+/// Check the files and directories a new root adds or changes since `from` against the schema
+/// `to` carries, returning what's wrong with them. They're checked against the schema `from`
+/// carries too, so a push can't drop or loosen the schema to let its own objects through; a
+/// schema change has to be pushed before the metadata that relies on it. Objects left as they
+/// were aren't checked, so a schema only applies to metadata set after it, and without a `from`
+/// every object is checked. Subtrees with the same CID on both sides are skipped.
+pub async fn schema_violations(
+    ipfs_rpc: &IpfsRpc,
+    from: Option<&Cid>,
+    to: &Cid,
+) -> Result<Vec<String>, IpfsRpcError> {
+    let mut schemas = Vec::new();
+    if let Some(from) = from {
+        let manifest = ipfs_rpc.dag_get(from).await?;
+        schemas.extend(manifest_schema(from, &manifest)?);
+    }
+    let manifest = ipfs_rpc.dag_get(to).await?;
+    schemas.extend(manifest_schema(to, &manifest)?);
+    schemas.dedup();
+    if schemas.is_empty() {
+        return Ok(Vec::new());
+    }
+    let from_data = match from {
+        Some(from) => Some(to_leaky(&manifest_data(ipfs_rpc, from).await?)?),
+        None => None,
+    };
+    let to_data = to_leaky(&manifest_data(ipfs_rpc, to).await?)?;
+
+    let mut violations = Vec::new();
+    walk_diff(ipfs_rpc, from_data, Some(to_data), |path, from, to| {
+        let Some(to) = to else {
+            return;
+        };
+        let unchanged = from
+            .is_some_and(|from| (to.is_dir() || from.link == to.link) && from.same_metadata(to));
+        if let (false, Some(metadata)) = (unchanged, &to.metadata) {
+            violations.extend(violations_of(&schemas, &path.to_string_lossy(), metadata));
+        }
+    })
+    .await?;
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_schema_violations() {
+        let manifest_cid = Cid::default();
+        let manifest = json!({
+            "schema": { "fields": { "author": "string", "score": "float" }, "open": false }
+        });
+        let schemas = [manifest_schema(&manifest_cid, &manifest).unwrap().unwrap()];
+
+        let ok = to_ipld(&json!({ "author": "al", "score": 3 })).unwrap();
+        assert!(violations_of(&schemas, "/a", &ok).is_empty());
+
+        let bad =
+            to_ipld(&json!({ "autor": "al", "score": { "/": { "bytes": "aGVsbG8" } } })).unwrap();
+        assert_eq!(
+            violations_of(&schemas, "/a", &bad),
+            vec![
+                "/a: autor isn't a key the schema allows".to_string(),
+                "/a: score should be float, not bytes".to_string(),
+            ]
+        );
+        assert_eq!(
+            violations_of(&schemas, "/a", &to_ipld(&json!(["al"])).unwrap()),
+            vec!["/a: metadata is not a map".to_string()]
+        );
+
+        assert!(manifest_schema(&manifest_cid, &json!({ "data": {} }))
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            manifest_schema(
+                &manifest_cid,
+                &json!({ "schema": { "fields": { "a": "word" } } })
+            ),
+            Err(IpfsRpcError::MalformedBlock(..))
+        ));
+    }
+
+    #[test]
+    fn test_violations_of_every_schema() {
+        let manifest_cid = Cid::default();
+        let head = json!({ "schema": { "fields": { "author": "string" }, "open": false } });
+        let pushed = json!({ "schema": { "fields": {}, "open": true } });
+        let schemas = [
+            manifest_schema(&manifest_cid, &head).unwrap().unwrap(),
+            manifest_schema(&manifest_cid, &pushed).unwrap().unwrap(),
+        ];
+
+        // Loosening the schema doesn't let the push's own metadata through
+        let metadata = to_ipld(&json!({ "autor": "al" })).unwrap();
+        assert_eq!(
+            violations_of(&schemas, "/a", &metadata),
+            vec!["/a: autor isn't a key the schema allows".to_string()]
+        );
+
+        // The same problem under both schemas is only reported once
+        let schemas = [schemas[0].clone(), schemas[0].clone()];
+        assert_eq!(violations_of(&schemas, "/a", &metadata).len(), 1);
+    }
+}
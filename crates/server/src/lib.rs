@@ -1,9 +1,16 @@
+mod admin;
 mod api;
 pub mod app;
 mod database;
+mod events;
+mod gc;
 mod health;
+mod ipfs;
+mod metrics;
+mod replication;
 mod server;
 mod version;
+mod webhooks;
 
 use std::time::Duration;
 
@@ -51,6 +58,56 @@ pub async fn server(
     })
 }
 
+/// Periodically unpins blocks only reachable from roots that have expired under the retention
+/// policy. Nothing is spawned if no policy is configured.
+pub fn garbage_collector(
+    config: &crate::app::Config,
+    state: crate::app::AppState,
+    mut shutdown_rx: watch::Receiver<()>,
+) -> Option<JoinHandle<()>> {
+    if !state.retention_policy().is_enabled() {
+        tracing::info!("no retention policy configured, garbage collection disabled");
+        return None;
+    }
+
+    let period = config.gc_interval();
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown_rx.changed() => break,
+            }
+
+            match crate::gc::collect(&state).await {
+                Ok(plan) => tracing::info!(
+                    roots = plan.expired_roots.len(),
+                    blocks = plan.blocks.len(),
+                    "garbage collection complete"
+                ),
+                Err(err) => tracing::error!("garbage collection failed: {err}"),
+            }
+        }
+    }))
+}
+
+/// Picks back up any replication jobs that were interrupted the last time the server stopped.
+pub async fn resume_replication(state: &crate::app::AppState) {
+    if let Err(err) = crate::replication::resume_replication(state).await {
+        tracing::error!("failed to resume replication: {err}");
+    }
+}
+
+/// Picks back up any webhook deliveries that were still being retried the last time the server
+/// stopped.
+pub async fn resume_webhooks(state: &crate::app::AppState) {
+    if let Err(err) = crate::webhooks::resume_webhooks(state).await {
+        tracing::error!("failed to resume webhooks: {err}");
+    }
+}
+
 /// Sets up system panics to use the tracing infrastructure to log reported issues. This doesn't
 /// prevent the panic from taking out the service but ensures that it and any available information
 /// is properly reported using the standard logging mechanism.
@@ -38,8 +38,10 @@ async fn main() {
     leaky_server::register_panic_logger();
     leaky_server::report_version();
 
+    let (graceful_waiter, shutdown_rx) = leaky_server::graceful_shutdown_blocker();
+
     // Create the app state
-    let state = match AppState::from_config(&config).await {
+    let state = match AppState::from_config(&config, shutdown_rx.clone()).await {
         Ok(state) => state,
         Err(e) => {
             eprintln!("Error creating app state: {}", e);
@@ -47,9 +49,15 @@ async fn main() {
         }
     };
 
-    let (graceful_waiter, shutdown_rx) = leaky_server::graceful_shutdown_blocker();
+    leaky_server::resume_replication(&state).await;
+    leaky_server::resume_webhooks(&state).await;
+
     let mut handles = Vec::new();
 
+    if let Some(gc) = leaky_server::garbage_collector(&config, state.clone(), shutdown_rx.clone()) {
+        handles.push(gc);
+    }
+
     let server = leaky_server::server(config, state, shutdown_rx).await;
     handles.push(server);
 
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::database::Database;
+
+const METRICS_NAMESPACE: &str = "leaky";
+
+/// Label used for requests that didn't match any route, so arbitrary paths can't blow up the
+/// cardinality of our metrics
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Label every metric carries, naming the bucket the server hosts
+const BUCKET_LABEL: &str = "bucket";
+
+/// Prometheus collectors for the server, registered against their own registry. A server hosts
+/// a single bucket, so everything it records is scoped to that bucket by a `bucket` label.
+#[derive(Clone)]
+pub struct Metrics(Arc<MetricsInner>);
+
+struct MetricsInner {
+    registry: Registry,
+
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+
+    root_pushes: IntCounterVec,
+    root_pulls: IntCounterVec,
+
+    db_pool_connections: IntGauge,
+    db_pool_idle_connections: IntGauge,
+    db_pool_max_connections: IntGauge,
+
+    ipfs_request_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new(bucket_name: &str) -> Result<Self, prometheus::Error> {
+        let labels = HashMap::from([(BUCKET_LABEL.to_string(), bucket_name.to_string())]);
+        let registry = Registry::new_custom(Some(METRICS_NAMESPACE.to_string()), Some(labels))?;
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled, by route"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency, by route",
+            ),
+            &["method", "route"],
+        )?;
+        let root_pushes = IntCounterVec::new(
+            Opts::new(
+                "root_pushes_total",
+                "Attempts to push a new root, by outcome (a conflict is a rejected update)",
+            ),
+            &["outcome"],
+        )?;
+        let root_pulls = IntCounterVec::new(
+            Opts::new(
+                "root_pulls_total",
+                "Requests for the current root, by outcome",
+            ),
+            &["outcome"],
+        )?;
+        let db_pool_connections = IntGauge::new(
+            "db_pool_connections",
+            "Connections currently held by the SQLite pool",
+        )?;
+        let db_pool_idle_connections = IntGauge::new(
+            "db_pool_idle_connections",
+            "Idle connections in the SQLite pool",
+        )?;
+        let db_pool_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Maximum connections the SQLite pool will open",
+        )?;
+        let ipfs_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "ipfs_request_duration_seconds",
+                "Latency of calls to the IPFS RPC API, by method and outcome",
+            ),
+            &["method", "outcome"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(root_pushes.clone()))?;
+        registry.register(Box::new(root_pulls.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_idle_connections.clone()))?;
+        registry.register(Box::new(db_pool_max_connections.clone()))?;
+        registry.register(Box::new(ipfs_request_duration.clone()))?;
+
+        Ok(Self(Arc::new(MetricsInner {
+            registry,
+            http_requests,
+            http_request_duration,
+            root_pushes,
+            root_pulls,
+            db_pool_connections,
+            db_pool_idle_connections,
+            db_pool_max_connections,
+            ipfs_request_duration,
+        })))
+    }
+
+    pub fn record_push(&self, outcome: &str) {
+        self.0.root_pushes.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn record_pull(&self, outcome: &str) {
+        self.0.root_pulls.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn record_ipfs_request(&self, method: &str, outcome: &str, elapsed: Duration) {
+        self.0
+            .ipfs_request_duration
+            .with_label_values(&[method, outcome])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Pool utilization is sampled rather than tracked, so it should be refreshed before encoding
+    pub fn observe_db_pool(&self, db: &Database) {
+        self.0.db_pool_connections.set(db.size() as i64);
+        self.0.db_pool_idle_connections.set(db.num_idle() as i64);
+        self.0
+            .db_pool_max_connections
+            .set(db.options().get_max_connections() as i64);
+    }
+
+    /// Render every registered metric in the Prometheus text exposition format
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.0.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
+
+/// Middleware recording the count and latency of every request against the route it matched
+pub async fn track_requests(
+    State(metrics): State<Metrics>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed();
+
+    let status = response.status().as_u16().to_string();
+    metrics
+        .0
+        .http_requests
+        .with_label_values(&[&method, &route, &status])
+        .inc();
+    metrics
+        .0
+        .http_request_duration
+        .with_label_values(&[&method, &route])
+        .observe(elapsed.as_secs_f64());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_track_requests_by_route() {
+        let metrics = Metrics::new("bucket").unwrap();
+        let nested = Router::new().route("/thing/:id", get(|| async { "ok" }));
+        let router =
+            Router::new()
+                .nest("/api", nested)
+                .layer(axum::middleware::from_fn_with_state(
+                    metrics.clone(),
+                    track_requests,
+                ));
+
+        let request = http::Request::get("/api/thing/42")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = http::Request::get("/nowhere").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let encoded = metrics.encode().unwrap();
+        assert!(encoded.contains(
+            r#"leaky_http_requests_total{method="GET",route="/api/thing/:id",status="200",bucket="bucket"} 1"#
+        ));
+        assert!(encoded.contains(
+            r#"leaky_http_requests_total{method="GET",route="unmatched",status="404",bucket="bucket"} 1"#
+        ));
+    }
+}
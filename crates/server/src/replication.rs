@@ -0,0 +1,69 @@
+use cid::Cid;
+
+use crate::app::AppState;
+use crate::database::models::{PinStatus, RootPin, RootPinError};
+use crate::ipfs::{manifest_blocks, IpfsRpcError, PinMode};
+
+/// Pin every block of a pushed root on the server's IPFS node in the background, recording the
+/// outcome so clients can tell when their push is durable.
+pub fn spawn_replication(state: AppState, cid: Cid) {
+    tokio::spawn(async move {
+        let (status, error) = match replicate_root(&state, &cid).await {
+            Ok(count) => {
+                tracing::info!(%cid, blocks = count, "replicated root");
+                (PinStatus::Pinned, None)
+            }
+            Err(err) => {
+                tracing::error!(%cid, "failed to replicate root: {err}");
+                (PinStatus::Failed, Some(err.to_string()))
+            }
+        };
+
+        if let Err(err) = record_status(&state, &cid, status, error).await {
+            tracing::error!(%cid, "failed to record pin status: {err}");
+        }
+    });
+}
+
+/// Restart replication for any roots left pending, e.g. by a restart in the middle of a job
+pub async fn resume_replication(state: &AppState) -> Result<(), ReplicationError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    let pending = RootPin::pending(&mut conn).await?;
+    for cid in pending {
+        tracing::info!(%cid, "resuming replication");
+        spawn_replication(state.clone(), cid);
+    }
+    Ok(())
+}
+
+async fn replicate_root(state: &AppState, cid: &Cid) -> Result<usize, ReplicationError> {
+    let _guard = state.gc_lock().read().await;
+    let ipfs_rpc = state.ipfs_rpc();
+    let blocks = manifest_blocks(ipfs_rpc, cid).await?;
+    // Pin the data before the nodes and manifest that reference it
+    for (block, mode) in blocks.iter().rev() {
+        ipfs_rpc.pin_add(block, *mode == PinMode::Recursive).await?;
+    }
+    Ok(blocks.len())
+}
+
+async fn record_status(
+    state: &AppState,
+    cid: &Cid,
+    status: PinStatus,
+    error: Option<String>,
+) -> Result<(), ReplicationError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    RootPin::update(cid, status, error, &mut conn).await?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplicationError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("root pin error: {0}")]
+    RootPin(#[from] RootPinError),
+    #[error("ipfs error: {0}")]
+    Ipfs(#[from] IpfsRpcError),
+}
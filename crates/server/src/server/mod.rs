@@ -3,14 +3,17 @@ use tokio::sync::watch;
 use tower_http::trace::{DefaultOnFailure, DefaultOnResponse, TraceLayer};
 use tower_http::LatencyUnit;
 
+use crate::admin;
 use crate::api;
 use crate::app::{AppState, AppStateSetupError, Config};
 use crate::health;
+use crate::metrics;
 
 mod error_handlers;
 
 const HEALTH_PREFIX: &str = "/_status";
 const API_PREFIX: &str = "/api/v0";
+const ADMIN_PREFIX: &str = "/_admin";
 
 pub async fn run(
     config: Config,
@@ -28,11 +31,16 @@ pub async fn run(
         )
         .on_failure(DefaultOnFailure::new().latency_unit(LatencyUnit::Micros));
 
+    let metrics_layer =
+        axum::middleware::from_fn_with_state(state.metrics().clone(), metrics::track_requests);
+
     let root_router = Router::new()
         .nest(API_PREFIX, api::router(state.clone()))
         .nest(HEALTH_PREFIX, health::router(state.clone()))
+        .nest(ADMIN_PREFIX, admin::router(state.clone()))
         .with_state(state)
         .fallback(error_handlers::not_found_handler)
+        .layer(metrics_layer)
         .layer(trace_layer);
 
     tracing::info!(addr = ?listen_addr, "server listening");
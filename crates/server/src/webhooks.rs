@@ -0,0 +1,322 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cid::Cid;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::app::{AppState, Config};
+use crate::database::models::{PendingWebhookDelivery, Webhook, WebhookDelivery, WebhookError};
+use crate::events::RootEvent;
+use crate::ipfs::{diff_manifests, IpfsRpcError, ManifestChange};
+
+/// Hex HMAC-SHA256 of the request body, keyed with the webhook's secret and prefixed `sha256=`
+const SIGNATURE_HEADER: &str = "x-leaky-signature";
+const EVENT_HEADER: &str = "x-leaky-event";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Client and settings shared by every webhook delivery. Webhooks are registered per server, and
+/// a server hosts exactly one bucket, named in every payload.
+#[derive(Clone)]
+pub struct WebhookSender {
+    client: reqwest::Client,
+    bucket: Arc<str>,
+    max_attempts: u32,
+}
+
+impl WebhookSender {
+    pub fn from_config(config: &Config) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            bucket: Arc::from(config.bucket_name()),
+            max_attempts: config.webhook_max_attempts().max(1),
+        })
+    }
+}
+
+/// Body POSTed to every webhook when a branch gets a new head
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    bucket: &'a str,
+    #[serde(flatten)]
+    event: &'a RootEvent,
+    /// Files added, removed, modified, retagged or moved since the previous root. Left out if
+    /// either version of the bucket couldn't be read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changed_paths: Option<Vec<String>>,
+}
+
+/// Notify every registered webhook of a new head in the background. Each delivery is retried with
+/// exponential backoff until it succeeds or runs out of attempts, and every attempt is logged.
+/// Deliveries are tracked in the database until they finish, so a restart resumes them rather
+/// than dropping them. An attempt cut off by a restart is made again, so a webhook may see the
+/// same event more than once.
+pub fn spawn_webhooks(state: AppState, cid: Cid, previous_cid: Cid, event: RootEvent) {
+    tokio::spawn(async move {
+        let webhooks = match list_webhooks(&state).await {
+            Ok(webhooks) => webhooks,
+            Err(err) => {
+                tracing::error!(%cid, "failed to list webhooks: {err}");
+                return;
+            }
+        };
+        if webhooks.is_empty() {
+            return;
+        }
+
+        let changed_paths = match diff_roots(&state, &previous_cid, &cid).await {
+            Ok(paths) => Some(paths),
+            Err(err) => {
+                tracing::warn!(%cid, %previous_cid, "failed to diff roots for webhooks: {err}");
+                None
+            }
+        };
+        let payload = WebhookPayload {
+            bucket: &state.webhooks().bucket,
+            event: &event,
+            changed_paths,
+        };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(err) => {
+                tracing::error!(%cid, "failed to serialize webhook payload: {err}");
+                return;
+            }
+        };
+
+        for webhook in webhooks {
+            match queue_delivery(&state, &webhook, &event.branch, &cid, &body).await {
+                Ok(delivery) => spawn_delivery(state.clone(), webhook, delivery),
+                Err(err) => {
+                    tracing::error!(webhook = webhook.id(), %cid, "failed to queue webhook: {err}")
+                }
+            }
+        }
+    });
+}
+
+/// Restart any deliveries left pending, e.g. by a restart while they were waiting to retry
+pub async fn resume_webhooks(state: &AppState) -> Result<(), WebhooksError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    let pending = PendingWebhookDelivery::list(&mut conn).await?;
+    let webhooks = Webhook::list(&mut conn)
+        .await?
+        .into_iter()
+        .map(|webhook| (webhook.id(), webhook))
+        .collect::<HashMap<_, _>>();
+    for delivery in pending {
+        let Some(webhook) = webhooks.get(&delivery.webhook_id()).cloned() else {
+            continue;
+        };
+        tracing::info!(webhook = webhook.id(), cid = %delivery.cid(), "resuming webhook");
+        spawn_delivery(state.clone(), webhook, delivery);
+    }
+    Ok(())
+}
+
+async fn queue_delivery(
+    state: &AppState,
+    webhook: &Webhook,
+    branch: &str,
+    cid: &Cid,
+    body: &[u8],
+) -> Result<PendingWebhookDelivery, WebhooksError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    let delivery =
+        PendingWebhookDelivery::create(webhook.id(), branch, cid, body, &mut conn).await?;
+    Ok(delivery)
+}
+
+fn spawn_delivery(state: AppState, webhook: Webhook, delivery: PendingWebhookDelivery) {
+    tokio::spawn(async move {
+        deliver(&state, &webhook, &delivery).await;
+    });
+}
+
+async fn list_webhooks(state: &AppState) -> Result<Vec<Webhook>, WebhooksError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    Ok(Webhook::list(&mut conn).await?)
+}
+
+async fn diff_roots(
+    state: &AppState,
+    previous_cid: &Cid,
+    cid: &Cid,
+) -> Result<Vec<String>, WebhooksError> {
+    if previous_cid == cid {
+        return Ok(Vec::new());
+    }
+    let changes = diff_manifests(state.ipfs_rpc(), previous_cid, cid).await?;
+    Ok(changed_paths(&changes))
+}
+
+/// Every path touched by a set of changes. Moves touch both their source and destination.
+fn changed_paths(changes: &[ManifestChange]) -> Vec<String> {
+    let mut paths = BTreeSet::new();
+    for change in changes {
+        if let ManifestChange::Moved { from, .. } = change {
+            paths.insert(from.clone());
+        }
+        paths.insert(change.path().clone());
+    }
+    paths.into_iter().collect()
+}
+
+async fn deliver(state: &AppState, webhook: &Webhook, delivery: &PendingWebhookDelivery) {
+    let sender = state.webhooks();
+    let cid = delivery.cid();
+    let body = delivery.body();
+    let signature = sign(webhook.secret(), body);
+    let mut shutdown_rx = state.shutdown_signal();
+
+    for attempt in delivery.attempts() + 1..=sender.max_attempts {
+        let request = sender
+            .client
+            .post(webhook.url())
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, "root")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.to_vec());
+        let (status_code, error) = match request.send().await {
+            Ok(response) => (Some(response.status().as_u16()), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        let succeeded = status_code.is_some_and(|code| (200..300).contains(&code));
+
+        if let Err(err) = record_attempt(state, delivery, attempt, status_code, error).await {
+            tracing::error!(webhook = webhook.id(), %cid, "failed to log webhook delivery: {err}");
+        }
+        if succeeded {
+            break;
+        }
+        if attempt == sender.max_attempts {
+            tracing::warn!(webhook = webhook.id(), %cid, attempts = attempt, "giving up on webhook");
+            break;
+        }
+
+        // Shutting down leaves the delivery pending, to be resumed at startup
+        tokio::select! {
+            _ = tokio::time::sleep(backoff(attempt)) => {}
+            _ = shutdown_rx.changed() => return,
+        }
+    }
+
+    if let Err(err) = finish_delivery(state, delivery).await {
+        tracing::error!(webhook = webhook.id(), %cid, "failed to finish webhook delivery: {err}");
+    }
+}
+
+/// Log an attempt and count it against the pending delivery
+async fn record_attempt(
+    state: &AppState,
+    delivery: &PendingWebhookDelivery,
+    attempt: u32,
+    status_code: Option<u16>,
+    error: Option<String>,
+) -> Result<(), WebhooksError> {
+    let mut conn = state.sqlite_database().begin().await?;
+    WebhookDelivery::create(
+        delivery.webhook_id(),
+        delivery.branch(),
+        &delivery.cid(),
+        attempt,
+        status_code,
+        error,
+        &mut conn,
+    )
+    .await?;
+    PendingWebhookDelivery::update(delivery.id(), attempt, &mut conn).await?;
+    conn.commit().await?;
+    Ok(())
+}
+
+async fn finish_delivery(
+    state: &AppState,
+    delivery: &PendingWebhookDelivery,
+) -> Result<(), WebhooksError> {
+    let mut conn = state.sqlite_database().acquire().await?;
+    PendingWebhookDelivery::delete(delivery.id(), &mut conn).await?;
+    Ok(())
+}
+
+/// How long to wait after a failed attempt before the next one
+fn backoff(attempt: u32) -> Duration {
+    let factor = 1u32 << attempt.saturating_sub(1).min(16);
+    INITIAL_BACKOFF.saturating_mul(factor).min(MAX_BACKOFF)
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhooksError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("webhook error: {0}")]
+    Webhook(#[from] WebhookError),
+    #[error("ipfs error: {0}")]
+    Ipfs(#[from] IpfsRpcError),
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Multihash;
+
+    use super::*;
+
+    fn cid(i: u8) -> Cid {
+        Cid::new_v1(0x55, Multihash::wrap(0x12, &[i; 32]).unwrap())
+    }
+
+    #[test]
+    fn test_changed_paths() {
+        let changes = vec![
+            ManifestChange::Added {
+                path: "/added".to_string(),
+                cid: cid(1),
+            },
+            ManifestChange::MetadataChanged {
+                path: "/tagged".to_string(),
+                cid: cid(2),
+            },
+            ManifestChange::Moved {
+                from: "/a/moved".to_string(),
+                to: "/b/moved".to_string(),
+                cid: cid(3),
+            },
+        ];
+        assert_eq!(
+            changed_paths(&changes),
+            vec!["/a/moved", "/added", "/b/moved", "/tagged"]
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+        assert_eq!(backoff(100), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}